@@ -82,6 +82,40 @@ impl From<Vec2> for (f32, f32) {
     }
 }
 
+/// A length: absolute or relative to the font size
+///
+/// This is used by formatting tokens for properties such as
+/// [letter spacing](crate::FontToken::letter_spacing).
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Length {
+    /// A length in pixels
+    Px(f32),
+    /// A length relative to the font size (units of Em)
+    Em(f32),
+}
+
+impl Default for Length {
+    #[inline]
+    fn default() -> Self {
+        Length::ZERO
+    }
+}
+
+impl Length {
+    /// Zero
+    pub const ZERO: Length = Length::Px(0.0);
+
+    /// Resolve to pixels, given the font size `dpem`
+    #[inline]
+    pub fn to_px(self, dpem: f32) -> f32 {
+        match self {
+            Length::Px(x) => x,
+            Length::Em(x) => x * dpem,
+        }
+    }
+}
+
 /// Range type
 ///
 /// Essentially this is just a `std::ops::Range<u32>`, but with convenient
@@ -0,0 +1,742 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Virtualized layout of large documents
+
+use crate::conv::{to_u32, to_usize};
+use crate::fonts::{FontSelector, NoFontMatch};
+use crate::util::is_hard_break;
+use crate::{Affinity, Align, CaretHeight, Direction, Forme, Vec2};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Prefix sums over paragraph heights
+///
+/// This is a Fenwick tree, supporting update and prefix-sum queries in
+/// `O(log n)` time.
+#[derive(Clone, Debug, Default)]
+struct Heights {
+    values: Vec<f32>,
+    /// `tree[k - 1]` is the sum of `values` over `k - lowbit(k) .. k`
+    tree: Vec<f64>,
+}
+
+fn lowbit(k: usize) -> usize {
+    k & k.wrapping_neg()
+}
+
+impl Heights {
+    fn new(values: Vec<f32>) -> Self {
+        let mut tree: Vec<f64> = values.iter().map(|h| f64::from(*h)).collect();
+        let n = tree.len();
+        for k in 1..=n {
+            let p = k + lowbit(k);
+            if p <= n {
+                tree[p - 1] += tree[k - 1];
+            }
+        }
+        Heights { values, tree }
+    }
+
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    fn get(&self, i: usize) -> f32 {
+        self.values[i]
+    }
+
+    fn set(&mut self, i: usize, value: f32) {
+        let delta = f64::from(value) - f64::from(self.values[i]);
+        self.values[i] = value;
+        let mut k = i + 1;
+        while k <= self.len() {
+            self.tree[k - 1] += delta;
+            k += lowbit(k);
+        }
+    }
+
+    fn push(&mut self, value: f32) {
+        let k = self.len() + 1;
+        let sum = self.prefix(k - 1) - self.prefix(k - lowbit(k));
+        self.values.push(value);
+        self.tree.push(f64::from(value) + sum);
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.values.truncate(len);
+        self.tree.truncate(len);
+    }
+
+    /// Sum of values before `i`
+    fn prefix(&self, i: usize) -> f64 {
+        let (mut sum, mut k) = (0.0, i);
+        while k > 0 {
+            sum += self.tree[k - 1];
+            k &= k - 1;
+        }
+        sum
+    }
+
+    /// Find the item containing `y`, clamped to the valid range
+    fn find(&self, y: f64) -> usize {
+        let n = self.len();
+        let (mut pos, mut rem) = (0, y);
+        let mut step = n.next_power_of_two();
+        while step > 0 {
+            let next = pos + step;
+            if next <= n && self.tree[next - 1] <= rem {
+                pos = next;
+                rem -= self.tree[next - 1];
+            }
+            step >>= 1;
+        }
+        pos.min(n.saturating_sub(1))
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    forme: Forme,
+    used: u64,
+}
+
+/// A document with virtualized layout
+///
+/// This is an alternative to [`Forme`] (or [`Text`](crate::Text)) for very
+/// large plain texts such as log files. The text is split into paragraphs
+/// at mandatory line breaks; each paragraph is shaped and wrapped
+/// independently and only when required by [`Self::layout_viewport`].
+///
+/// Paragraphs not yet laid out are given an estimated height derived from
+/// their length and the metrics of paragraphs already laid out, thus the
+/// total [height](Self::height) and all position queries are available at all
+/// times (and become exact as paragraphs are laid out). Heights are retained
+/// after measurement, but layouts themselves are evicted (least recently used
+/// first) beyond the [cache capacity](Self::set_cache_capacity), bounding
+/// memory usage.
+///
+/// Positions are relative to the top-left of the document. The
+/// [`Forme`] of paragraph `i` is positioned with its origin at
+/// `Vec2(0.0, self.paragraph_top(i))`.
+///
+/// Note that paragraph formatting and bidirectional text spanning multiple
+/// paragraphs are not supported; the [direction](Self::set_direction) applies
+/// to each paragraph independently.
+#[derive(Clone, Debug)]
+pub struct Document {
+    text: String,
+    /// Start of each paragraph
+    starts: Vec<u32>,
+    font: FontSelector,
+    dpem: f32,
+    direction: Direction,
+    wrap_width: f32,
+    align: Align,
+    heights: Heights,
+    /// Number of lines of each paragraph, if measured
+    measured: Vec<Option<u32>>,
+    /// Sums of height and number of lines over paragraphs laid out
+    metrics: (f64, usize),
+    cache: HashMap<usize, Entry>,
+    capacity: usize,
+    tick: u64,
+    /// Paragraphs in the last viewport
+    viewport: Range<usize>,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Document::new(String::new())
+    }
+}
+
+impl Document {
+    /// Construct from `text`
+    ///
+    /// The default font size is 16 pixels per Em and the default wrap width
+    /// is infinite (no wrapping).
+    pub fn new(text: impl Into<String>) -> Self {
+        let mut doc = Document {
+            text: String::new(),
+            starts: vec![],
+            font: FontSelector::default(),
+            dpem: 16.0,
+            direction: Direction::default(),
+            wrap_width: f32::INFINITY,
+            align: Align::default(),
+            heights: Heights::default(),
+            measured: vec![],
+            metrics: (0.0, 0),
+            cache: HashMap::new(),
+            capacity: 256,
+            tick: 0,
+            viewport: 0..0,
+        };
+        doc.set_text(text);
+        doc
+    }
+
+    /// Access the text
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Set the text
+    ///
+    /// All layouts and measurements are discarded.
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        self.text = text.into();
+        self.starts.clear();
+        find_paragraphs(&self.text, 0, &mut self.starts);
+        self.viewport = 0..0;
+        self.invalidate();
+    }
+
+    /// Append `text`
+    ///
+    /// Paragraphs other than the last are not affected, thus appending is
+    /// cheap (excepting the cost of the new text itself).
+    pub fn append(&mut self, text: &str) {
+        let mut last = self.starts.len() - 1;
+        if last > 0 && self.text.ends_with('\r') && text.starts_with('\n') {
+            // The CR LF pair must not be split
+            last -= 1;
+        }
+        let start = to_usize(self.starts[last]);
+        self.text.push_str(text);
+        self.starts.truncate(last);
+        find_paragraphs(&self.text[start..], start, &mut self.starts);
+
+        self.cache.retain(|i, _| *i < last);
+        for i in last..self.measured.len() {
+            if let Some(lines) = self.measured[i] {
+                self.metrics.0 -= f64::from(self.heights.get(i));
+                self.metrics.1 -= to_usize(lines);
+            }
+        }
+        self.heights.truncate(last);
+        self.measured.truncate(last);
+        for i in last..self.starts.len() {
+            self.heights.push(self.estimate(i));
+            self.measured.push(None);
+        }
+    }
+
+    /// Set the font
+    ///
+    /// All layouts and measurements are discarded.
+    pub fn set_font(&mut self, font: FontSelector) {
+        if font != self.font {
+            self.font = font;
+            self.invalidate();
+        }
+    }
+
+    /// Set the font size (pixels per Em)
+    ///
+    /// All layouts and measurements are discarded.
+    pub fn set_font_size(&mut self, dpem: f32) {
+        if dpem != self.dpem {
+            self.dpem = dpem;
+            self.invalidate();
+        }
+    }
+
+    /// Set the base text direction
+    ///
+    /// All layouts and measurements are discarded.
+    pub fn set_direction(&mut self, direction: Direction) {
+        if direction != self.direction {
+            self.direction = direction;
+            self.invalidate();
+        }
+    }
+
+    /// Set the wrap width
+    ///
+    /// Lines are wrapped to `width`; if this is infinite, lines are not
+    /// wrapped. Where finite, `width` is also used for
+    /// [alignment](Self::set_align).
+    ///
+    /// All layouts and measurements are discarded.
+    pub fn set_wrap_width(&mut self, width: f32) {
+        if width != self.wrap_width {
+            self.wrap_width = width;
+            self.invalidate();
+        }
+    }
+
+    /// Set the horizontal alignment
+    ///
+    /// All layouts are discarded.
+    pub fn set_align(&mut self, align: Align) {
+        if align != self.align {
+            self.align = align;
+            self.cache.clear();
+        }
+    }
+
+    /// Set the capacity of the layout cache
+    ///
+    /// This is the number of paragraph layouts retained, excepting that
+    /// paragraphs within the last viewport are never evicted.
+    /// The default capacity is 256.
+    pub fn set_cache_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// Get the number of paragraph layouts currently cached
+    #[inline]
+    pub fn cached_layouts(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Get the number of paragraphs
+    ///
+    /// This is at least one. Text ending with a line break has an empty final
+    /// paragraph.
+    #[inline]
+    pub fn num_paragraphs(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Get the text range of paragraph `i`
+    ///
+    /// This excludes the paragraph's trailing line break, if any.
+    ///
+    /// Panics if `i >= self.num_paragraphs()`.
+    pub fn paragraph_range(&self, i: usize) -> Range<usize> {
+        let start = to_usize(self.starts[i]);
+        let end = self.starts.get(i + 1).map(|s| to_usize(*s));
+        let mut end = end.unwrap_or(self.text.len());
+        while let Some(c) = self.text[start..end].chars().next_back()
+            && is_hard_break(c)
+        {
+            end -= c.len_utf8();
+        }
+        start..end
+    }
+
+    /// Find the paragraph containing `index`
+    ///
+    /// A line break is considered part of the preceding paragraph.
+    pub fn find_paragraph(&self, index: usize) -> usize {
+        let index = to_u32(index.min(self.text.len()));
+        self.starts.partition_point(|s| *s <= index) - 1
+    }
+
+    /// Get the total height of the document
+    ///
+    /// This includes estimated heights of paragraphs not yet laid out.
+    #[inline]
+    pub fn height(&self) -> f32 {
+        self.heights.prefix(self.heights.len()) as f32
+    }
+
+    /// Get the position of the top of paragraph `i`
+    ///
+    /// Panics if `i > self.num_paragraphs()`.
+    #[inline]
+    pub fn paragraph_top(&self, i: usize) -> f32 {
+        self.heights.prefix(i) as f32
+    }
+
+    /// Get the (possibly estimated) height of paragraph `i`
+    ///
+    /// Panics if `i >= self.num_paragraphs()`.
+    #[inline]
+    pub fn paragraph_height(&self, i: usize) -> f32 {
+        self.heights.get(i)
+    }
+
+    /// Returns true if the height of paragraph `i` has been measured
+    ///
+    /// The height of a paragraph is measured when it is first laid out and
+    /// retained until the text or layout configuration is changed.
+    ///
+    /// Panics if `i >= self.num_paragraphs()`.
+    #[inline]
+    pub fn is_measured(&self, i: usize) -> bool {
+        self.measured[i].is_some()
+    }
+
+    /// Find the paragraph at vertical position `y`
+    ///
+    /// The result is clamped to the valid range.
+    #[inline]
+    pub fn paragraph_at(&self, y: f32) -> usize {
+        self.heights.find(f64::from(y))
+    }
+
+    /// Get the layout of paragraph `i`, if available
+    ///
+    /// Paragraphs are laid out by [`Self::layout_viewport`].
+    /// The result is positioned relative to the [top of the
+    /// paragraph](Self::paragraph_top).
+    #[inline]
+    pub fn paragraph_forme(&self, i: usize) -> Option<&Forme> {
+        self.cache.get(&i).map(|entry| &entry.forme)
+    }
+
+    /// Lay out paragraphs visible in a viewport
+    ///
+    /// The viewport covers the vertical range `top..top + height`. All
+    /// paragraphs intersecting this range are laid out (if not already) and
+    /// the range of these paragraphs is returned. Cached layouts of other
+    /// paragraphs may be evicted.
+    ///
+    /// Laying out a paragraph replaces its estimated height with its measured
+    /// height, which may move following paragraphs (and, if `top` is within
+    /// or after a paragraph whose height changed, the content visible at
+    /// `top`). Where stable scrolling is required, the caller may compare
+    /// [`Self::paragraph_top`] of the first visible paragraph before and after
+    /// this call and adjust the scroll offset accordingly.
+    pub fn layout_viewport(&mut self, top: f32, height: f32) -> Result<Range<usize>, NoFontMatch> {
+        let start = self.paragraph_at(top);
+        let mut end = start;
+        loop {
+            self.layout(end)?;
+            end += 1;
+            if end >= self.num_paragraphs() || self.paragraph_top(end) >= top + height {
+                break;
+            }
+        }
+
+        self.viewport = start..end;
+        self.evict();
+        Ok(start..end)
+    }
+
+    /// Find the text index nearest to `pos`
+    ///
+    /// This is exact where the paragraph at `pos` is laid out; otherwise the
+    /// index is estimated from the vertical position within the paragraph.
+    pub fn text_index_nearest(&self, pos: Vec2) -> usize {
+        let i = self.paragraph_at(pos.1);
+        let range = self.paragraph_range(i);
+        let top = self.paragraph_top(i);
+        if let Some(forme) = self.paragraph_forme(i) {
+            return range.start + forme.text_index_nearest(Vec2(pos.0, pos.1 - top));
+        }
+
+        let height = self.heights.get(i);
+        let frac = match height > 0.0 {
+            true => ((pos.1 - top) / height).clamp(0.0, 1.0),
+            false => 0.0,
+        };
+        let mut index = range.start + (frac * range.len() as f32) as usize;
+        while !self.text.is_char_boundary(index) {
+            index -= 1;
+        }
+        index
+    }
+
+    /// Find the position of the caret at `index`
+    ///
+    /// Returns the bounding box `(top_left, bottom_right)` of the caret
+    /// spanning its line (see [`Forme::caret_shape`]). This is exact where the
+    /// paragraph containing `index` is laid out; otherwise the position is
+    /// estimated (with horizontal position 0).
+    pub fn caret_rect(&self, index: usize) -> (Vec2, Vec2) {
+        let i = self.find_paragraph(index);
+        let range = self.paragraph_range(i);
+        let top = self.paragraph_top(i);
+        let index = index.clamp(range.start, range.end) - range.start;
+
+        if let Some(forme) = self.paragraph_forme(i)
+            && let Some(shape) = forme.caret_shape(index, Affinity::Downstream, CaretHeight::Line)
+        {
+            let caret = shape.primary;
+            let offset = Vec2(0.0, top);
+            return (
+                caret.top.min(caret.bottom) + offset,
+                caret.top.max(caret.bottom) + offset,
+            );
+        }
+
+        let line_height = self.line_height();
+        let height = (self.heights.get(i) - line_height).max(0.0);
+        let frac = match range.is_empty() {
+            false => index as f32 / range.len() as f32,
+            true => 0.0,
+        };
+        let y = top + frac * height;
+        (Vec2(0.0, y), Vec2(0.0, y + line_height))
+    }
+
+    /// Lay out paragraph `i`, if not already cached
+    fn layout(&mut self, i: usize) -> Result<(), NoFontMatch> {
+        self.tick += 1;
+        if let Some(entry) = self.cache.get_mut(&i) {
+            entry.used = self.tick;
+            return Ok(());
+        }
+
+        let range = self.paragraph_range(i);
+        let mut forme = Forme::default();
+        forme
+            .set_text(&self.text[range], self.direction)
+            .with_font(.., self.font, self.dpem)?;
+        let align_width = match self.wrap_width.is_finite() {
+            true => self.wrap_width,
+            false => 0.0,
+        };
+        let height = forme.prepare_lines(self.wrap_width, align_width, self.align);
+
+        if self.measured[i].is_none() {
+            let calibrate = self.metrics.1 == 0;
+            self.metrics.0 += f64::from(height);
+            self.metrics.1 += forme.num_lines();
+            self.measured[i] = Some(to_u32(forme.num_lines()));
+            if calibrate {
+                // Update estimates using the measured line height
+                self.heights.set(i, height);
+                self.estimate_all();
+            }
+        }
+        self.heights.set(i, height);
+
+        let entry = Entry {
+            forme,
+            used: self.tick,
+        };
+        self.cache.insert(i, entry);
+        Ok(())
+    }
+
+    /// Evict least-recently-used layouts outside of the last viewport
+    fn evict(&mut self) {
+        let excess = self.cache.len().saturating_sub(self.capacity);
+        if excess == 0 {
+            return;
+        }
+
+        let mut candidates: Vec<(u64, usize)> = self
+            .cache
+            .iter()
+            .filter(|(i, _)| !self.viewport.contains(i))
+            .map(|(i, entry)| (entry.used, *i))
+            .collect();
+        candidates.sort_unstable();
+        for (_, i) in candidates.into_iter().take(excess) {
+            self.cache.remove(&i);
+        }
+    }
+
+    /// Discard all layouts and measurements
+    fn invalidate(&mut self) {
+        self.cache.clear();
+        self.metrics = (0.0, 0);
+        self.measured.clear();
+        self.measured.resize(self.starts.len(), None);
+        self.estimate_all();
+    }
+
+    /// Re-estimate heights of all paragraphs not measured
+    fn estimate_all(&mut self) {
+        let values = (0..self.starts.len())
+            .map(|i| match self.measured[i] {
+                Some(_) => self.heights.get(i),
+                None => self.estimate(i),
+            })
+            .collect();
+        self.heights = Heights::new(values);
+    }
+
+    /// The (measured or estimated) average line height
+    fn line_height(&self) -> f32 {
+        match self.metrics {
+            (_, 0) => 1.2 * self.dpem,
+            (height, lines) => (height / lines as f64) as f32,
+        }
+    }
+
+    /// Estimate the height of paragraph `i`
+    fn estimate(&self, i: usize) -> f32 {
+        // We assume an average advance of half an Em per byte
+        let bytes_per_line = (self.wrap_width / (0.5 * self.dpem)).max(1.0);
+        let len = self.paragraph_range(i).len() as f32;
+        let lines = (len / bytes_per_line).ceil().max(1.0);
+        lines * self.line_height()
+    }
+}
+
+/// Push the start of each paragraph of `text` (offset by `offset`)
+///
+/// Paragraphs end after each mandatory break (treating CR LF as a single
+/// break). At least one paragraph is pushed.
+fn find_paragraphs(text: &str, offset: usize, starts: &mut Vec<u32>) {
+    starts.push(to_u32(offset));
+    let mut iter = text.char_indices().peekable();
+    while let Some((i, c)) = iter.next() {
+        if c == '\r' && iter.peek().is_some_and(|(_, c)| *c == '\n') {
+            continue;
+        }
+        if is_hard_break(c) {
+            starts.push(to_u32(offset + i + c.len_utf8()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lines(n: usize) -> String {
+        (0..n).map(|i| format!("line {i}\n")).collect()
+    }
+
+    #[test]
+    fn heights() {
+        let mut heights = Heights::new(vec![1.0, 2.0, 3.0]);
+        assert_eq!(heights.prefix(0), 0.0);
+        assert_eq!(heights.prefix(3), 6.0);
+        heights.push(4.0);
+        heights.push(5.0);
+        assert_eq!(heights.prefix(5), 15.0);
+        heights.set(1, 0.0);
+        assert_eq!(heights.prefix(2), 1.0);
+        assert_eq!(heights.find(0.5), 0);
+        assert_eq!(heights.find(1.0), 2);
+        assert_eq!(heights.find(6.5), 3);
+        assert_eq!(heights.find(100.0), 4);
+        heights.truncate(2);
+        assert_eq!(heights.prefix(2), 1.0);
+    }
+
+    #[test]
+    fn paragraphs() {
+        let doc = Document::new("a\nb\r\nc\u{2029}");
+        assert_eq!(doc.num_paragraphs(), 4);
+        let ranges: Vec<_> = (0..4).map(|i| doc.paragraph_range(i)).collect();
+        assert_eq!(ranges, [0..1, 2..3, 5..6, 9..9]);
+        assert_eq!(doc.find_paragraph(1), 0);
+        assert_eq!(doc.find_paragraph(4), 1);
+        assert_eq!(doc.find_paragraph(9), 3);
+
+        let mut doc = Document::new("a\nb");
+        doc.append("c\r");
+        assert_eq!(doc.num_paragraphs(), 3);
+        doc.append("\nd");
+        assert_eq!(doc.num_paragraphs(), 3);
+        assert_eq!(doc.paragraph_range(1), 2..4);
+        assert_eq!(doc.paragraph_range(2), 6..7);
+
+        // Appending discards metrics of the last paragraph
+        doc.layout_viewport(0.0, 1000.0).unwrap();
+        let (height, lines) = doc.metrics;
+        assert_eq!(lines, 3);
+        let last = f64::from(doc.paragraph_height(2));
+        doc.append(" more");
+        assert!(!doc.is_measured(2));
+        assert_eq!(doc.metrics, (height - last, 2));
+
+        let doc = Document::default();
+        assert_eq!(doc.num_paragraphs(), 1);
+        assert_eq!(doc.paragraph_range(0), 0..0);
+    }
+
+    #[test]
+    fn lazy_layout() {
+        let mut doc = Document::new(lines(10_000));
+        let estimate = doc.height();
+        assert!(estimate > 0.0);
+        assert_eq!(doc.cached_layouts(), 0);
+
+        let visible = doc.layout_viewport(0.0, 100.0).unwrap();
+        assert_eq!(visible.start, 0);
+        assert!(visible.len() > 1 && visible.len() < 20);
+        assert_eq!(doc.cached_layouts(), visible.len());
+        for i in visible.clone() {
+            assert!(doc.is_measured(i));
+            let forme = doc.paragraph_forme(i).unwrap();
+            assert_eq!(forme.num_lines(), 1);
+        }
+        assert!(!doc.is_measured(visible.end));
+        assert!(doc.paragraph_top(visible.end) >= 100.0);
+        assert!(doc.paragraph_top(visible.end - 1) < 100.0);
+
+        // Estimates are calibrated to the measured line height
+        let line_height = doc.paragraph_height(0);
+        assert_eq!(doc.paragraph_height(5000), line_height);
+        assert_eq!(doc.height(), line_height * 10_001.0);
+        assert!(doc.height() != estimate);
+    }
+
+    #[test]
+    fn estimates() {
+        let mut doc = Document::new(format!("short\n{}\nshort", "word ".repeat(100)));
+        doc.set_wrap_width(200.0);
+        let estimate = doc.paragraph_height(1);
+        assert!(estimate > 4.0 * doc.paragraph_height(0));
+
+        let visible = doc.layout_viewport(0.0, 1000.0).unwrap();
+        assert_eq!(visible, 0..3);
+        let forme = doc.paragraph_forme(1).unwrap();
+        assert!(forme.num_lines() > 4);
+        let measured = doc.paragraph_height(1);
+        let lines = forme.num_lines() as f32;
+        assert!(measured > 0.5 * estimate && measured < 2.0 * estimate);
+        assert_eq!(measured, lines * doc.paragraph_height(0));
+        assert_eq!(doc.height(), doc.paragraph_top(2) + doc.paragraph_height(2));
+    }
+
+    #[test]
+    fn eviction() {
+        let mut doc = Document::new(lines(1000));
+        doc.set_cache_capacity(16);
+        let visible = doc.layout_viewport(0.0, 100.0).unwrap();
+        let line_height = doc.paragraph_height(0);
+        for i in 1..50 {
+            doc.layout_viewport(i as f32 * 10.0 * line_height, 100.0)
+                .unwrap();
+            assert!(doc.cached_layouts() <= 16);
+        }
+        assert!(doc.paragraph_forme(visible.start).is_none());
+        assert!(doc.is_measured(visible.start));
+
+        // Paragraphs in the viewport are never evicted
+        doc.set_cache_capacity(1);
+        let visible = doc.layout_viewport(0.0, 100.0).unwrap();
+        assert_eq!(doc.cached_layouts(), visible.len());
+
+        doc.set_cache_capacity(16);
+        doc.layout_viewport(100.0 * line_height, 100.0).unwrap();
+        let visible = doc.layout_viewport(0.0, 100.0).unwrap();
+        assert!(doc.cached_layouts() > visible.len());
+        doc.set_cache_capacity(1);
+        assert_eq!(doc.cached_layouts(), visible.len());
+        assert!(visible.clone().all(|i| doc.paragraph_forme(i).is_some()));
+    }
+
+    #[test]
+    fn positions() {
+        let mut doc = Document::new(lines(1000));
+        let visible = doc.layout_viewport(0.0, 100.0).unwrap();
+        let i = visible.end - 1;
+        let index = doc.paragraph_range(i).start + 2;
+        let (a, b) = doc.caret_rect(index);
+        assert!(a.0 > 0.0);
+        assert_eq!(a.1, doc.paragraph_top(i));
+        assert_eq!(b.1, doc.paragraph_top(i + 1));
+        let mid = Vec2(a.0 + 0.1, 0.5 * (a.1 + b.1));
+        assert_eq!(doc.text_index_nearest(mid), index);
+
+        // Estimated positions
+        let i = 900;
+        let range = doc.paragraph_range(i);
+        let (a, b) = doc.caret_rect(range.start);
+        assert_eq!(a, Vec2(0.0, doc.paragraph_top(i)));
+        assert_eq!(b.1, doc.paragraph_top(i + 1));
+        assert_eq!(doc.text_index_nearest(a), range.start);
+        assert_eq!(
+            doc.text_index_nearest(Vec2(0.0, doc.height() + 10.0)),
+            doc.as_str().len()
+        );
+    }
+}
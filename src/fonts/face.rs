@@ -5,8 +5,8 @@
 
 //! Font face types
 
-use crate::GlyphId;
 use crate::conv::{DPU, LineMetrics};
+use crate::{GlyphId, Vec2};
 use ttf_parser::Face;
 
 /// Handle to a loaded font face
@@ -25,6 +25,20 @@ impl<'a> FaceRef<'a> {
         GlyphId(self.0.glyph_index(c).map(|id| id.0).unwrap_or(0))
     }
 
+    /// True if the glyph is a combining mark
+    ///
+    /// This uses glyph classes from the GDEF table where available, otherwise
+    /// tests for a zero advance.
+    pub(crate) fn is_mark(&self, id: GlyphId) -> bool {
+        let id = ttf_parser::GlyphId(id.0);
+        if let Some(gdef) = self.0.tables().gdef
+            && gdef.has_glyph_classes()
+        {
+            return gdef.glyph_class(id) == Some(ttf_parser::gdef::GlyphClass::Mark);
+        }
+        self.0.glyph_hor_advance(id) == Some(0)
+    }
+
     /// Convert `dpem` to `dpu`
     ///
     /// Output: a font-specific scale.
@@ -86,6 +100,33 @@ impl<'a> ScaledFaceRef<'a> {
         self.1.u16_to_px(x)
     }
 
+    /// Vertical advancement after this glyph, without shaping
+    ///
+    /// This uses the `vmtx` table where available, otherwise the line height
+    /// (ascent minus descent).
+    #[inline]
+    pub fn v_advance(&self, id: GlyphId) -> f32 {
+        match self.0.glyph_ver_advance(id.into()) {
+            Some(x) => self.1.u16_to_px(x),
+            None => self.1.i16_to_px(self.0.ascender()) - self.1.i16_to_px(self.0.descender()),
+        }
+    }
+
+    /// Offset of a glyph's origin in vertical layout
+    ///
+    /// In vertical layout, the pen position is at the top of a glyph, on the
+    /// central baseline. This method returns the offset from the pen position
+    /// to the glyph's (horizontal) origin, with `y` downwards. This uses the
+    /// `VORG` table where available, otherwise the ascent.
+    pub fn v_origin_offset(&self, id: GlyphId) -> Vec2 {
+        let x = -0.5 * self.h_advance(id);
+        let y = match self.0.glyph_y_origin(id.into()) {
+            Some(y) => self.1.i16_to_px(y),
+            None => self.ascent(),
+        };
+        Vec2(x, y)
+    }
+
     /// Horizontal side bearing
     ///
     /// If unspecified by the font this resolves to 0.
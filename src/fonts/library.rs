@@ -217,6 +217,16 @@ impl FaceStore {
     pub fn synthesis(&self) -> &Synthesis {
         &self.synthesis
     }
+
+    /// Get the slant of glyphs
+    ///
+    /// This is the horizontal offset per unit of height (positive where glyphs
+    /// lean to the right), accounting for the face's italic angle and any
+    /// synthetic skew.
+    pub fn slant(&self) -> f32 {
+        let degrees = self.synthesis.skew().unwrap_or(0.0) - self.face.italic_angle();
+        degrees.to_radians().tan()
+    }
 }
 
 #[derive(Default)]
@@ -7,10 +7,13 @@
 
 #![allow(deprecated)]
 
-use crate::{FontToken, fonts::FontSelector};
+use crate::{FontToken, InlineObject, ParagraphFormat, fonts::FontSelector};
 use std::fmt::Debug; // for doc-links
+use std::ops::Range;
 
 mod plain;
+mod rich;
+pub use rich::{FontFormat, RichText};
 
 #[cfg(feature = "markdown")]
 mod markdown;
@@ -90,6 +93,36 @@ pub trait FormattableText: std::cmp::PartialEq {
     /// Changes to the result of this method do not require any re-preparation
     /// of text.
     fn effect_tokens(&self) -> &[(u32, Self::Effect)];
+
+    /// Return the sequence of paragraph formats
+    ///
+    /// Each item `(index, format)` sets the format of the paragraph starting
+    /// at text `index` (see [`Appender::with_paragraph`]). Items should be
+    /// ordered by `index`. The default implementation returns `&[]`: all
+    /// paragraphs use default formatting.
+    ///
+    /// Any changes to the result of this method require full re-preparation of
+    /// text.
+    ///
+    /// [`Appender::with_paragraph`]: crate::Appender::with_paragraph
+    fn paragraph_formats(&self) -> &[(u32, ParagraphFormat)] {
+        &[]
+    }
+
+    /// Return the sequence of inline objects
+    ///
+    /// Each item `(index, object)` places an object at text `index`, which
+    /// should be a U+FFFC OBJECT REPLACEMENT CHARACTER (see
+    /// [`Appender::with_object`]). Items should be ordered by `index`. The
+    /// default implementation returns `&[]`.
+    ///
+    /// Any changes to the result of this method require full re-preparation of
+    /// text.
+    ///
+    /// [`Appender::with_object`]: crate::Appender::with_object
+    fn objects(&self) -> &[(u32, InlineObject)] {
+        &[]
+    }
 }
 
 impl<F: FormattableText + ?Sized> FormattableText for &F {
@@ -106,4 +139,36 @@ impl<F: FormattableText + ?Sized> FormattableText for &F {
     fn effect_tokens(&self) -> &[(u32, Self::Effect)] {
         F::effect_tokens(self)
     }
+
+    fn paragraph_formats(&self) -> &[(u32, ParagraphFormat)] {
+        F::paragraph_formats(self)
+    }
+
+    fn objects(&self) -> &[(u32, InlineObject)] {
+        F::objects(self)
+    }
+}
+
+/// Text which may be edited
+///
+/// Implementations adjust the positions of formatting tokens as text is
+/// replaced. See also [`EditBuffer`](crate::EditBuffer).
+///
+/// All ranges are byte ranges which must be on `char` boundaries.
+pub trait EditableText: FormattableText + Sized {
+    /// Replace `range` of the text with plain text `replace_with`
+    ///
+    /// The new text takes the formatting of the preceding text (or, at the
+    /// start of the text, of the following text). Formatting of text
+    /// following `range` is unaffected.
+    fn replace_range(&mut self, range: Range<usize>, replace_with: &str);
+
+    /// Copy `range` of the text, including formatting
+    fn slice(&self, range: Range<usize>) -> Self;
+
+    /// Replace `range` of the text with `replace_with`, including formatting
+    ///
+    /// For example, `text.splice(range.clone(), &text.slice(range))` leaves
+    /// `text` unchanged.
+    fn splice(&mut self, range: Range<usize>, replace_with: &Self);
 }
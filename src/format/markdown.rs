@@ -8,6 +8,7 @@
 use super::{Effect, EffectFlags, FontToken, FormattableText};
 use crate::conv::to_u32;
 use crate::fonts::{FamilySelector, FontSelector, FontStyle, FontWeight};
+use crate::{Length, ParagraphFormat};
 use pulldown_cmark::{Event, HeadingLevel, Tag, TagEnd};
 use std::fmt::Write;
 use std::iter::FusedIterator;
@@ -31,7 +32,7 @@ pub enum Error {
 /// -   Code (embedded and blocks); caveat: extra line after code blocks
 /// -   Explicit line breaks
 /// -   Headings
-/// -   Lists (numerated and bulleted); caveat: indentation after first line
+/// -   Lists (numerated and bulleted)
 /// -   Bold, italic (emphasis), strike-through
 ///
 /// Not supported:
@@ -53,6 +54,7 @@ pub struct Markdown {
     text: String,
     fmt: Vec<Fmt>,
     effects: Vec<(u32, Effect)>,
+    paragraphs: Vec<(u32, ParagraphFormat)>,
 }
 
 impl Markdown {
@@ -104,7 +106,12 @@ impl<'a> Iterator for FontTokenIter<'a> {
             if fmt.monospace {
                 font.family = FamilySelector::MONOSPACE;
             }
-            Some(FontToken { start, font, dpem })
+            Some(FontToken {
+                start,
+                font,
+                dpem,
+                ..Default::default()
+            })
         } else {
             None
         }
@@ -135,11 +142,16 @@ impl FormattableText for Markdown {
     fn effect_tokens(&self) -> &[(u32, Effect)] {
         &self.effects
     }
+
+    fn paragraph_formats(&self) -> &[(u32, ParagraphFormat)] {
+        &self.paragraphs
+    }
 }
 
 fn parse(input: &str) -> Result<Markdown, Error> {
     let mut text = String::with_capacity(input.len());
     let mut fmt: Vec<Fmt> = vec![Fmt::default()];
+    let mut paragraphs = vec![];
     let mut set_last = |item: &StackItem| {
         let f = item.fmt.clone();
         if let Some(last) = fmt.last_mut()
@@ -160,7 +172,7 @@ fn parse(input: &str) -> Result<Markdown, Error> {
         match ev {
             Event::Start(tag) => {
                 item.fmt.start = to_u32(text.len());
-                if let Some(clone) = item.start_tag(&mut text, &mut state, tag)? {
+                if let Some(clone) = item.start_tag(&mut text, &mut paragraphs, &mut state, tag)? {
                     stack.push(item);
                     item = clone;
                     set_last(&item);
@@ -220,7 +232,12 @@ fn parse(input: &str) -> Result<Markdown, Error> {
         }
     }
 
-    Ok(Markdown { text, fmt, effects })
+    Ok(Markdown {
+        text,
+        fmt,
+        effects,
+        paragraphs,
+    })
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -290,9 +307,14 @@ impl Default for Fmt {
     }
 }
 
+/// Indentation of each level of list (Em)
+const LIST_INDENT: f32 = 2.0;
+
 #[derive(Clone, Debug, Default)]
 struct StackItem {
     list: Option<u64>,
+    /// List nesting depth
+    depth: u32,
     fmt: Fmt,
 }
 
@@ -301,6 +323,7 @@ impl StackItem {
     fn start_tag(
         &mut self,
         text: &mut String,
+        paragraphs: &mut Vec<(u32, ParagraphFormat)>,
         state: &mut State,
         tag: Tag,
     ) -> Result<Option<Self>, Error> {
@@ -341,13 +364,17 @@ impl StackItem {
             Tag::HtmlBlock => return Err(Error::NotSupported("embedded HTML")),
             Tag::List(start) => {
                 state.start_block(text);
-                self.list = start;
-                None
+                with_clone(self, |item| {
+                    item.list = start;
+                    item.depth += 1;
+                })
             }
             Tag::Item => {
                 state.list_item(text);
-                // NOTE: we use \t for indent, which indents only the first
-                // line. Without better flow control we cannot fix this.
+                // The marker is followed by a tab which stops at the start margin
+                let indent = LIST_INDENT * self.depth as f32;
+                let format = ParagraphFormat::hanging(Length::Em(indent), Length::Em(LIST_INDENT));
+                paragraphs.push((to_u32(text.len()), format));
                 match &mut self.list {
                     Some(x) => {
                         write!(text, "{x}\t").unwrap();
@@ -382,11 +409,11 @@ impl StackItem {
     // returns true if stack must be popped
     fn end_tag(&self, state: &mut State, tag: TagEnd) -> bool {
         match tag {
-            TagEnd::Paragraph | TagEnd::List(_) => {
+            TagEnd::Paragraph => {
                 state.end_block();
                 false
             }
-            TagEnd::Heading(_) | TagEnd::CodeBlock => {
+            TagEnd::Heading(_) | TagEnd::CodeBlock | TagEnd::List(_) => {
                 state.end_block();
                 true
             }
@@ -5,8 +5,9 @@
 
 //! Implementations for plain text
 
-use super::FormattableText;
+use super::{EditableText, FormattableText};
 use crate::{FontToken, fonts::FontSelector};
+use std::ops::Range;
 
 impl FormattableText for str {
     type Effect = ();
@@ -18,8 +19,11 @@ impl FormattableText for str {
 
     #[inline]
     fn font_tokens(&self, dpem: f32, font: FontSelector) -> impl Iterator<Item = FontToken> {
-        let start = 0;
-        std::iter::once(FontToken { start, dpem, font })
+        std::iter::once(FontToken {
+            dpem,
+            font,
+            ..Default::default()
+        })
     }
 
     #[inline]
@@ -38,8 +42,11 @@ impl FormattableText for String {
 
     #[inline]
     fn font_tokens(&self, dpem: f32, font: FontSelector) -> impl Iterator<Item = FontToken> {
-        let start = 0;
-        std::iter::once(FontToken { start, dpem, font })
+        std::iter::once(FontToken {
+            dpem,
+            font,
+            ..Default::default()
+        })
     }
 
     #[inline]
@@ -47,3 +54,20 @@ impl FormattableText for String {
         &[]
     }
 }
+
+impl EditableText for String {
+    #[inline]
+    fn replace_range(&mut self, range: Range<usize>, replace_with: &str) {
+        String::replace_range(self, range, replace_with);
+    }
+
+    #[inline]
+    fn slice(&self, range: Range<usize>) -> Self {
+        self[range].to_string()
+    }
+
+    #[inline]
+    fn splice(&mut self, range: Range<usize>, replace_with: &Self) {
+        String::replace_range(self, range, replace_with);
+    }
+}
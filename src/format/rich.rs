@@ -0,0 +1,339 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Editable formatted text
+
+use super::{EditableText, FormattableText};
+use crate::conv::{to_u32, to_usize};
+use crate::{FontToken, Length, ParagraphFormat, fonts::FontSelector};
+use std::fmt::Debug;
+use std::ops::Range;
+
+/// Font formatting of a span of [`RichText`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FontFormat {
+    /// The font; if `None`, the font of the [`Text`](crate::Text) is used
+    pub font: Option<FontSelector>,
+    /// Font size, relative to that of the [`Text`](crate::Text)
+    pub scale: f32,
+    /// Letter spacing; see [`FontToken::letter_spacing`]
+    pub letter_spacing: Length,
+    /// Word spacing; see [`FontToken::word_spacing`]
+    pub word_spacing: Length,
+}
+
+impl Default for FontFormat {
+    /// Construct with the default font and size and no additional spacing
+    fn default() -> Self {
+        FontFormat {
+            font: None,
+            scale: 1.0,
+            letter_spacing: Length::ZERO,
+            word_spacing: Length::ZERO,
+        }
+    }
+}
+
+/// Formatted text supporting editing
+///
+/// This is a simple model of text with font formatting, effects and paragraph
+/// formats, implementing [`EditableText`]. Formatting is set over ranges of
+/// text and adjusted automatically as text is edited.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RichText<E> {
+    text: String,
+    fonts: Vec<(u32, FontFormat)>,
+    effects: Vec<(u32, E)>,
+    paragraphs: Vec<(u32, ParagraphFormat)>,
+}
+
+impl<E: Copy + Debug + Default + PartialEq> RichText<E> {
+    /// Construct from unformatted text
+    #[inline]
+    pub fn new(text: impl ToString) -> Self {
+        RichText {
+            text: text.to_string(),
+            fonts: vec![],
+            effects: vec![],
+            paragraphs: vec![],
+        }
+    }
+
+    /// Set the font formatting of `range`
+    pub fn set_font(&mut self, range: Range<usize>, format: FontFormat) {
+        set_span(&mut self.fonts, range, format, self.text.len());
+    }
+
+    /// Set the effect of `range`
+    pub fn set_effect(&mut self, range: Range<usize>, effect: E) {
+        set_span(&mut self.effects, range, effect, self.text.len());
+    }
+
+    /// Set the format of the paragraph starting at `index`
+    ///
+    /// `index` should be the start of a paragraph (see
+    /// [`FormattableText::paragraph_formats`]).
+    pub fn set_paragraph(&mut self, index: usize, format: ParagraphFormat) {
+        let index = to_u32(index);
+        let i = self.paragraphs.partition_point(|(p, _)| *p < index);
+        match self.paragraphs.get_mut(i) {
+            Some(item) if item.0 == index => item.1 = format,
+            _ => self.paragraphs.insert(i, (index, format)),
+        }
+    }
+
+    /// Access the font formatting tokens
+    #[inline]
+    pub fn fonts(&self) -> &[(u32, FontFormat)] {
+        &self.fonts
+    }
+
+    fn edit(&mut self, range: Range<usize>, replace_with: &str, fragment: Option<&Self>) {
+        let old_len = to_u32(self.text.len());
+        let new_len = to_u32(replace_with.len());
+        let range = to_u32(range.start)..to_u32(range.end);
+        self.text
+            .replace_range(to_usize(range.start)..to_usize(range.end), replace_with);
+
+        let fonts = fragment.map(|f| f.fonts.as_slice());
+        splice_tokens(&mut self.fonts, range.clone(), fonts, new_len, old_len);
+        let effects = fragment.map(|f| f.effects.as_slice());
+        splice_tokens(&mut self.effects, range.clone(), effects, new_len, old_len);
+
+        // Formats of paragraphs starting within the range are removed
+        let delta = new_len.wrapping_sub(range.end - range.start);
+        let a = self.paragraphs.partition_point(|(p, _)| *p <= range.start);
+        let b = match range.is_empty() {
+            true => a,
+            false => self.paragraphs.partition_point(|(p, _)| *p <= range.end),
+        };
+        let inserted = fragment
+            .map(|f| f.paragraphs.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .map(|(p, format)| (range.start + p, *format));
+        self.paragraphs.splice(a..b, inserted);
+        let tail = a + fragment.map(|f| f.paragraphs.len()).unwrap_or(0);
+        for (p, _) in &mut self.paragraphs[tail..] {
+            *p = p.wrapping_add(delta);
+        }
+    }
+}
+
+impl<E: Copy + Debug + Default + PartialEq> FormattableText for RichText<E> {
+    type Effect = E;
+
+    #[inline]
+    fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    fn font_tokens(&self, dpem: f32, font: FontSelector) -> impl Iterator<Item = FontToken> {
+        self.fonts.iter().map(move |(start, format)| FontToken {
+            start: *start,
+            dpem: dpem * format.scale,
+            font: format.font.unwrap_or(font),
+            letter_spacing: format.letter_spacing,
+            word_spacing: format.word_spacing,
+        })
+    }
+
+    #[inline]
+    fn effect_tokens(&self) -> &[(u32, E)] {
+        &self.effects
+    }
+
+    #[inline]
+    fn paragraph_formats(&self) -> &[(u32, ParagraphFormat)] {
+        &self.paragraphs
+    }
+}
+
+impl<E: Copy + Debug + Default + PartialEq> EditableText for RichText<E> {
+    #[inline]
+    fn replace_range(&mut self, range: Range<usize>, replace_with: &str) {
+        self.edit(range, replace_with, None);
+    }
+
+    fn slice(&self, range: Range<usize>) -> Self {
+        let r = to_u32(range.start)..to_u32(range.end);
+        RichText {
+            text: self.text[range].to_string(),
+            fonts: slice_tokens(&self.fonts, r.clone()),
+            effects: slice_tokens(&self.effects, r.clone()),
+            paragraphs: self
+                .paragraphs
+                .iter()
+                .filter(|(p, _)| r.start < *p && *p <= r.end)
+                .map(|(p, format)| (p - r.start, *format))
+                .collect(),
+        }
+    }
+
+    #[inline]
+    fn splice(&mut self, range: Range<usize>, replace_with: &Self) {
+        self.edit(range, &replace_with.text, Some(replace_with));
+    }
+}
+
+/// Find the index of the token active at `index`
+fn active<V>(tokens: &[(u32, V)], index: u32) -> Option<usize> {
+    tokens.partition_point(|t| t.0 <= index).checked_sub(1)
+}
+
+/// Set `value` over `range` of a text of length `len`
+fn set_span<V: Clone + Default + PartialEq>(
+    tokens: &mut Vec<(u32, V)>,
+    range: Range<usize>,
+    value: V,
+    len: usize,
+) {
+    if range.is_empty() {
+        return;
+    }
+    let (start, end) = (to_u32(range.start), to_u32(range.end));
+    let resume = active(tokens, end).map(|i| tokens[i].1.clone());
+    let a = tokens.partition_point(|t| t.0 < start);
+    let b = tokens.partition_point(|t| t.0 <= end);
+    let mut items = vec![(start, value)];
+    if range.end < len {
+        items.push((end, resume.unwrap_or_default()));
+    }
+    tokens.splice(a..b, items);
+    normalize(tokens);
+}
+
+/// Copy tokens over `range`, relative to its start
+fn slice_tokens<V: Clone>(tokens: &[(u32, V)], range: Range<u32>) -> Vec<(u32, V)> {
+    let mut v = vec![];
+    if let Some(i) = active(tokens, range.start) {
+        v.push((0, tokens[i].1.clone()));
+    }
+    let within = tokens
+        .iter()
+        .filter(|t| range.start < t.0 && t.0 < range.end)
+        .map(|(p, value)| (p - range.start, value.clone()));
+    v.extend(within);
+    v
+}
+
+/// Replace `range` (of a text of length `old_len`) with `new_len` bytes
+///
+/// If `fragment` is `None`, new text inherits the preceding token (or,
+/// at the start, the following token). Otherwise `fragment` gives the tokens of
+/// new text.
+fn splice_tokens<V: Clone + Default + PartialEq>(
+    tokens: &mut Vec<(u32, V)>,
+    range: Range<u32>,
+    fragment: Option<&[(u32, V)]>,
+    new_len: u32,
+    old_len: u32,
+) {
+    let after = active(tokens, range.end).map(|i| tokens[i].1.clone());
+    let after = after.unwrap_or_default();
+    let a = tokens.partition_point(|t| t.0 < range.start);
+    let b = tokens.partition_point(|t| t.0 <= range.end);
+
+    let mut items = vec![];
+    match fragment {
+        Some(fragment) => {
+            let fragment = fragment.iter().filter(|t| t.0 < new_len);
+            items.extend(fragment.map(|(p, value)| (range.start + p, value.clone())));
+        }
+        None if range.start == 0 => items.push((0, after.clone())),
+        None => (),
+    }
+    if range.end < old_len {
+        items.push((range.start + new_len, after));
+    }
+
+    let delta = new_len.wrapping_sub(range.end - range.start);
+    let tail: Vec<_> = tokens[b..]
+        .iter()
+        .map(|(p, value)| (p.wrapping_add(delta), value.clone()))
+        .collect();
+    tokens.truncate(a);
+    tokens.extend(items);
+    tokens.extend(tail);
+    normalize(tokens);
+}
+
+/// Remove redundant tokens
+fn normalize<V: Default + PartialEq>(tokens: &mut Vec<(u32, V)>) {
+    tokens.dedup_by(|b, a| b.1 == a.1);
+    if tokens.first().is_some_and(|t| t.1 == V::default()) {
+        tokens.remove(0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text() -> RichText<u8> {
+        let mut text = RichText::new("one two three");
+        text.set_effect(4..7, 1);
+        text.set_effect(8..13, 2);
+        text
+    }
+
+    #[test]
+    fn set_effect() {
+        let mut text = text();
+        assert_eq!(text.effect_tokens(), [(4, 1), (7, 0), (8, 2)]);
+        text.set_effect(2..10, 3);
+        assert_eq!(text.effect_tokens(), [(2, 3), (10, 2)]);
+    }
+
+    #[test]
+    fn replace() {
+        // Insertion takes the formatting of the preceding text
+        let mut text = text();
+        text.replace_range(7..7, "!");
+        assert_eq!(text.as_str(), "one two! three");
+        assert_eq!(text.effect_tokens(), [(4, 1), (8, 0), (9, 2)]);
+
+        // Replacement within a span
+        text.replace_range(5..7, "X");
+        assert_eq!(text.as_str(), "one tX! three");
+        assert_eq!(text.effect_tokens(), [(4, 1), (7, 0), (8, 2)]);
+
+        // Deletion over a span boundary
+        text.replace_range(2..8, "");
+        assert_eq!(text.as_str(), "onthree");
+        assert_eq!(text.effect_tokens(), [(2, 2)]);
+
+        // Insertion at the start takes the following formatting
+        let mut text = RichText::<u8>::new("ab");
+        text.set_effect(0..1, 1);
+        text.replace_range(0..0, "x");
+        assert_eq!(text.effect_tokens(), [(0, 1), (2, 0)]);
+    }
+
+    #[test]
+    fn splice() {
+        let mut text = text();
+        text.set_paragraph(0, ParagraphFormat::default());
+        let original = text.clone();
+        for range in [0..0, 2..5, 4..7, 6..13, 0..13] {
+            let slice = text.slice(range.clone());
+            text.replace_range(range.clone(), "xyz");
+            text.splice(range.start..range.start + 3, &slice);
+            assert_eq!(text, original, "range {range:?}");
+        }
+
+        let mut text = RichText::<u8>::new("a\nb\nc");
+        text.set_paragraph(2, ParagraphFormat::default());
+        text.set_paragraph(4, ParagraphFormat::default());
+        let original = text.clone();
+        let slice = text.slice(1..3);
+        text.replace_range(1..3, "");
+        assert_eq!(text.as_str(), "a\nc");
+        assert_eq!(text.paragraph_formats().len(), 1);
+        assert_eq!(text.paragraph_formats()[0].0, 2);
+        text.splice(1..1, &slice);
+        assert_eq!(text, original);
+    }
+}
@@ -0,0 +1,240 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Text navigation: caret geometry
+
+use super::{Affinity, Forme, MarkerPos};
+use crate::Vec2;
+
+/// Source of the height of a caret
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CaretHeight {
+    /// The caret spans from [`Line::top`](super::Line::top) to
+    /// [`Line::bottom`](super::Line::bottom)
+    ///
+    /// Carets on a line have uniform height.
+    #[default]
+    Line,
+    /// The caret spans the ascent and descent of the font at the caret
+    ///
+    /// Carets match the size of adjacent text.
+    Font,
+}
+
+/// A caret, drawn as a line segment
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Caret {
+    /// The top end of the caret
+    ///
+    /// In vertical [writing modes](Forme::set_writing_mode), this is the end
+    /// of the caret at the top of the line (the ascent).
+    pub top: Vec2,
+    /// The bottom end of the caret
+    pub bottom: Vec2,
+    level: u8,
+}
+
+impl Caret {
+    /// Returns the embedding level of the text at the caret
+    ///
+    /// See [`MarkerPos::embedding_level`].
+    #[inline]
+    pub fn embedding_level(&self) -> u8 {
+        self.level
+    }
+
+    /// Returns true if the caret is left-to-right
+    #[inline]
+    pub fn is_ltr(&self) -> bool {
+        self.level % 2 == 0
+    }
+
+    /// Returns true if the caret is right-to-left
+    #[inline]
+    pub fn is_rtl(&self) -> bool {
+        self.level % 2 == 1
+    }
+}
+
+/// The shape of a caret, as returned by [`Forme::caret_shape`]
+///
+/// Where a caret is at a boundary of bidirectional text, its index has two
+/// positions: one after the preceding text and one before the following text.
+/// The `primary` caret is at the position selected by the affinity while the
+/// `secondary` caret is at the other position. Native platforms typically
+/// draw a split caret in this case, with the primary caret in the upper half of
+/// the line and the secondary caret in the lower half (or draw only the
+/// primary caret, perhaps with a flag indicating direction).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CaretShape {
+    /// The caret at the position selected by the affinity
+    pub primary: Caret,
+    /// The caret at the alternative position, if any
+    ///
+    /// This is `None` unless the caret is at a boundary of bidirectional text
+    /// on a single line (at a line wrap only the primary caret is drawn).
+    pub secondary: Option<Caret>,
+}
+
+impl Forme {
+    /// Find the shape of the caret at `index` with the given `affinity`
+    ///
+    /// Expects state [`Status::Ready`](crate::Status::Ready).
+    ///
+    /// The caret is a line segment with `height` given by the line or the
+    /// font at the caret position. In horizontal writing modes the caret is
+    /// slanted to match the font's italic angle or synthetic skew.
+    /// Where the caret is at a boundary of bidirectional text, a
+    /// [secondary](CaretShape::secondary) caret is also returned.
+    ///
+    /// Returns `None` when `index` is not at a position in the text (as with
+    /// [`Self::caret_pos`]).
+    pub fn caret_shape(
+        &self,
+        index: usize,
+        affinity: Affinity,
+        height: CaretHeight,
+    ) -> Option<CaretShape> {
+        let iter = self.text_glyph_pos(index);
+        let positions = iter.as_slice();
+        let (primary, other) = match (affinity, positions) {
+            (_, []) => return None,
+            (_, [pos]) => (pos, None),
+            (Affinity::Upstream, [a, b]) => (a, Some(b)),
+            (_, [a, b, ..]) => (b, Some(a)),
+        };
+
+        let primary_caret = self.caret(primary, height);
+        let secondary = other.and_then(|pos| {
+            let caret = self.caret(pos, height);
+            let baseline = |pos: &MarkerPos| {
+                self.config
+                    .writing_mode
+                    .to_logical(pos.pos, self.block_size)
+                    .1
+            };
+            (baseline(pos) == baseline(primary) && caret.is_ltr() != primary_caret.is_ltr())
+                .then_some(caret)
+        });
+
+        Some(CaretShape {
+            primary: primary_caret,
+            secondary,
+        })
+    }
+
+    fn caret(&self, pos: &MarkerPos, height: CaretHeight) -> Caret {
+        let Vec2(x, y) = self
+            .config
+            .writing_mode
+            .to_logical(pos.pos, self.block_size);
+        let font = (y - pos.ascent, y - pos.descent);
+        let (top, bottom) = match height {
+            CaretHeight::Line => self
+                .lines
+                .iter()
+                .find(|line| line.top <= y && y <= line.bottom)
+                .map(|line| (line.top, line.bottom))
+                .unwrap_or(font),
+            CaretHeight::Font => font,
+        };
+
+        let slant = match self.config.writing_mode.is_vertical() {
+            false => pos.slant(),
+            true => 0.0,
+        };
+        Caret {
+            top: self.to_physical(Vec2(x + slant * (y - top), top)),
+            bottom: self.to_physical(Vec2(x - slant * (bottom - y), bottom)),
+            level: pos.embedding_level(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fonts::{FontSelector, FontStyle};
+    use crate::forme::test_util::{prepare, shape};
+    use crate::{Align, Direction};
+
+    #[test]
+    fn height() {
+        let forme = prepare("abc", 1000.0);
+        let line = forme.get_line(0).unwrap();
+        let shape = forme
+            .caret_shape(1, Affinity::Downstream, CaretHeight::Line)
+            .unwrap();
+        assert!(shape.secondary.is_none());
+        let caret = shape.primary;
+        let x = forme.caret_pos(1, Affinity::Downstream).unwrap().pos.0;
+        assert_eq!(caret.top, Vec2(x, line.top()));
+        assert_eq!(caret.bottom, Vec2(x, line.bottom()));
+
+        let font = forme
+            .caret_shape(1, Affinity::Downstream, CaretHeight::Font)
+            .unwrap()
+            .primary;
+        assert_eq!(font.top.0, x);
+        assert!(font.top.1 >= caret.top.1 && font.bottom.1 <= caret.bottom.1);
+        assert!(font.bottom.1 - font.top.1 > 0.0);
+
+        assert!(
+            forme
+                .caret_shape(100, Affinity::Downstream, CaretHeight::Line)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn slant() {
+        let font = FontSelector {
+            style: FontStyle::Italic,
+            ..Default::default()
+        };
+        let mut forme = Forme::default();
+        assert!(
+            forme
+                .set_text("abc", Direction::Auto)
+                .with_font(.., font, 16.0)
+                .is_ok()
+        );
+        forme.prepare_lines(1000.0, 1000.0, Align::Default);
+        let caret = forme
+            .caret_shape(1, Affinity::Downstream, CaretHeight::Line)
+            .unwrap()
+            .primary;
+        // The caret leans to the right
+        assert!(caret.top.0 > caret.bottom.0);
+    }
+
+    #[test]
+    fn split() {
+        // Visual order: "ab גבא cd"
+        let forme = prepare("ab אבג cd", 1000.0);
+        let up = forme
+            .caret_shape(3, Affinity::Upstream, CaretHeight::Line)
+            .unwrap();
+        let down = forme
+            .caret_shape(3, Affinity::Downstream, CaretHeight::Line)
+            .unwrap();
+        assert!(up.primary.is_ltr() && down.primary.is_rtl());
+        assert_eq!(up.secondary, Some(down.primary));
+        assert_eq!(down.secondary, Some(up.primary));
+
+        // A line wrap does not give a split caret
+        let mut forme = Forme::default();
+        forme.set_overflow_wrap(crate::OverflowWrap::Anywhere);
+        shape(&mut forme, "aaaaaaaa");
+        forme.prepare_lines(25.0, 25.0, Align::Default);
+        assert_eq!(forme.get_line(1).unwrap().text_range().start, 2);
+        assert_eq!(forme.text_glyph_pos(2).len(), 2);
+        let shape = forme
+            .caret_shape(2, Affinity::Upstream, CaretHeight::Line)
+            .unwrap();
+        assert!(shape.secondary.is_none());
+    }
+}
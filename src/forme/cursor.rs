@@ -0,0 +1,330 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Text navigation: line, visual and vertical cursor movement
+
+use super::Forme;
+use crate::conv::to_usize;
+use crate::{Vec2, segment};
+use icu_segmenter::GraphemeClusterSegmenter;
+
+impl Forme {
+    /// Find the start of the line containing `index`
+    ///
+    /// Expects state [`Status::Wrapped`](crate::Status::Wrapped) or higher.
+    ///
+    /// Returns `index` if it is not on any line (see [`Self::find_line`]).
+    pub fn line_start(&self, index: usize) -> usize {
+        self.find_line(index).map(|(_, r)| r.start).unwrap_or(index)
+    }
+
+    /// Find the end of the line containing `index`
+    ///
+    /// Expects state [`Status::Wrapped`](crate::Status::Wrapped) or higher.
+    ///
+    /// The line end excludes any trailing line break or, where the line was
+    /// wrapped, trailing white-space. Returns `index` if it is not on any line
+    /// (see [`Self::find_line`]).
+    pub fn line_end(&self, index: usize) -> usize {
+        self.find_line(index).map(|(_, r)| r.end).unwrap_or(index)
+    }
+
+    /// Move the cursor one grapheme cluster to the left (visually)
+    ///
+    /// Expects state [`Status::Ready`](crate::Status::Ready). The `text` must
+    /// be the text passed to [`Self::set_text`].
+    ///
+    /// Navigation follows the visual order of bidirectional text, thus
+    /// (within a left-to-right line) this moves backwards over left-to-right
+    /// text but forwards over embedded right-to-left text. From the left end of
+    /// a line the cursor moves to the right end of the previous line (or of
+    /// the next line where the line is right-to-left).
+    ///
+    /// Returns `index` where no movement is possible.
+    /// See also [`segment`] for logical-order navigation.
+    pub fn move_left(&self, text: &str, index: usize) -> usize {
+        self.move_visual(text, index, false)
+    }
+
+    /// Move the cursor one grapheme cluster to the right (visually)
+    ///
+    /// This mirrors [`Self::move_left`].
+    pub fn move_right(&self, text: &str, index: usize) -> usize {
+        self.move_visual(text, index, true)
+    }
+
+    fn move_visual(&self, text: &str, index: usize, right: bool) -> usize {
+        let Some((line, _)) = self.find_line(index) else {
+            return index;
+        };
+
+        let carets = self.line_carets(text, line);
+        let Some(i) = carets.iter().position(|c| c.1 == index) else {
+            // The index is not a caret position, e.g. within a cluster
+            return match right != self.line_is_rtl(line).unwrap_or_default() {
+                true => segment::next_grapheme(text, index),
+                false => segment::prev_grapheme(text, index),
+            };
+        };
+        let next = if right {
+            i.checked_add(1)
+        } else {
+            i.checked_sub(1)
+        };
+        if let Some(&(_, index)) = next.and_then(|i| carets.get(i)) {
+            return index;
+        }
+
+        // Move to the logically adjacent line
+        let forwards = right != self.line_is_rtl(line).unwrap_or_default();
+        if forwards {
+            match self.lines.get(line + 1) {
+                Some(next) => next.text_range().start,
+                None => index,
+            }
+        } else if line > 0 {
+            let end = self.lines[line - 1].text_range().end;
+            if end == index {
+                // The line was wrapped without a break char
+                segment::prev_grapheme(text, index)
+            } else {
+                end
+            }
+        } else {
+            index
+        }
+    }
+
+    /// Move the cursor up or down by `lines`
+    ///
+    /// Expects state [`Status::Ready`](crate::Status::Ready).
+    ///
+    /// Moves the cursor at `index` by `lines` (negative values move up, or
+    /// towards the start of the text), returning the new index and the goal
+    /// horizontal position. The cursor is placed at the position on the target
+    /// line nearest `goal_x`; when `None`, the current position of `index` is
+    /// used. Pass the returned goal to subsequent vertical movements (and reset
+    /// it to `None` after any other movement) to preserve the original column
+    /// when passing over shorter lines.
+    ///
+    /// Where the target is beyond the first or last line, the cursor moves to
+    /// that line, or, if already there, to the start or end of the text.
+    /// Returns `index` unchanged if it is not on any line
+    /// (see [`Self::find_line`]).
+    ///
+    /// In vertical [writing modes](Self::set_writing_mode), `goal_x` is the
+    /// vertical coordinate.
+    pub fn move_vertical(&self, index: usize, lines: isize, goal_x: Option<f32>) -> (usize, f32) {
+        let Some((line, _)) = self.find_line(index) else {
+            return (index, goal_x.unwrap_or(0.0));
+        };
+        let goal_x = goal_x.unwrap_or_else(|| self.caret_x(line, index));
+        let target = line
+            .checked_add_signed(lines)
+            .filter(|target| *target < self.lines.len());
+        self.move_to_line(index, line, target, lines < 0, goal_x)
+    }
+
+    /// Move the cursor up or down by a `distance` in pixels
+    ///
+    /// Expects state [`Status::Ready`](crate::Status::Ready).
+    ///
+    /// This is a variant of [`Self::move_vertical`] suitable for the page-up
+    /// and page-down keys. The target line is that at `distance` from the
+    /// middle of the current line (negative values move up), usually the
+    /// height of the visible area. Clamping at the first and last lines and
+    /// usage of `goal_x` are as for [`Self::move_vertical`].
+    pub fn move_page(&self, index: usize, distance: f32, goal_x: Option<f32>) -> (usize, f32) {
+        let Some((line, _)) = self.find_line(index) else {
+            return (index, goal_x.unwrap_or(0.0));
+        };
+        let goal_x = goal_x.unwrap_or_else(|| self.caret_x(line, index));
+        let current = &self.lines[line];
+        let y = 0.5 * (current.top + current.bottom) + distance;
+        let target = if distance < 0.0 {
+            self.lines.iter().rposition(|l| l.top <= y)
+        } else {
+            self.lines.iter().position(|l| y <= l.bottom)
+        };
+        self.move_to_line(index, line, target, distance < 0.0, goal_x)
+    }
+
+    fn move_to_line(
+        &self,
+        index: usize,
+        line: usize,
+        target: Option<usize>,
+        up: bool,
+        goal_x: f32,
+    ) -> (usize, f32) {
+        let target = match target {
+            Some(target) => target,
+            None if up && line > 0 => 0,
+            None if !up && line + 1 < self.lines.len() => self.lines.len() - 1,
+            None if up => return (0, goal_x),
+            None => return (self.text_len, goal_x),
+        };
+        let index = self.line_index_nearest(target, goal_x).unwrap_or(index);
+        (index, goal_x)
+    }
+
+    /// Get the (logical) horizontal position of `index` on `line`
+    fn caret_x(&self, line: usize, index: usize) -> f32 {
+        let line = &self.lines[line];
+        let iter = self.text_glyph_pos(index);
+        let positions = iter.as_slice();
+        let logical = |pos: Vec2| self.config.writing_mode.to_logical(pos, self.block_size);
+        positions
+            .iter()
+            .map(|marker| logical(marker.pos))
+            .rfind(|pos| line.top <= pos.1 && pos.1 <= line.bottom)
+            .or(positions.last().map(|marker| logical(marker.pos)))
+            .map(|pos| pos.0)
+            .unwrap_or(0.0)
+    }
+
+    /// Get caret positions on `line` as `(x, index)`, sorted by `x`
+    ///
+    /// Only grapheme cluster boundaries which [`Self::find_line`] places on
+    /// `line` are included. Where an index has two positions (at the
+    /// boundary of runs), the position reported last by
+    /// [`Self::text_glyph_pos`] is used.
+    fn line_carets(&self, text: &str, line: usize) -> Vec<(f32, usize)> {
+        let mut carets: Vec<(f32, usize)> = vec![];
+        self.visit_carets(&self.lines[line], |x, index, _| {
+            carets.push((x, to_usize(index)));
+        });
+        // Keep the position reported last for each index (the sort is stable)
+        carets.reverse();
+        carets.sort_by_key(|c| c.1);
+        carets.dedup_by_key(|c| c.1);
+
+        let range = self.lines[line].text_range();
+        let start = carets.first().map(|c| c.1).unwrap_or(range.start);
+        let end = carets.last().map(|c| c.1).unwrap_or(range.end);
+        let boundaries: Vec<usize> = GraphemeClusterSegmenter::new()
+            .segment_str(&text[start..end])
+            .map(|b| start + b)
+            .collect();
+
+        carets.retain(|(_, index)| {
+            boundaries.binary_search(index).is_ok()
+                && (range.contains(index) || self.find_line(*index).map(|l| l.0) == Some(line))
+        });
+        carets.sort_by(|a, b| a.0.total_cmp(&b.0));
+        carets
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Forme;
+    use crate::forme::test_util::prepare;
+
+    /// Visit all positions reachable by repeated movement from `index`
+    fn walk(forme: &Forme, text: &str, mut index: usize, right: bool) -> Vec<usize> {
+        let mut v = vec![index];
+        loop {
+            let next = match right {
+                false => forme.move_left(text, index),
+                true => forme.move_right(text, index),
+            };
+            if next == index {
+                return v;
+            }
+            assert!(v.len() <= text.len(), "cycle: {v:?}");
+            index = next;
+            v.push(index);
+        }
+    }
+
+    #[test]
+    fn lines() {
+        let text = "aa bb\ncc";
+        let forme = prepare(text, 25.0);
+        assert_eq!(forme.line_start(1), 0);
+        assert_eq!(forme.line_end(1), 2);
+        assert_eq!(forme.line_start(3), 3);
+        assert_eq!(forme.line_end(4), 5);
+        assert_eq!(forme.line_start(8), 6);
+        assert_eq!(forme.line_end(6), 8);
+
+        assert_eq!(walk(&forme, text, 0, true), [0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(walk(&forme, text, 8, false), [8, 7, 6, 5, 4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn graphemes() {
+        let text = "e\u{301}x";
+        let forme = prepare(text, 1000.0);
+        assert_eq!(walk(&forme, text, 0, true), [0, 3, 4]);
+    }
+
+    #[test]
+    fn bidi() {
+        // Logical order: "ab אבג cd"; visual order: "ab גבא cd"
+        let text = "ab אבג cd";
+        let forme = prepare(text, 1000.0);
+        let right = walk(&forme, text, 0, true);
+        assert_eq!(right.first(), Some(&0));
+        assert_eq!(right.last(), Some(&text.len()));
+        // Movement over the Hebrew word is in reverse logical order
+        let hebrew: Vec<usize> = right
+            .iter()
+            .cloned()
+            .filter(|i| (4..9).contains(i))
+            .collect();
+        assert!(hebrew.is_sorted_by(|a, b| a > b), "{right:?}");
+        // Every grapheme boundary is reachable
+        let mut sorted = right.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted.len(), text.chars().count() + 1);
+
+        let mut left = walk(&forme, text, text.len(), false);
+        left.reverse();
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn vertical() {
+        let text = "abcdef\nab\nabcdef";
+        let forme = prepare(text, 1000.0);
+        assert_eq!(forme.num_lines(), 3);
+
+        // The goal column is preserved over the short line
+        let (index, goal) = forme.move_vertical(5, 1, None);
+        assert_eq!(index, 9);
+        let (index, goal2) = forme.move_vertical(index, 1, Some(goal));
+        assert_eq!((index, goal2), (15, goal));
+        assert_eq!(forme.move_vertical(index, -1, None).0, 9);
+
+        // Clamping to the first and last lines, then to the ends of the text
+        assert_eq!(forme.move_vertical(15, -5, Some(goal)), (5, goal));
+        assert_eq!(forme.move_vertical(5, -1, Some(goal)), (0, goal));
+        assert_eq!(forme.move_vertical(5, 5, Some(goal)), (15, goal));
+        assert_eq!(forme.move_vertical(15, 1, Some(goal)).0, text.len());
+        assert_eq!(forme.move_vertical(15, 0, None).0, 15);
+    }
+
+    #[test]
+    fn page() {
+        let text = "abcdef\nab\nabcdef\nabc";
+        let forme = prepare(text, 1000.0);
+        let line = forme.get_line(0).unwrap();
+        let height = line.bottom() - line.top();
+
+        let (index, goal) = forme.move_page(5, 2.0 * height, None);
+        assert_eq!(index, 15);
+        assert_eq!(forme.move_page(index, -2.0 * height, Some(goal)).0, 5);
+        assert_eq!(forme.move_page(5, 100.0 * height, Some(goal)).0, text.len());
+        assert_eq!(
+            forme.move_page(text.len(), 100.0 * height, None).0,
+            text.len()
+        );
+        assert_eq!(forme.move_page(15, -100.0 * height, Some(goal)).0, 5);
+        assert_eq!(forme.move_page(5, -height, Some(goal)).0, 0);
+    }
+}
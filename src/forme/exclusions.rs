@@ -0,0 +1,193 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Text preparation: variable line widths
+
+use super::Forme;
+use super::wrap_lines::WrapOptions;
+use crate::{Align, Vec2};
+
+/// A set of rectangular exclusions
+///
+/// Text flows around exclusions (e.g. floated images); see
+/// [`Forme::prepare_lines_in`]. Coordinates are logical (see
+/// [`Forme::set_writing_mode`]): `Vec2(inline, block)`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Exclusions {
+    /// Width of the container
+    pub width: f32,
+    /// Excluded rectangles, each as `(min, max)` corners
+    pub rects: Vec<(Vec2, Vec2)>,
+}
+
+impl Exclusions {
+    /// Construct with no exclusions
+    #[inline]
+    pub fn new(width: f32) -> Self {
+        Exclusions {
+            width,
+            rects: vec![],
+        }
+    }
+
+    /// Add an excluded rectangle
+    #[inline]
+    pub fn with_rect(mut self, min: Vec2, max: Vec2) -> Self {
+        self.rects.push((min, max));
+        self
+    }
+
+    /// Get the widest interval of `0..self.width` which is not excluded
+    /// between `top` and `bottom`
+    pub fn line_bounds(&self, top: f32, bottom: f32) -> (f32, f32) {
+        let mut excluded: Vec<_> = self
+            .rects
+            .iter()
+            .filter(|(min, max)| min.1 < bottom && max.1 > top)
+            .map(|(min, max)| (min.0, max.0))
+            .collect();
+        excluded.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut best = (0.0, 0.0);
+        let mut start = 0.0f32;
+        for (x0, x1) in excluded
+            .into_iter()
+            .chain(std::iter::once((self.width, self.width)))
+        {
+            let end = x0.min(self.width);
+            if end - start > best.1 - best.0 {
+                best = (start, end);
+            }
+            start = start.max(x1);
+        }
+        best
+    }
+}
+
+impl Forme {
+    /// Flow text content into lines of variable width
+    ///
+    /// This is a variant of [`Self::prepare_lines`] where the interval
+    /// available to each line is supplied by `line_bounds(top, bottom)`,
+    /// given the block-axis extent of the line. The result should be an
+    /// interval `(start, end)` within `0..width` on the inline axis (the
+    /// leftmost point is `start` in all [writing modes](Self::set_writing_mode)
+    /// except that in vertical modes it is the top). Text is wrapped to and
+    /// aligned within this interval according to `h_align`.
+    /// See also [`Exclusions::line_bounds`].
+    ///
+    /// Since a line's height depends on its content, which depends on the
+    /// available width, `line_bounds` is first called with an estimate of the
+    /// height (from the font at the start of the line). If the line turns out
+    /// to be taller, `line_bounds` is called again with the new `bottom`; if
+    /// the interval is narrower, the line is wrapped again. Thus
+    /// `line_bounds` may be called multiple times for each line with
+    /// increasing values of `bottom`.
+    ///
+    /// Lines do not skip intervals which are too narrow for their content;
+    /// in this case content overflows the interval.
+    ///
+    /// Unlike [`Self::prepare_lines`], lines are never re-used from a previous
+    /// call.
+    ///
+    /// Returns the required height.
+    pub fn prepare_lines_in(
+        &mut self,
+        width: f32,
+        h_align: Align,
+        mut line_bounds: impl FnMut(f32, f32) -> (f32, f32),
+    ) -> f32 {
+        let options = WrapOptions {
+            bounds: Some(&mut line_bounds),
+            ..Default::default()
+        };
+        self.prepare_lines_impl(width, width, h_align, options)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Direction;
+    use crate::fonts::FontSelector;
+    use crate::forme::test_util::{glyph_pos, prepare, shape};
+
+    fn line_start_x(forme: &Forme, line: usize) -> f32 {
+        let index = forme.get_line(line).unwrap().text_range().start;
+        glyph_pos(forme, index).0
+    }
+
+    #[test]
+    fn line_bounds() {
+        let exclusions = Exclusions::new(100.0)
+            .with_rect(Vec2(0.0, 0.0), Vec2(30.0, 20.0))
+            .with_rect(Vec2(60.0, 10.0), Vec2(70.0, 30.0));
+        assert_eq!(exclusions.line_bounds(0.0, 5.0), (30.0, 100.0));
+        assert_eq!(exclusions.line_bounds(0.0, 15.0), (30.0, 60.0));
+        assert_eq!(exclusions.line_bounds(20.0, 30.0), (0.0, 60.0));
+        assert_eq!(exclusions.line_bounds(30.0, 40.0), (0.0, 100.0));
+    }
+
+    #[test]
+    fn float() {
+        let text = "one two three four five six seven eight nine ten";
+        let plain = prepare(text, 150.0);
+
+        // A "floated image" left of the first two lines
+        let line1 = plain.get_line(1).unwrap();
+        let exclusions =
+            Exclusions::new(150.0).with_rect(Vec2::ZERO, Vec2(50.0, line1.bottom() - 1.0));
+        let mut forme = Forme::default();
+        shape(&mut forme, text);
+        forme.prepare_lines_in(150.0, Align::Default, |top, bottom| {
+            exclusions.line_bounds(top, bottom)
+        });
+        assert!(forme.num_lines() > plain.num_lines());
+        assert_eq!(line_start_x(&forme, 0), 50.0);
+        assert_eq!(line_start_x(&forme, 1), 50.0);
+        assert_eq!(line_start_x(&forme, 2), 0.0);
+        for line in forme.lines() {
+            let x = if line.top() < line1.bottom() - 1.0 {
+                50.0
+            } else {
+                0.0
+            };
+            let (start, end) = (line.text_range().start, line.text_range().end);
+            for index in [start, end] {
+                let pos = glyph_pos(&forme, index);
+                assert!(x <= pos.0 && pos.0 <= 150.0);
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_height() {
+        // The first line is taller than estimated from its first font
+        let text = "aaa bbb BIG";
+        let mut forme = Forme::default();
+        let mut appender = forme.set_text(text, Direction::Auto);
+        assert!(
+            appender
+                .with_font(..8, FontSelector::default(), 16.0)
+                .unwrap()
+                .with_font(8.., FontSelector::default(), 40.0)
+                .is_ok()
+        );
+
+        let mut calls = vec![];
+        forme.prepare_lines_in(200.0, Align::Default, |top, bottom| {
+            calls.push((top, bottom));
+            match bottom > 40.0 {
+                false => (0.0, 200.0),
+                true => (0.0, 100.0),
+            }
+        });
+        // The first line is re-wrapped after discovering its height
+        assert!(calls.len() > 2);
+        assert!(calls[1].1 > calls[0].1);
+        assert_eq!(forme.get_line(0).unwrap().text_range(), 0..7);
+        assert!(forme.bounding_box().1.0 <= 100.0);
+    }
+}
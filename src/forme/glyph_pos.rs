@@ -5,13 +5,18 @@
 
 //! Methods using positioned glyphs
 
-use super::Forme;
+use super::{Forme, WritingMode};
 use crate::conv::to_usize;
 use crate::fonts::{self, FaceId, ScaledFaceRef};
+use crate::shaper::Orientation;
 use crate::{Glyph, Range, Vec2, shaper};
 use std::fmt::Debug;
 
 /// Used to return the position of a glyph with associated metrics
+///
+/// In vertical [writing modes](Forme::set_writing_mode), `pos` is on the
+/// central baseline and the marker is horizontal, extending from
+/// `pos.0 - ascent` to `pos.0 - descent`.
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
 pub struct MarkerPos {
     /// (x, y) coordinate of glyph
@@ -20,6 +25,7 @@ pub struct MarkerPos {
     pub ascent: f32,
     /// Descent (subtract from y to get bottom)
     pub descent: f32,
+    slant: f32,
     level: u8,
 }
 
@@ -34,6 +40,15 @@ impl MarkerPos {
         self.level
     }
 
+    /// Returns the slant of the font
+    ///
+    /// This is the horizontal offset per unit of height (positive where glyphs
+    /// lean to the right); see [`FaceStore::slant`](fonts::FaceStore::slant).
+    #[inline]
+    pub fn slant(&self) -> f32 {
+        self.slant
+    }
+
     /// Returns true if the cursor is left-to-right
     #[inline]
     pub fn is_ltr(&self) -> bool {
@@ -106,14 +121,63 @@ impl ExactSizeIterator for MarkerPosIter {}
 /// Yielded by [`Forme::runs`].
 pub struct GlyphRun<'a, E> {
     run: &'a shaper::GlyphRun,
+    extra: &'a shaper::RunExtra,
     range: Range,
+    /// Logical offset of the run part
     offset: Vec2,
+    /// Physical offset applied after mapping
+    origin: Vec2,
     top: f32,
     bottom: f32,
+    mode: WritingMode,
+    block_size: f32,
+    /// Offset of the baseline of rotated glyphs from the central baseline
+    rotated_shift: f32,
     effects: &'a [(u32, E)],
 }
 
 impl<'a, E: Copy + Default> GlyphRun<'a, E> {
+    /// Map a logical position (including `self.offset`) to physical
+    ///
+    /// For rotated glyphs, `pos.1 - self.offset.1` is a cross-axis offset.
+    fn to_physical(&self, pos: Vec2) -> Vec2 {
+        if !self.mode.is_vertical() {
+            return pos + self.origin;
+        }
+
+        let baseline = self.offset.1;
+        let mut p = self
+            .mode
+            .to_physical(Vec2(pos.0, baseline), self.block_size);
+        if self.extra.orientation == Orientation::Rotated {
+            p.0 -= self.rotated_shift + (pos.1 - baseline);
+        }
+        p + self.origin
+    }
+
+    /// Map glyph `glyph` with index `gi` to a physical position
+    fn physical_glyph(&self, gi: usize, glyph: &Glyph) -> Glyph {
+        let mut position = self.to_physical(glyph.position + self.offset);
+        if let Some(offset) = self.extra.v_offsets.get(gi) {
+            position += *offset;
+        }
+        Glyph {
+            index: glyph.index,
+            id: glyph.id,
+            position,
+        }
+    }
+
+    /// True if glyphs must be drawn rotated
+    ///
+    /// In vertical [writing modes](Forme::set_writing_mode), some glyphs are
+    /// rotated 90° clockwise. These must be drawn rotated about their
+    /// position (origin).
+    #[inline]
+    pub fn is_rotated(&self) -> bool {
+        self.extra.orientation == Orientation::Rotated
+    }
+
     /// Get the [`FaceId`] for this run
     #[inline]
     pub fn face_id(&self) -> FaceId {
@@ -144,9 +208,12 @@ impl<'a, E: Copy + Default> GlyphRun<'a, E> {
     /// have the same [`ScaledFaceRef::ascent`] value when multiple fonts are
     /// used, thus it is usually preferable to use the this value for
     /// background colors (highlighting).
+    ///
+    /// In vertical [writing modes](Forme::set_writing_mode), this is the
+    /// left edge of the line.
     #[inline]
     pub fn line_top(&self) -> f32 {
-        self.top
+        self.line_edges().0
     }
 
     /// Get the `bottom` position of the line
@@ -155,9 +222,23 @@ impl<'a, E: Copy + Default> GlyphRun<'a, E> {
     /// have the same [`ScaledFaceRef::descent`] value when multiple fonts are
     /// used, thus it is usually preferable to use the this value for
     /// background colors (highlighting).
+    ///
+    /// In vertical [writing modes](Forme::set_writing_mode), this is the
+    /// right edge of the line.
     #[inline]
     pub fn line_bottom(&self) -> f32 {
-        self.bottom
+        self.line_edges().1
+    }
+
+    fn line_edges(&self) -> (f32, f32) {
+        match self.mode {
+            WritingMode::HorizontalTb => (self.origin.1 + self.top, self.origin.1 + self.bottom),
+            WritingMode::VerticalRl => (
+                self.origin.0 + self.block_size - self.bottom,
+                self.origin.0 + self.block_size - self.top,
+            ),
+            WritingMode::VerticalLr => (self.origin.0 + self.top, self.origin.0 + self.bottom),
+        }
     }
 
     /// Get an iterator over glyphs for this run
@@ -167,13 +248,11 @@ impl<'a, E: Copy + Default> GlyphRun<'a, E> {
     /// This method ignores effects; if you want those call
     /// [`Self::glyphs_with_effects`] instead.
     pub fn glyphs(&self) -> impl Iterator<Item = Glyph> + '_ {
+        let start = self.range.start();
         self.run.glyphs[self.range.to_std()]
             .iter()
-            .map(|glyph| Glyph {
-                index: glyph.index,
-                id: glyph.id,
-                position: glyph.position + self.offset,
-            })
+            .enumerate()
+            .map(move |(i, glyph)| self.physical_glyph(start + i, glyph))
     }
 
     /// Yield glyphs and effects for this run
@@ -188,6 +267,10 @@ impl<'a, E: Copy + Default> GlyphRun<'a, E> {
     /// information from [`Self::scaled_face`] to draw underline, strike-through
     /// and background effects.
     ///
+    /// In vertical [writing modes](Forme::set_writing_mode), `p.0` is the
+    /// horizontal position of the baseline while `p.1` and `x2` are the
+    /// y-axis positions of the top and bottom edges of the sub-range.
+    ///
     /// Note: this is more computationally expensive than [`GlyphRun::glyphs`],
     /// so prefer the former method when callback `g` is not required.
     pub fn glyphs_with_effects<F, G>(&self, mut f: F, mut g: G)
@@ -196,6 +279,14 @@ impl<'a, E: Copy + Default> GlyphRun<'a, E> {
         G: FnMut(Vec2, f32, E),
     {
         let ltr = self.run.level.is_ltr();
+        let mut g = |p: Vec2, x2: f32, effect: E| {
+            let p1 = self.to_physical(p);
+            let x2 = match self.mode.is_vertical() {
+                false => x2 + self.origin.0,
+                true => self.to_physical(Vec2(x2, p.1)).1,
+            };
+            g(p1, x2, effect)
+        };
 
         let mut effect = E::default();
         let mut effect_next = 0;
@@ -228,7 +319,13 @@ impl<'a, E: Copy + Default> GlyphRun<'a, E> {
         let mut b = a;
 
         // Iterate over glyphs in logical order.
-        for mut glyph in self.run.glyphs[self.range.to_std()].iter().cloned() {
+        let start = self.range.start();
+        for (i, mut glyph) in self.run.glyphs[self.range.to_std()]
+            .iter()
+            .cloned()
+            .enumerate()
+        {
+            let physical = self.physical_glyph(start + i, &glyph);
             glyph.position += self.offset;
 
             // Does the effect change?
@@ -254,7 +351,7 @@ impl<'a, E: Copy + Default> GlyphRun<'a, E> {
                 y = glyph.position.1;
             }
 
-            f(glyph, effect);
+            f(physical, effect);
             b = glyph.position.0;
         }
 
@@ -292,6 +389,7 @@ impl Forme {
     ///     of another; these positions may be the same or may not be (over
     ///     line breaks and with bidirectional text). If only a single position
     ///     is desired, usually the latter is preferred (via `next_back()`).
+    ///     See also [`Self::caret_pos`] which selects a position by affinity.
     ///
     /// The result is not guaranteed to be within [`Self::bounding_box`].
     /// Depending on the use-case, the caller may need to clamp the resulting
@@ -299,26 +397,37 @@ impl Forme {
     pub fn text_glyph_pos(&self, index: usize) -> MarkerPosIter {
         let mut v: [MarkerPos; 2] = Default::default();
         let (a, mut b) = (0, 0);
-        let mut push_result = |pos, ascent, descent, level| {
-            v[b] = MarkerPos {
+        let mut push_result = |pos: Vec2, baseline: f32, sf: ScaledFaceRef, slant, level| {
+            let (mut ascent, mut descent) = (sf.ascent(), sf.descent());
+            let pos = if self.config.writing_mode.is_vertical() {
+                let half = 0.5 * (ascent - descent);
+                (ascent, descent) = (half, -half);
+                self.to_physical(Vec2(pos.0, baseline))
+            } else {
+                pos
+            };
+            // Keep the first and last matches
+            let i = b.min(1);
+            v[i] = MarkerPos {
                 pos,
                 ascent,
                 descent,
+                slant,
                 level,
             };
-            b = 1;
+            b = i + 1;
         };
 
         // We don't care too much about performance: use a naive search strategy
         'a: for run_part in &self.wrapped_runs {
-            if index > to_usize(run_part.text_end) {
+            if index > to_usize(run_part.text_end) || run_part.is_synthetic() {
                 continue;
             }
 
             let glyph_run = &self.runs[to_usize(run_part.glyph_run)];
-            let sf = fonts::library()
-                .get_face(glyph_run.face_id)
-                .scale_by_dpu(glyph_run.dpu);
+            let store = fonts::library().get_face_store(glyph_run.face_id);
+            let sf = store.face_ref().scale_by_dpu(glyph_run.dpu);
+            let slant = store.slant();
 
             // If index is at the end of a run, we potentially get two matches.
             if index == to_usize(run_part.text_end) {
@@ -333,7 +442,7 @@ impl Forme {
                 };
 
                 let pos = run_part.offset + pos;
-                push_result(pos, sf.ascent(), sf.descent(), glyph_run.level.number());
+                push_result(pos, run_part.offset.1, sf, slant, glyph_run.level.number());
                 continue;
             }
 
@@ -363,7 +472,7 @@ impl Forme {
             };
 
             let pos = run_part.offset + pos;
-            push_result(pos, sf.ascent(), sf.descent(), glyph_run.level.number());
+            push_result(pos, run_part.offset.1, sf, slant, glyph_run.level.number());
             break;
         }
 
@@ -386,7 +495,8 @@ impl Forme {
     /// where `i` is the largest value such that `effects[i].0 <= j`, or the
     /// default value of `E` if no such `i` exists.
     ///
-    /// Runs are yielded in undefined order.
+    /// Runs are yielded in undefined order. Inline objects are not included;
+    /// see [`Self::objects`].
     pub fn runs<'a, E: Copy + Debug + Default>(
         &'a self,
         offset: Vec2,
@@ -411,20 +521,61 @@ impl Forme {
         let mut line = line_iter.next().unwrap();
         self.wrapped_runs
             .iter()
-            .filter(|part| !part.glyph_range.is_empty())
+            .filter(|part| !part.glyph_range.is_empty() && self.part_extra(part).object.is_none())
             .map(move |part| {
                 while part.text_end > line.text_range.end {
                     line = line_iter.next().unwrap();
                 }
 
+                let run = self.part_run(part);
+                let extra = self.part_extra(part);
+                let mut rotated_shift = 0.0;
+                if extra.orientation == Orientation::Rotated {
+                    let sf = fonts::library().get_face(run.face_id).scale_by_dpu(run.dpu);
+                    rotated_shift = 0.5 * (sf.ascent() + sf.descent());
+                }
+
                 GlyphRun {
-                    run: &self.runs[to_usize(part.glyph_run)],
+                    run,
+                    extra,
                     range: part.glyph_range,
-                    offset: offset + part.offset,
-                    top: offset.1 + line.top,
-                    bottom: offset.1 + line.bottom,
+                    offset: part.offset,
+                    origin: offset,
+                    top: line.top,
+                    bottom: line.bottom,
+                    mode: self.config.writing_mode,
+                    block_size: self.block_size,
+                    rotated_shift,
                     effects,
                 }
             })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::forme::test_util::shape_with;
+    use crate::{Align, Forme, InlineObject};
+
+    #[test]
+    fn wrapped_positions() {
+        let object = InlineObject::new(10.0, 10.0, 0.0);
+        for text in ["abc אבג def גדה ghi x", "abc \u{FFFC} x"] {
+            for width in [20.0, 30.0, 40.0] {
+                let mut forme = Forme::default();
+                shape_with(&mut forme, text, |appender| {
+                    if let Some(index) = text.find('\u{FFFC}') {
+                        appender.with_object(index, object);
+                    }
+                });
+                forme.prepare_lines(width, width, Align::Default);
+                assert!(forme.num_lines() > 1);
+
+                for index in text.char_indices().map(|(i, _)| i).chain([text.len()]) {
+                    let len = forme.text_glyph_pos(index).len();
+                    assert!((1..=2).contains(&len), "{text:?} at {width}: index {index}");
+                }
+            }
+        }
+    }
+}
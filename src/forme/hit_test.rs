@@ -0,0 +1,198 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Text navigation: hit-testing with caret affinity
+
+use super::{Forme, Line, MarkerPos};
+use crate::Vec2;
+use crate::conv::to_usize;
+
+/// Caret affinity
+///
+/// A text index may correspond to two caret positions: at a line wrap (the
+/// end of one line and the start of the next) or at a boundary of
+/// bidirectional text (the end of one run and the start of another). The
+/// affinity selects between these.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Affinity {
+    /// The caret is associated with the following text
+    ///
+    /// At a line wrap, the caret is at the start of the next line.
+    #[default]
+    Downstream,
+    /// The caret is associated with the preceding text
+    ///
+    /// At a line wrap, the caret is at the end of the previous line.
+    Upstream,
+}
+
+/// Result of [`Forme::hit_test`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HitTest {
+    /// The text index of the nearest caret position
+    pub index: usize,
+    /// The affinity of the caret position
+    ///
+    /// Use with [`Forme::caret_pos`] to find the caret position.
+    pub affinity: Affinity,
+    /// True if the tested position is inside a glyph
+    ///
+    /// This is true when the position is within the vertical bounds of a line
+    /// and the horizontal extent of a glyph (including white-space and inline
+    /// objects) on that line. It is false when the position is before, after,
+    /// above or below text, or over space inserted by a tab.
+    pub inside: bool,
+}
+
+impl Forme {
+    /// Find the caret position nearest `pos`
+    ///
+    /// Expects state [`Status::Ready`](crate::Status::Ready).
+    ///
+    /// This is similar to [`Self::text_index_nearest`], but additionally
+    /// reports caret affinity, such that (using [`Self::caret_pos`]) a caret
+    /// at a line wrap or a boundary of bidirectional text may be placed where
+    /// the user clicked.
+    pub fn hit_test(&self, pos: Vec2) -> HitTest {
+        let pos = self.config.writing_mode.to_logical(pos, self.block_size);
+        let mut n = 0;
+        for (i, line) in self.lines.iter().enumerate() {
+            if line.top > pos.1 {
+                break;
+            }
+            n = i;
+        }
+        let Some(line) = self.lines.get(n) else {
+            return HitTest::default();
+        };
+
+        let mut result = HitTest {
+            index: line.text_range().start,
+            ..Default::default()
+        };
+        let mut best_dist = f32::INFINITY;
+        self.visit_carets(line, |x, index, affinity| {
+            let dist = (x - pos.0).abs();
+            if dist < best_dist {
+                result.index = to_usize(index);
+                result.affinity = affinity;
+                best_dist = dist;
+            }
+        });
+
+        result.inside = (line.top..line.bottom).contains(&pos.1) && self.over_glyph(line, pos.0);
+        result
+    }
+
+    /// True if logical position `x` is within the extent of a glyph on `line`
+    fn over_glyph(&self, line: &Line, x: f32) -> bool {
+        self.wrapped_runs[line.run_range.to_std()]
+            .iter()
+            .filter(|part| !part.is_synthetic())
+            .any(|part| {
+                let glyph_run = &self.runs[to_usize(part.glyph_run)];
+                let x = x - part.offset.0;
+                part.glyph_range.to_std().any(|gi| {
+                    let (start, end) = glyph_run.glyph_span(gi);
+                    (start..end).contains(&x)
+                })
+            })
+    }
+
+    /// Find the position of the caret at `index` with the given `affinity`
+    ///
+    /// Expects state [`Status::Ready`](crate::Status::Ready).
+    ///
+    /// Where [`Self::text_glyph_pos`] yields two positions, this selects the
+    /// first (the end of a line or run) for [`Affinity::Upstream`] and the
+    /// last for [`Affinity::Downstream`]. Otherwise the affinity is ignored.
+    /// Returns `None` when `index` is not at a position in the text.
+    pub fn caret_pos(&self, index: usize, affinity: Affinity) -> Option<MarkerPos> {
+        let mut iter = self.text_glyph_pos(index);
+        match affinity {
+            Affinity::Upstream => iter.next(),
+            Affinity::Downstream => iter.next_back(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Align;
+    use crate::forme::test_util::{glyph_pos, prepare, shape};
+
+    #[test]
+    fn wrap_point() {
+        // Wrapping within the word gives lines "aa", "aa", ...
+        let text = "aaaaaaaa";
+        let mut forme = Forme::default();
+        forme.set_overflow_wrap(crate::OverflowWrap::Anywhere);
+        shape(&mut forme, text);
+        forme.prepare_lines(25.0, 25.0, Align::Default);
+        let (line0, line1) = (forme.get_line(0).unwrap(), forme.get_line(1).unwrap());
+        assert_eq!((line0.text_range(), line1.text_range()), (0..2, 2..4));
+
+        // Beyond the end of the first line
+        let hit = forme.hit_test(Vec2(1000.0, line0.top() + 1.0));
+        assert_eq!((hit.index, hit.affinity), (2, Affinity::Upstream));
+        assert!(!hit.inside);
+        let up = forme.caret_pos(2, Affinity::Upstream).unwrap();
+        assert!(up.pos.0 > 0.0);
+        assert!(up.pos.1 < line0.bottom());
+
+        // The start of the second line
+        let hit = forme.hit_test(Vec2(1.0, line1.top() + 1.0));
+        assert_eq!((hit.index, hit.affinity), (2, Affinity::Downstream));
+        assert!(hit.inside);
+        let down = forme.caret_pos(2, Affinity::Downstream).unwrap();
+        assert_eq!(down.pos.0, 0.0);
+        assert!(down.pos.1 > line1.top());
+
+        // Elsewhere, affinity is ignored
+        let hit = forme.hit_test(Vec2(12.0, line1.top() + 1.0));
+        assert_eq!(hit.index, 3);
+        assert_eq!(
+            forme.caret_pos(3, Affinity::Upstream),
+            forme.caret_pos(3, Affinity::Downstream)
+        );
+        let last = forme.get_line(forme.num_lines() - 1).unwrap();
+        assert!(!forme.hit_test(Vec2(1.0, last.bottom() + 1.0)).inside);
+    }
+
+    #[test]
+    fn inside() {
+        let text = "a\tb";
+        let forme = prepare(text, 1000.0);
+        let y = forme.get_line(0).unwrap().top() + 1.0;
+        let (a, tab, b) = (
+            glyph_pos(&forme, 0).0,
+            glyph_pos(&forme, 1).0,
+            glyph_pos(&forme, 2).0,
+        );
+        assert!(forme.hit_test(Vec2(0.5 * (a + tab), y)).inside);
+        assert!(!forme.hit_test(Vec2(b - 0.5, y)).inside);
+        assert!(forme.hit_test(Vec2(b + 0.5, y)).inside);
+    }
+
+    #[test]
+    fn bidi() {
+        // Visual order: "ab גבא cd"
+        let text = "ab אבג cd";
+        let forme = prepare(text, 1000.0);
+        let up = forme.caret_pos(3, Affinity::Upstream).unwrap();
+        let down = forme.caret_pos(3, Affinity::Downstream).unwrap();
+        assert!(up.is_ltr() && down.is_rtl());
+        assert!(up.pos.0 < down.pos.0);
+
+        // Each position is found by hit-testing
+        let y = forme.get_line(0).unwrap().top() + 1.0;
+        let hit = forme.hit_test(Vec2(up.pos.0 - 0.5, y));
+        assert_eq!((hit.index, hit.affinity), (3, Affinity::Upstream));
+        let hit = forme.hit_test(Vec2(down.pos.0 - 0.5, y));
+        assert_eq!((hit.index, hit.affinity), (3, Affinity::Downstream));
+    }
+}
@@ -0,0 +1,405 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Text preparation: justification
+
+use super::RunSpecial;
+use super::wrap_lines::PartInfo;
+use crate::Align;
+#[allow(unused)]
+use crate::Forme;
+use crate::conv::{to_u32, to_usize};
+use crate::fonts::FontLibrary;
+use crate::shaper::{GlyphRun, RunExtras};
+
+/// Where to add space when justifying text
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum JustifyMode {
+    /// Stretch inter-word gaps
+    ///
+    /// Space is added at each soft-break opportunity within the line. In
+    /// scripts which do not separate words with spaces (e.g. Chinese) this
+    /// usually means between each character.
+    #[default]
+    InterWord,
+    /// Stretch the gaps between all grapheme clusters
+    ///
+    /// This is often preferred for CJK text.
+    InterCharacter,
+    /// Stretch inter-word gaps, then inter-character gaps
+    ///
+    /// Inter-word gaps are stretched up to [`Justify::max_word_stretch`];
+    /// any remaining space is distributed between grapheme clusters.
+    Auto,
+}
+
+/// Justification options
+///
+/// These options control how lines are stretched when using
+/// [`Align::Stretch`]. The default options stretch inter-word gaps without
+/// limit and do not stretch the last line of each paragraph.
+///
+/// See [`Forme::set_justify`] and [`Text::set_justify`].
+///
+/// [`Text::set_justify`]: crate::Text::set_justify
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Justify {
+    /// Where to add space
+    pub mode: JustifyMode,
+    /// Maximum space added to each inter-word gap
+    ///
+    /// Units are Em (relative to the smallest font size on the line). Lines
+    /// which cannot be fully stretched within this limit are aligned to the
+    /// start, unless [`JustifyMode::Auto`] is used. Default: infinity.
+    pub max_word_stretch: f32,
+    /// Enable kashida elongation
+    ///
+    /// If true, Arabic text is also stretched by inserting tatweel (U+0640)
+    /// glyphs between joined letters, where the font provides this glyph.
+    /// Each kashida opportunity receives the same space as an inter-word gap.
+    pub kashida: bool,
+    /// Alignment of the last line of each paragraph
+    ///
+    /// This applies to lines which are not wrapped: those ending in a
+    /// mandatory line break or the end of the text. Use [`Align::Stretch`] to
+    /// justify these lines too. Default: [`Align::Default`].
+    pub last_line: Align,
+}
+
+impl Default for Justify {
+    fn default() -> Self {
+        Justify {
+            mode: JustifyMode::InterWord,
+            max_word_stretch: f32::INFINITY,
+            kashida: false,
+            last_line: Align::Default,
+        }
+    }
+}
+
+impl Justify {
+    /// Distribute `spare` space over `parts` of a line
+    ///
+    /// Parts are in logical order and must have `end_space` set. Parts may be
+    /// split (at cluster boundaries and kashida opportunities). Sets
+    /// [`PartInfo::extra`] and [`PartInfo::kashida`]; returns the total space
+    /// added.
+    pub(super) fn apply(
+        &self,
+        fonts: &FontLibrary,
+        runs: &[GlyphRun],
+        extras: &RunExtras,
+        parts: &mut Vec<PartInfo>,
+        spare: f32,
+    ) -> f32 {
+        let len = parts.len();
+        let mut dpem = f32::INFINITY;
+        let mut word_gaps = 0;
+        let mut kashida_gaps = 0;
+        for (i, part) in parts.iter().enumerate() {
+            let run = &runs[to_usize(part.run)];
+            dpem = dpem.min(run.dpem);
+            if self.mode != JustifyMode::InterCharacter && is_word_gap(run, part, i + 1 < len) {
+                word_gaps += 1;
+            }
+            if self.kashida {
+                kashida_gaps += kashida_range(extras, part).len();
+            }
+        }
+
+        let mut per_word = 0.0;
+        let num = word_gaps + kashida_gaps;
+        if num > 0 {
+            per_word = (spare / (num as f32)).min(self.max_word_stretch * dpem);
+        }
+        let mut remainder = spare - per_word * (num as f32);
+        let split_clusters = match self.mode {
+            JustifyMode::InterWord => false,
+            JustifyMode::InterCharacter => true,
+            JustifyMode::Auto => remainder > 0.0,
+        };
+
+        if !split_clusters && kashida_gaps == 0 {
+            for (i, part) in parts.iter_mut().enumerate() {
+                let run = &runs[to_usize(part.run)];
+                if per_word > 0.0 && is_word_gap(run, part, i + 1 < len) {
+                    part.extra += per_word;
+                }
+            }
+            return spare - remainder;
+        }
+
+        let mut output = Vec::with_capacity(2 * len);
+        let mut splits = Vec::new();
+        for (i, part) in parts.iter().enumerate() {
+            let run = &runs[to_usize(part.run)];
+            let word_gap =
+                self.mode != JustifyMode::InterCharacter && is_word_gap(run, part, i + 1 < len);
+
+            splits.clear();
+            if split_clusters {
+                let face = fonts.get_face(run.face_id);
+                for gi in (part.glyph_range.start() + 1)..part.glyph_range.end() {
+                    let glyph = &run.glyphs[gi];
+                    if glyph.index != run.glyphs[gi - 1].index && !face.is_mark(glyph.id) {
+                        splits.push(to_u32(gi));
+                    }
+                }
+            }
+            let kashida = if self.kashida && per_word > 0.0 {
+                kashida_range(extras, part)
+            } else {
+                &[]
+            };
+            if !kashida.is_empty() {
+                splits.extend_from_slice(kashida);
+                splits.sort_unstable();
+                splits.dedup();
+            }
+
+            let first = output.len();
+            split_part(run, part, &splits, &mut output);
+            for sub in &mut output[first..] {
+                if kashida.binary_search(&sub.glyph_range.end).is_ok() {
+                    sub.extra = per_word;
+                    sub.kashida = true;
+                }
+            }
+            if let Some(sub) = output.last_mut() {
+                sub.extra = part.extra;
+                if word_gap {
+                    sub.extra += per_word;
+                }
+            }
+        }
+
+        // Splitting may move the end of content to a sub-part
+        let mut is_tail = true;
+        for part in output.iter_mut().rev() {
+            if !part.end_space {
+                break;
+            }
+            part.end_space = is_tail;
+            if part.len_no_space > 0.0 {
+                is_tail = false;
+            }
+        }
+
+        if split_clusters {
+            let num = output
+                .iter()
+                .filter(|part| !part.end_space && !part.glyph_range.is_empty())
+                .count();
+            if num > 0 {
+                let per_char = remainder / (num as f32);
+                for part in &mut output {
+                    if !part.end_space && !part.glyph_range.is_empty() {
+                        part.extra += per_char;
+                    }
+                }
+                remainder = 0.0;
+            }
+        }
+
+        *parts = output;
+        spare - remainder
+    }
+}
+
+/// True if an inter-word gap follows `part`
+fn is_word_gap(run: &GlyphRun, part: &PartInfo, not_last: bool) -> bool {
+    let not_at_end = part.glyph_range.end() < run.glyphs.len();
+    not_last && !part.end_space && (not_at_end || run.special != RunSpecial::NoBreak)
+}
+
+/// Kashida opportunities strictly within `part`
+fn kashida_range<'a>(extras: &'a RunExtras, part: &PartInfo) -> &'a [u32] {
+    let kashida = &extras.get(to_usize(part.run)).kashida;
+    let range = part.glyph_range;
+    let start = kashida.partition_point(|gi| *gi <= range.start);
+    let end = kashida.partition_point(|gi| *gi < range.end);
+    &kashida[start..end.max(start)]
+}
+
+/// Split `part` before each glyph index in `splits`
+///
+/// Split indices must be strictly increasing and within the glyph range of
+/// `part`. Sub-parts are appended to `output` in logical order.
+fn split_part(run: &GlyphRun, part: &PartInfo, splits: &[u32], output: &mut Vec<PartInfo>) {
+    if splits.is_empty() {
+        output.push(part.clone());
+        return;
+    }
+
+    let range = part.glyph_range;
+    let part_end = part.offset + part.len;
+    let mut start = range.start;
+    for end in splits.iter().cloned().chain(std::iter::once(range.end)) {
+        debug_assert!(start < end);
+        let (x0, x1, len_no_space);
+        if run.level.is_ltr() {
+            x0 = if start == range.start {
+                part.offset
+            } else {
+                run.glyphs[to_usize(start)].position.0
+            };
+            x1 = if end == range.end {
+                part_end
+            } else {
+                run.glyphs[to_usize(end)].position.0
+            };
+            len_no_space = part.offset + part.len_no_space - x0;
+        } else {
+            x1 = if start == range.start {
+                part_end
+            } else {
+                run.glyphs[to_usize(start) - 1].position.0
+            };
+            x0 = if end == range.end {
+                part.offset
+            } else {
+                run.glyphs[to_usize(end) - 1].position.0
+            };
+            len_no_space = x1 - (part_end - part.len_no_space);
+        }
+
+        let len = x1 - x0;
+        output.push(PartInfo {
+            run: part.run,
+            offset: x0,
+            len,
+            len_no_space: len_no_space.clamp(0.0, len),
+            glyph_range: (start..end).into(),
+            end_space: part.end_space,
+            extra: 0.0,
+            kashida: false,
+            hyphen: if end == range.end { part.hyphen } else { 0.0 },
+            ellipsis: match part.ellipsis_start {
+                false if end == range.end => part.ellipsis,
+                true if start == range.start => part.ellipsis,
+                _ => 0.0,
+            },
+            ellipsis_start: part.ellipsis_start,
+        });
+        start = end;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::forme::test_util::{glyph_pos, prepare, shaped};
+    use crate::{Vec2, fonts};
+
+    const SAMPLE: &str = "Some words to justify within a narrow box";
+
+    #[test]
+    fn inter_word() {
+        let width = 120.0;
+        let mut forme = shaped(SAMPLE, |forme| forme.set_justify(Justify::default()));
+        forme.prepare_lines(width, width, Align::Stretch);
+        assert!(forme.num_lines() > 2);
+
+        let line = forme.get_line(0).unwrap().text_range();
+        let content_end = SAMPLE[line.clone()].trim_end().len();
+        assert!((glyph_pos(&forme, content_end).0 - width).abs() < 0.01);
+
+        let line = forme.get_line(forme.num_lines() - 1).unwrap().text_range();
+        assert!(glyph_pos(&forme, line.end).0 < width - 1.0);
+    }
+
+    #[test]
+    fn max_word_stretch() {
+        let width = 150.0;
+        let justify = Justify {
+            max_word_stretch: 0.0,
+            ..Default::default()
+        };
+        let mut forme = shaped(SAMPLE, |forme| forme.set_justify(justify));
+        forme.prepare_lines(width, width, Align::Stretch);
+        let line = forme.get_line(0).unwrap().text_range();
+        let content_end = SAMPLE[line.clone()].trim_end().len();
+        assert_eq!(glyph_pos(&forme, 0).0, 0.0);
+        assert!(glyph_pos(&forme, content_end).0 < width - 1.0);
+
+        let justify = Justify {
+            mode: JustifyMode::Auto,
+            max_word_stretch: 0.1,
+            ..justify
+        };
+        let mut forme = shaped(SAMPLE, |forme| forme.set_justify(justify));
+        forme.prepare_lines(width, width, Align::Stretch);
+        assert!((glyph_pos(&forme, content_end).0 - width).abs() < 0.01);
+    }
+
+    #[test]
+    fn inter_character() {
+        let width = 120.0;
+        let justify = Justify {
+            mode: JustifyMode::InterCharacter,
+            last_line: Align::Stretch,
+            ..Default::default()
+        };
+        let mut forme = shaped(SAMPLE, |forme| forme.set_justify(justify));
+        forme.prepare_lines(width, width, Align::Stretch);
+
+        for line in forme.lines() {
+            let range = line.text_range();
+            let content_end = SAMPLE[..range.end].trim_end().len();
+            assert!((glyph_pos(&forme, content_end).0 - width).abs() < 0.01);
+
+            // Gaps between characters are equal (for equal character widths)
+            let x0 = glyph_pos(&forme, range.start + 1).0;
+            let x1 = glyph_pos(&forme, range.start + 2).0;
+            let plain = prepare(SAMPLE, 1000.0);
+            let d = (x1 - x0)
+                - (glyph_pos(&plain, range.start + 2).0 - glyph_pos(&plain, range.start + 1).0);
+            assert!(d > 0.0);
+
+            // Caret positions map back to text indices
+            for index in range.start..content_end {
+                let pos = Vec2(glyph_pos(&forme, index).0, line.top());
+                assert_eq!(forme.text_index_nearest(pos), index);
+            }
+        }
+    }
+
+    #[test]
+    fn kashida() {
+        let text = "بسم الله الرحمن الرحيم بسم الله الرحمن الرحيم";
+        let width = 200.0;
+        let justify = Justify {
+            kashida: true,
+            ..Default::default()
+        };
+
+        let count_tatweel = |forme: &Forme| {
+            let mut n = 0;
+            for run in forme.runs::<()>(Vec2::ZERO, &[]) {
+                let tatweel = fonts::library()
+                    .get_face(run.face_id())
+                    .glyph_index('\u{0640}');
+                n += run.glyphs().filter(|g| g.id == tatweel).count();
+            }
+            n
+        };
+
+        let mut forme = shaped(text, |_| ());
+        forme.prepare_lines(width, width, Align::Stretch);
+        assert!(forme.num_lines() > 1);
+        assert_eq!(count_tatweel(&forme), 0);
+
+        let mut forme = shaped(text, |forme| forme.set_justify(justify));
+        forme.prepare_lines(width, width, Align::Stretch);
+        assert!(count_tatweel(&forme) > 0);
+        for index in 0..text.len() {
+            if text.is_char_boundary(index) {
+                assert!(forme.text_glyph_pos(index).len() > 0);
+            }
+        }
+    }
+}
@@ -8,18 +8,50 @@
 #[allow(unused)]
 use crate::Status;
 use crate::conv::to_usize;
-use crate::{Vec2, shaper};
+use crate::{Align, Vec2, shaper};
 use smallvec::SmallVec;
 use tinyvec::TinyVec;
 
+mod caret;
+mod cursor;
+mod exclusions;
 mod glyph_pos;
+mod hit_test;
+mod justify;
+mod objects;
+mod overflow;
+mod pages;
+mod paragraph;
+mod selection;
+mod spacing;
+mod strategy;
+mod tabs;
 mod text_runs;
+mod truncate;
+mod white_space;
 mod wrap_lines;
+mod writing_mode;
+pub use caret::{Caret, CaretHeight, CaretShape};
+pub use exclusions::Exclusions;
 pub use glyph_pos::{GlyphRun, MarkerPos, MarkerPosIter};
+pub use hit_test::{Affinity, HitTest};
+pub use justify::{Justify, JustifyMode};
+pub use objects::{InlineObject, ObjectPos};
+pub use overflow::OverflowWrap;
+pub use pages::Fragmentation;
+use paragraph::LineFormat;
+pub use paragraph::ParagraphFormat;
+pub use spacing::{LineHeight, LineSpacing};
+pub use strategy::WrapStrategy;
+pub use tabs::{TabAlign, TabInterval, TabStop, TabStops};
 pub use text_runs::Appender;
 pub(crate) use text_runs::RunSpecial;
+pub use truncate::{Truncate, TruncatePosition};
+pub(crate) use white_space::Collapse;
+pub use white_space::WhiteSpace;
 pub use wrap_lines::Line;
-use wrap_lines::RunPart;
+use wrap_lines::{LineState, RunPart};
+pub use writing_mode::WritingMode;
 
 /// Error returned on operations if not ready
 ///
@@ -70,19 +102,17 @@ pub struct NotReady;
 /// direction in bi-directional text).
 ///
 /// Navigating to the start or end of a line can be done with
-/// [`Forme::find_line`], [`Forme::get_line`] and [`Line::text_range`].
+/// [`Forme::line_start`] and [`Forme::line_end`].
 ///
-/// Navigating forwards or backwards should be done via a library such as
-/// [`unicode-segmentation`](https://github.com/unicode-rs/unicode-segmentation)
-/// which provides a
-/// [`GraphemeCursor`](https://unicode-rs.github.io/unicode-segmentation/unicode_segmentation/struct.GraphemeCursor.html)
-/// to step back or forward one "grapheme", in logical text order.
-/// The direction of navigation may be reversed for [right-to-left text](Self::text_is_rtl)
-/// (i.e. reversed logical-order navigation).
+/// Navigating forwards or backwards in logical text order (by grapheme, word
+/// or paragraph) may be done with the functions of the [`segment`](crate::segment)
+/// module. Navigation in visual order (e.g. for left and right arrow keys in
+/// bidirectional text) may be done with [`Forme::move_left`] and
+/// [`Forme::move_right`].
 ///
-/// To navigate "up" and "down" lines, use [`Forme::text_glyph_pos`] to
-/// get the position of the cursor, [`Forme::find_line`] to get the line
-/// number, then [`Forme::line_index_nearest`] to find the new index.
+/// To navigate "up" and "down" lines, use [`Forme::move_vertical`] (or
+/// [`Forme::move_page`] for the page-up and page-down keys); these return a
+/// goal position which should be passed to subsequent vertical movements.
 ///
 /// [`Text`]: crate::Text
 #[derive(Clone, Debug)]
@@ -92,14 +122,49 @@ pub struct Forme {
     /// Frequently (especially for small labels) a single run represents the
     /// whole text. Complex texts may involve many runs.
     runs: SmallVec<[shaper::GlyphRun; 1]>,
+    /// Rarely used properties of `runs`
+    run_extras: shaper::RunExtras,
     /// Contiguous runs, in logical order
     ///
     /// Within a line, runs may not be in visual order due to BIDI reversals.
     wrapped_runs: TinyVec<[RunPart; 1]>,
+    /// Runs of glyphs inserted by line wrapping (e.g. kashida)
+    ///
+    /// These are referenced by [`RunPart::SYNTHETIC`] parts.
+    synthetic_runs: Vec<(shaper::GlyphRun, shaper::RunExtra)>,
     /// Visual (wrapped) lines, in visual and logical order
     lines: TinyVec<[Line; 1]>,
     l_bound: f32,
     r_bound: f32,
+    config: Box<Config>,
+    /// Paragraph formats, keyed by the text index of the paragraph start
+    paragraphs: Vec<(u32, ParagraphFormat)>,
+    /// Wrapping state after each line
+    line_states: Vec<LineState>,
+    /// Number of lines which may be re-used by [`Self::prepare_lines`]
+    valid_lines: usize,
+    /// Parameters of the last call to [`Self::prepare_lines`]
+    wrap_params: Option<(f32, f32, Align)>,
+    /// Sum of offsets applied since [`Self::prepare_lines`]
+    offset: Vec2,
+    /// Length of the text
+    text_len: usize,
+    /// Size on the block axis, used to map to physical coordinates
+    block_size: f32,
+}
+
+/// Configuration of a [`Forme`]
+///
+/// This is boxed since it is large and rarely accessed.
+#[derive(Clone, Debug, Default)]
+struct Config {
+    justify: Justify,
+    tab_stops: TabStops,
+    line_spacing: LineSpacing,
+    writing_mode: WritingMode,
+    truncate: Truncate,
+    wrap_strategy: WrapStrategy,
+    overflow_wrap: OverflowWrap,
 }
 
 #[cfg(test)]
@@ -110,17 +175,27 @@ fn size_of_elts() {
     assert_eq!(size_of::<shaper::GlyphRun>(), 112);
     assert_eq!(size_of::<RunPart>(), 24);
     assert_eq!(size_of::<Line>(), 24);
-    assert_eq!(size_of::<Forme>(), 200);
+    assert_eq!(size_of::<Forme>(), 344);
 }
 
 impl Default for Forme {
     fn default() -> Self {
         Forme {
             runs: Default::default(),
+            run_extras: Default::default(),
             wrapped_runs: Default::default(),
+            synthetic_runs: Default::default(),
             lines: Default::default(),
             l_bound: 0.0,
             r_bound: 0.0,
+            config: Default::default(),
+            paragraphs: vec![],
+            line_states: vec![],
+            valid_lines: 0,
+            wrap_params: None,
+            offset: Vec2::ZERO,
+            text_len: 0,
+            block_size: 0.0,
         }
     }
 }
@@ -129,12 +204,58 @@ impl Forme {
     /// Reset the `Forme` to empty
     ///
     /// May be called from any [`Status`]; results in [`Status::Empty`].
+    /// Configuration (e.g. [`Self::set_justify`]) is not reset.
     pub fn clear(&mut self) {
         self.runs.clear();
+        self.run_extras.clear();
         self.wrapped_runs.clear();
+        self.synthetic_runs.clear();
         self.lines.clear();
+        self.paragraphs.clear();
         self.l_bound = 0.0;
         self.r_bound = 0.0;
+        self.line_states.clear();
+        self.valid_lines = 0;
+        self.text_len = 0;
+    }
+
+    /// Get justification options
+    #[inline]
+    pub fn justify(&self) -> Justify {
+        self.config.justify
+    }
+
+    /// Set justification options
+    ///
+    /// These options affect lines aligned with [`Align::Stretch`] (see
+    /// [`Self::prepare_lines`]). Changes take effect on the next call to
+    /// [`Self::prepare_lines`].
+    ///
+    /// [`Align::Stretch`]: crate::Align::Stretch
+    #[inline]
+    pub fn set_justify(&mut self, justify: Justify) {
+        self.config.justify = justify;
+        self.valid_lines = 0;
+    }
+
+    /// Get the glyph run referenced by a [`RunPart`]
+    #[inline]
+    fn part_run(&self, part: &RunPart) -> &shaper::GlyphRun {
+        if part.is_synthetic() {
+            &self.synthetic_runs[to_usize(part.glyph_run & !RunPart::SYNTHETIC)].0
+        } else {
+            &self.runs[to_usize(part.glyph_run)]
+        }
+    }
+
+    /// Get rarely used properties of the glyph run referenced by a [`RunPart`]
+    #[inline]
+    fn part_extra(&self, part: &RunPart) -> &shaper::RunExtra {
+        if part.is_synthetic() {
+            &self.synthetic_runs[to_usize(part.glyph_run & !RunPart::SYNTHETIC)].1
+        } else {
+            self.run_extras.get(to_usize(part.glyph_run))
+        }
     }
 
     /// Get the number of lines (after wrapping)
@@ -173,7 +294,9 @@ impl Forme {
 
         let top = self.lines.first().unwrap().top;
         let bottom = self.lines.last().unwrap().bottom;
-        (Vec2(self.l_bound, top), Vec2(self.r_bound, bottom))
+        let a = self.to_physical(Vec2(self.l_bound, top));
+        let b = self.to_physical(Vec2(self.r_bound, bottom));
+        (a.min(b), a.max(b))
     }
 
     /// Find the line containing text `index`
@@ -234,8 +357,8 @@ impl Forme {
     pub fn line_is_rtl(&self, line: usize) -> Option<bool> {
         if let Some(line) = self.lines.get(line) {
             let first_run = line.run_range.start();
-            let glyph_run = to_usize(self.wrapped_runs[first_run].glyph_run);
-            Some(self.runs[glyph_run].base_level.is_rtl())
+            let glyph_run = self.part_run(&self.wrapped_runs[first_run]);
+            Some(glyph_run.base_level.is_rtl())
         } else {
             None
         }
@@ -251,6 +374,7 @@ impl Forme {
     /// Note: if the font's `rect` does not start at the origin, then its top-left
     /// coordinate should first be subtracted from `pos`.
     pub fn text_index_nearest(&self, pos: Vec2) -> usize {
+        let pos = self.config.writing_mode.to_logical(pos, self.block_size);
         let mut n = 0;
         for (i, line) in self.lines.iter().enumerate() {
             if line.top > pos.1 {
@@ -268,30 +392,45 @@ impl Forme {
     ///
     /// This is similar to [`Forme::text_index_nearest`], but allows the
     /// line to be specified explicitly. Returns `None` only on invalid `line`.
+    ///
+    /// In vertical [writing modes](Self::set_writing_mode), `x` is the
+    /// vertical coordinate.
     pub fn line_index_nearest(&self, line: usize, x: f32) -> Option<usize> {
         if line >= self.lines.len() {
             return None;
         }
         let line = &self.lines[line];
-        let run_range = line.run_range.to_std();
 
         let mut best = line.text_range().start;
         let mut best_dist = f32::INFINITY;
-        let mut try_best = |dist, index: u32| {
+        self.visit_carets(line, |pos, index, _| {
+            let dist = (pos - x).abs();
             if dist < best_dist {
                 best = to_usize(index);
                 best_dist = dist;
             }
-        };
+        });
 
-        for run_part in &self.wrapped_runs[run_range] {
+        Some(best)
+    }
+
+    /// Visit each caret position on `line`
+    ///
+    /// Calls `f(x, index, affinity)` for each glyph boundary in logical order
+    /// of runs, where `x` is the (logical) horizontal position of text `index`.
+    /// An index may be visited twice where runs meet; the position at the
+    /// (logical) end of a run has [`Affinity::Upstream`].
+    fn visit_carets(&self, line: &Line, mut f: impl FnMut(f32, u32, Affinity)) {
+        for run_part in &self.wrapped_runs[line.run_range.to_std()] {
+            if run_part.is_synthetic() {
+                continue;
+            }
             let glyph_run = &self.runs[to_usize(run_part.glyph_run)];
-            let rel_pos = x - run_part.offset.0;
+            let offset = run_part.offset.0;
 
             if glyph_run.level.is_ltr() {
                 for glyph in &glyph_run.glyphs[run_part.glyph_range.to_std()] {
-                    let dist = (glyph.position.0 - rel_pos).abs();
-                    try_best(dist, glyph.index);
+                    f(offset + glyph.position.0, glyph.index, Affinity::Downstream);
                 }
 
                 let end_pos = if run_part.glyph_range.end() < glyph_run.glyphs.len() {
@@ -299,13 +438,14 @@ impl Forme {
                 } else {
                     glyph_run.caret
                 };
-                try_best((end_pos - rel_pos).abs(), run_part.text_end);
+                f(offset + end_pos, run_part.text_end, Affinity::Upstream);
             } else {
                 let mut index = run_part.text_end;
+                let mut affinity = Affinity::Upstream;
                 for glyph in glyph_run.glyphs[run_part.glyph_range.to_std()].iter().rev() {
-                    let dist = (glyph.position.0 - rel_pos).abs();
-                    try_best(dist, index);
-                    index = glyph.index
+                    f(offset + glyph.position.0, index, affinity);
+                    index = glyph.index;
+                    affinity = Affinity::Downstream;
                 }
 
                 let end_pos = if run_part.glyph_range.start() > 0 {
@@ -315,11 +455,9 @@ impl Forme {
                 } else {
                     glyph_run.caret
                 };
-                try_best((end_pos - rel_pos).abs(), index);
+                f(offset + end_pos, index, affinity);
             }
         }
-
-        Some(best)
     }
 
     #[cfg(test)]
@@ -327,3 +465,59 @@ impl Forme {
         &self.runs
     }
 }
+
+/// Fixtures shared by tests
+#[cfg(test)]
+pub(crate) mod test_util {
+    use super::{Appender, Forme};
+    use crate::fonts::FontSelector;
+    use crate::{Align, Direction, Vec2};
+
+    /// Set `text` using the default font at 16 pixels per Em
+    ///
+    /// `configure` may be used to set further properties of the text.
+    pub(crate) fn shape_with<'a>(
+        forme: &'a mut Forme,
+        text: &'a str,
+        configure: impl FnOnce(&mut Appender<'a>),
+    ) {
+        let mut appender = forme.set_text(text, Direction::Auto);
+        configure(&mut appender);
+        assert!(
+            appender
+                .with_font(.., FontSelector::default(), 16.0)
+                .is_ok()
+        );
+    }
+
+    /// Set `text` using the default font at 16 pixels per Em
+    pub(crate) fn shape(forme: &mut Forme, text: &str) {
+        shape_with(forme, text, |_| ());
+    }
+
+    /// Construct a [`Forme`] over `text`, applying `configure` before shaping
+    pub(crate) fn shaped(text: &str, configure: impl FnOnce(&mut Forme)) -> Forme {
+        let mut forme = Forme::default();
+        configure(&mut forme);
+        shape(&mut forme, text);
+        forme
+    }
+
+    /// Construct a [`Forme`] over `text`, wrapped and aligned to `width`
+    pub(crate) fn prepare(text: &str, width: f32) -> Forme {
+        let mut forme = Forme::default();
+        shape(&mut forme, text);
+        forme.prepare_lines(width, width, Align::Default);
+        forme
+    }
+
+    /// Get the (first) glyph position at `index`
+    pub(crate) fn glyph_pos(forme: &Forme, index: usize) -> Vec2 {
+        forme.text_glyph_pos(index).next().unwrap().pos
+    }
+
+    /// Get the text range of each line
+    pub(crate) fn line_ranges(forme: &Forme) -> Vec<std::ops::Range<usize>> {
+        forme.lines().map(|line| line.text_range()).collect()
+    }
+}
@@ -0,0 +1,171 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Text preparation: inline objects
+
+use super::Forme;
+use crate::Vec2;
+use crate::conv::to_usize;
+use crate::shaper::Orientation;
+
+/// Metrics of an inline object
+///
+/// Inline objects (e.g. icons or images) are placed in text using
+/// [`Appender::with_object`](super::Appender::with_object).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InlineObject {
+    /// Width (inline advance) of the object
+    pub width: f32,
+    /// Position of the top edge above the baseline
+    pub ascent: f32,
+    /// Position of the bottom edge relative to the baseline
+    ///
+    /// As with font metrics, this is negative when the object extends below
+    /// the baseline.
+    pub descent: f32,
+}
+
+impl InlineObject {
+    /// Construct
+    #[inline]
+    pub fn new(width: f32, ascent: f32, descent: f32) -> Self {
+        InlineObject {
+            width,
+            ascent,
+            descent,
+        }
+    }
+}
+
+/// The position of an inline object
+///
+/// Yielded by [`Forme::objects`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ObjectPos {
+    /// Text index of the object
+    pub index: usize,
+    /// Object metrics
+    pub object: InlineObject,
+    /// Position of the top-left corner
+    pub pos: Vec2,
+    /// Size of the object's box
+    ///
+    /// In vertical [writing modes](Forme::set_writing_mode) the object's
+    /// `width` is on the vertical axis.
+    pub size: Vec2,
+}
+
+impl Forme {
+    /// Iterate over positioned inline objects
+    ///
+    /// Expects state: [`Status::Ready`](crate::Status::Ready).
+    ///
+    /// Objects are yielded in logical order. Objects on lines omitted due to
+    /// [truncation](Self::set_truncate) are not included.
+    pub fn objects(&self) -> impl Iterator<Item = ObjectPos> + '_ {
+        self.wrapped_runs
+            .iter()
+            .filter(|part| !part.glyph_range.is_empty() && !part.is_synthetic())
+            .filter_map(|part| {
+                let run = self.part_run(part);
+                let extra = self.part_extra(part);
+                let object = extra.object?;
+                let glyph = run.glyphs[part.glyph_range.start()];
+                let x = part.offset.0 + glyph.position.0;
+                let baseline = part.offset.1;
+                let (top, bottom) = match extra.orientation {
+                    Orientation::Horizontal => {
+                        (baseline - object.ascent, baseline - object.descent)
+                    }
+                    _ => {
+                        // Vertical text is centered on the baseline
+                        let half = 0.5 * (object.ascent - object.descent);
+                        (baseline - half, baseline + half)
+                    }
+                };
+                let a = self.to_physical(Vec2(x, top));
+                let b = self.to_physical(Vec2(x + object.width, bottom));
+                Some(ObjectPos {
+                    index: to_usize(glyph.index),
+                    object,
+                    pos: a.min(b),
+                    size: (b - a).abs(),
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Align;
+    use crate::forme::test_util::{glyph_pos, shape_with};
+
+    const OBJECT: InlineObject = InlineObject {
+        width: 40.0,
+        ascent: 30.0,
+        descent: -10.0,
+    };
+
+    #[test]
+    fn object() {
+        let text = "ab\u{FFFC}cd";
+        let mut forme = Forme::default();
+        shape_with(&mut forme, text, |appender| {
+            appender.with_object(2, OBJECT);
+        });
+        forme.prepare_lines(500.0, 500.0, Align::Default);
+        let objects: Vec<_> = forme.objects().collect();
+        assert_eq!(objects.len(), 1);
+        let obj = objects[0];
+        assert_eq!(obj.index, 2);
+        assert_eq!(obj.size, Vec2(40.0, 40.0));
+        assert_eq!(obj.pos.0, glyph_pos(&forme, 2).0);
+        assert_eq!(glyph_pos(&forme, 5).0, obj.pos.0 + 40.0);
+
+        // The line box holds the object
+        let line = forme.get_line(0).unwrap();
+        assert!(line.top() <= obj.pos.1);
+        assert!(obj.pos.1 + obj.size.1 <= line.bottom());
+
+        // The placeholder glyph is not drawn
+        let glyphs: usize = forme
+            .runs::<()>(Vec2::ZERO, &[])
+            .map(|run| run.glyphs().count())
+            .sum();
+        assert_eq!(glyphs, 4);
+    }
+
+    #[test]
+    fn wrap() {
+        let text = "abc\u{FFFC}def";
+        let mut forme = Forme::default();
+        shape_with(&mut forme, text, |appender| {
+            appender.with_object(3, OBJECT);
+        });
+        forme.prepare_lines(60.0, 60.0, Align::Default);
+        assert_eq!(forme.num_lines(), 3);
+        assert_eq!(forme.get_line(1).unwrap().text_range(), 3..6);
+        let obj = forme.objects().next().unwrap();
+        assert_eq!(obj.pos.0, 0.0);
+        assert!(obj.pos.1 >= forme.get_line(0).unwrap().bottom());
+    }
+
+    #[test]
+    fn rtl() {
+        let text = "אב\u{FFFC}גד";
+        let mut forme = Forme::default();
+        shape_with(&mut forme, text, |appender| {
+            appender.with_object(4, OBJECT);
+        });
+        forme.prepare_lines(500.0, 500.0, Align::Default);
+        let obj = forme.objects().next().unwrap();
+        assert_eq!(obj.index, 4);
+        // Visually: גד [object] אב
+        assert!(glyph_pos(&forme, 7).0 <= obj.pos.0);
+        assert!(obj.pos.0 + obj.size.0 <= glyph_pos(&forme, 2).0);
+    }
+}
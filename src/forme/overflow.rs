@@ -0,0 +1,232 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Text preparation: overflow wrapping
+
+use super::{Forme, RunSpecial};
+
+/// Overflow wrapping mode
+///
+/// See [`Forme::set_overflow_wrap`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OverflowWrap {
+    /// Lines only wrap at line-break opportunities
+    ///
+    /// Words longer than a line overflow.
+    #[default]
+    Normal,
+    /// Words which do not fit on an empty line may wrap between any clusters
+    ///
+    /// This is similar to CSS `overflow-wrap: anywhere`.
+    Anywhere,
+}
+
+/// Parts of a word as `(run, part range)`
+type Word = [(usize, std::ops::Range<usize>)];
+
+impl Forme {
+    /// Get the overflow wrapping mode
+    #[inline]
+    pub fn overflow_wrap(&self) -> OverflowWrap {
+        self.config.overflow_wrap
+    }
+
+    /// Set the overflow wrapping mode
+    ///
+    /// With [`OverflowWrap::Anywhere`], words (or other unbreakable content
+    /// such as a URL) which do not fit on an empty line are broken between
+    /// clusters (usually grapheme clusters). Break points are added to the
+    /// shaped text at all cluster boundaries; text is not re-shaped.
+    ///
+    /// This affects [`Self::prepare_lines`], [`Self::measure_height`] and
+    /// [`Self::measure_min_width`]. Changes take effect on the next call to
+    /// [`Self::prepare_lines`].
+    #[inline]
+    pub fn set_overflow_wrap(&mut self, mode: OverflowWrap) {
+        if mode == self.config.overflow_wrap {
+            return;
+        }
+        self.config.overflow_wrap = mode;
+        self.valid_lines = 0;
+
+        for (run_index, run) in self.runs.iter_mut().enumerate() {
+            match mode {
+                OverflowWrap::Normal => run.remove_emergency_breaks(),
+                OverflowWrap::Anywhere if !self.run_extras.get(run_index).no_wrap => {
+                    run.add_emergency_breaks()
+                }
+                OverflowWrap::Anywhere => (),
+            }
+        }
+    }
+
+    /// Measure the minimum width required to avoid overflow
+    ///
+    /// Expects state [`Status::Shaped`](crate::Status::Shaped) or higher.
+    ///
+    /// This is the *min-content* width: the length of the longest word or,
+    /// with [`OverflowWrap::Anywhere`], of the longest cluster. It includes
+    /// [paragraph](super::ParagraphFormat) margins and indents.
+    /// See also [`Self::measure_width`] which measures unwrapped lines.
+    pub fn measure_min_width(&self) -> f32 {
+        let mut max_len = 0.0f32;
+        let mut run_index = 0;
+        while run_index < self.runs.len() {
+            let margin = self.paragraph_margin(run_index);
+            let mut para_len = 0.0f32;
+            run_index = self.visit_words(run_index, |word, len| {
+                let no_wrap = word
+                    .iter()
+                    .any(|(run, _)| self.run_extras.get(*run).no_wrap);
+                let len = match self.config.overflow_wrap {
+                    _ if no_wrap => len,
+                    OverflowWrap::Normal => len,
+                    OverflowWrap::Anywhere => word
+                        .iter()
+                        .map(|(run, parts)| self.runs[*run].max_cluster_len(parts.clone()))
+                        .fold(0.0, f32::max),
+                };
+                para_len = para_len.max(len);
+            });
+            max_len = max_len.max(margin + para_len);
+        }
+        max_len
+    }
+
+    /// The largest margin of lines of the paragraph starting with `para_run`
+    fn paragraph_margin(&self, para_run: usize) -> f32 {
+        let first = self.line_format(para_run, true);
+        let other = self.line_format(para_run, false);
+        (first.start + first.end).max(other.start + other.end)
+    }
+
+    /// Visit each word of the paragraph starting with run `para_run`
+    ///
+    /// Calls `f(word, len)` for each word, where `len` is the word's length
+    /// excluding trailing white-space. Emergency breaks are ignored.
+    ///
+    /// Returns the first run of the next paragraph.
+    fn visit_words(&self, para_run: usize, mut f: impl FnMut(&Word, f32)) -> usize {
+        let mut word = vec![];
+        let mut len = 0.0;
+        let mut len_no_space = 0.0;
+        let mut run_index = para_run;
+        while run_index < self.runs.len() {
+            let run = &self.runs[run_index];
+            let num_parts = run.num_parts();
+            let allow_break = run.allows_break_at_end(self.run_extras.get(run_index).no_wrap);
+
+            let mut start = 0;
+            for part_index in 1..=num_parts {
+                if run.part_end_emergency(part_index) {
+                    continue;
+                }
+                let part = run.part_lengths(start..part_index);
+                if part.len_no_space > 0.0 {
+                    len_no_space = len + part.len_no_space;
+                }
+                len += part.len;
+                word.push((run_index, start..part_index));
+
+                if part_index < num_parts || allow_break {
+                    f(&word, len_no_space);
+                    word.clear();
+                    len = 0.0;
+                    len_no_space = 0.0;
+                }
+                start = part_index;
+            }
+
+            run_index += 1;
+            if run.special == RunSpecial::HardBreak {
+                break;
+            }
+        }
+        run_index
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Align;
+    use crate::forme::WrapStrategy;
+    use crate::forme::test_util::{line_ranges, shaped};
+
+    #[test]
+    fn anywhere() {
+        let text = "see 0123456789abcdef0123456789abcdef now";
+        let mut normal = shaped(text, |forme| forme.set_overflow_wrap(OverflowWrap::Normal));
+        normal.prepare_lines(100.0, 100.0, Align::Default);
+        assert_eq!(line_ranges(&normal), [0..3, 4..36, 37..40]);
+        assert!(normal.bounding_box().1.0 > 100.0);
+
+        let mut forme = shaped(text, |forme| {
+            forme.set_overflow_wrap(OverflowWrap::Anywhere)
+        });
+        let height = forme.measure_height(100.0, None);
+        assert_eq!(forme.prepare_lines(100.0, 100.0, Align::Default), height);
+        let lines = line_ranges(&forme);
+        assert!(lines.len() > 3);
+        // The long word is only broken after moving to a new line
+        assert_eq!(lines[0], 0..3);
+        assert_eq!(lines.last().unwrap().end, text.len());
+        assert!(forme.bounding_box().1.0 <= 100.0);
+
+        // Wider lines do not break short words
+        forme.prepare_lines(1000.0, 1000.0, Align::Default);
+        assert_eq!(forme.num_lines(), 1);
+        forme.prepare_lines(100.0, 100.0, Align::Default);
+        assert_eq!(line_ranges(&forme), lines);
+    }
+
+    #[test]
+    fn anywhere_optimal() {
+        let text = "aaa bb cc ddddd";
+        let mut forme = shaped(text, |forme| {
+            forme.set_wrap_strategy(WrapStrategy::Optimal);
+            forme.set_overflow_wrap(OverflowWrap::Anywhere);
+        });
+        forme.prepare_lines(60.0, 60.0, Align::Default);
+        let lines = line_ranges(&forme);
+        assert_eq!(lines, [0..3, 4..9, 10..15]);
+
+        // Measuring a narrow width does not affect later wrapping
+        assert!(forme.measure_height(12.0, None) > 0.0);
+        forme.prepare_lines(60.0, 60.0, Align::Default);
+        assert_eq!(line_ranges(&forme), lines);
+    }
+
+    #[test]
+    fn anywhere_rtl() {
+        let text = "אבגדהוזחטיכלמנסעפצקרשת";
+        let mut forme = shaped(text, |forme| {
+            forme.set_overflow_wrap(OverflowWrap::Anywhere)
+        });
+        forme.prepare_lines(60.0, 60.0, Align::Default);
+        assert!(forme.num_lines() > 1);
+        let mut end = 0;
+        for line in forme.lines() {
+            assert_eq!(line.text_range().start, end);
+            end = line.text_range().end;
+        }
+        assert_eq!(end, text.len());
+        assert!(forme.bounding_box().0.0 >= 0.0);
+    }
+
+    #[test]
+    fn min_width() {
+        let text = "a bbbbbb cc";
+        let normal = shaped(text, |forme| forme.set_overflow_wrap(OverflowWrap::Normal));
+        let forme = shaped(text, |forme| {
+            forme.set_overflow_wrap(OverflowWrap::Anywhere)
+        });
+        let word = normal.measure_min_width();
+        assert!(word > 0.0 && word < normal.measure_width(f32::INFINITY));
+        let cluster = forme.measure_min_width();
+        assert!(cluster > 0.0 && cluster < 0.25 * word);
+    }
+}
@@ -0,0 +1,194 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Text preparation: pagination
+
+use super::Forme;
+use super::wrap_lines::{PageLimit, WrapOptions};
+use crate::{Align, Vec2};
+
+/// Control over breaking paragraphs between boxes
+///
+/// See [`Forme::prepare_box`]. The default value of `1` for each field
+/// imposes no restrictions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fragmentation {
+    /// Minimum number of lines of a paragraph left at the end of a box
+    pub orphans: usize,
+    /// Minimum number of lines of a paragraph moved to the start of a box
+    pub widows: usize,
+}
+
+impl Default for Fragmentation {
+    fn default() -> Self {
+        Fragmentation {
+            orphans: 1,
+            widows: 1,
+        }
+    }
+}
+
+impl Fragmentation {
+    /// Construct with the given limits
+    #[inline]
+    pub fn new(orphans: usize, widows: usize) -> Self {
+        Fragmentation { orphans, widows }
+    }
+}
+
+impl Forme {
+    /// Flow text content from `start` into a box of limited `height`
+    ///
+    /// This is a variant of [`Self::prepare_lines`] which wraps lines from
+    /// text index `start`, which must be the start of a line (e.g. `0` or the
+    /// result of a previous call), and stops once lines exceed `height`.
+    /// The first line is positioned at the top of the box (paragraph spacing
+    /// is not applied before it).
+    ///
+    /// Returns the text index at which the next box should start, or `None`
+    /// if all remaining content fits within this box. The box holds text
+    /// `start..next` where `next` is the result or the length of the text.
+    ///
+    /// Paragraphs are broken between boxes according to `control` where
+    /// possible. Each box holds at least one line.
+    pub fn prepare_box(
+        &mut self,
+        start: usize,
+        wrap_width: f32,
+        align_width: f32,
+        h_align: Align,
+        height: f32,
+        control: Fragmentation,
+    ) -> Option<usize> {
+        let options = WrapOptions {
+            start,
+            page: Some(PageLimit {
+                height,
+                widows: control.widows.max(1),
+            }),
+            ..Default::default()
+        };
+        self.prepare_lines_impl(wrap_width, align_width, h_align, options);
+
+        let k = self.lines.iter().position(|line| line.bottom() > height)?;
+
+        // The range of lines of the paragraph containing line k
+        let mut para_start = k;
+        while para_start > 0 && !self.line_ends_paragraph(para_start - 1) {
+            para_start -= 1;
+        }
+        let mut para_end = k;
+        while para_end + 1 < self.lines.len() && !self.line_ends_paragraph(para_end) {
+            para_end += 1;
+        }
+        let starts_here = para_start > 0 || self.is_paragraph_start(start);
+
+        let mut cut = k;
+        if self.line_ends_paragraph(para_end) && para_end + 1 - k < control.widows {
+            cut = (para_end + 1)
+                .saturating_sub(control.widows)
+                .max(para_start);
+        }
+        if starts_here && cut > para_start && cut - para_start < control.orphans {
+            cut = para_start;
+        }
+        if cut == 0 {
+            cut = k.max(1);
+        }
+
+        let next = self.lines.get(cut).map(|line| line.text_range().start);
+        self.truncate_lines(cut);
+        next
+    }
+
+    /// Split text over a sequence of boxes
+    ///
+    /// Each item of `boxes` is the size of a box (`width, height`), which is
+    /// used with [`Self::prepare_box`]. Returns the text range of each box
+    /// used; if `boxes` has insufficient items, the last range does not reach
+    /// the end of the text.
+    ///
+    /// The result is laid out for the last box used. Call
+    /// [`Self::prepare_box`] to lay out another box.
+    pub fn paginate(
+        &mut self,
+        boxes: impl IntoIterator<Item = Vec2>,
+        h_align: Align,
+        control: Fragmentation,
+    ) -> Vec<std::ops::Range<usize>> {
+        let mut ranges = vec![];
+        let mut start = 0;
+        for size in boxes {
+            match self.prepare_box(start, size.0, size.0, h_align, size.1, control) {
+                Some(next) => {
+                    ranges.push(start..next);
+                    start = next;
+                }
+                None => {
+                    ranges.push(start..self.text_len);
+                    break;
+                }
+            }
+        }
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::forme::test_util::shaped;
+    use crate::{LineHeight, LineSpacing};
+
+    #[test]
+    fn pages() {
+        let spacing = LineSpacing {
+            height: LineHeight::Px(20.0),
+            ..Default::default()
+        };
+        let text = "a\nb\nc\nd\ne";
+        let mut forme = shaped(text, |forme| forme.set_line_spacing(spacing));
+        let boxes = std::iter::repeat(Vec2(100.0, 50.0));
+        let ranges = forme.paginate(boxes, Align::Default, Fragmentation::default());
+        assert_eq!(ranges, [0..4, 4..8, 8..9]);
+        assert_eq!(forme.num_lines(), 1);
+        assert_eq!(forme.get_line(0).unwrap().top(), 0.0);
+
+        // Insufficient boxes
+        let boxes = [Vec2(100.0, 50.0)];
+        let ranges = forme.paginate(boxes, Align::Default, Fragmentation::default());
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0], 0..4);
+
+        // A box from the middle of a wrapped paragraph
+        let text = "aa bb cc dd";
+        let mut forme = shaped(text, |forme| forme.set_line_spacing(spacing));
+        let next = forme.prepare_box(6, 30.0, 30.0, Align::Default, 30.0, Default::default());
+        assert_eq!(next, Some(9));
+        assert_eq!(forme.get_line(0).unwrap().text_range(), 6..8);
+        assert_eq!(forme.num_lines(), 1);
+    }
+
+    #[test]
+    fn widows_and_orphans() {
+        // Lines: x, aa, bb, cc, dd
+        let text = "x\naa bb cc dd";
+        let spacing = LineSpacing {
+            height: LineHeight::Px(20.0),
+            ..Default::default()
+        };
+        let mut forme = shaped(text, |forme| forme.set_line_spacing(spacing));
+        let mut prepare_box = |control| {
+            let next = forme.prepare_box(0, 30.0, 30.0, Align::Default, 80.0, control);
+            (next, forme.num_lines())
+        };
+
+        assert_eq!(prepare_box(Fragmentation::default()), (Some(11), 4));
+        assert_eq!(prepare_box(Fragmentation::new(1, 2)), (Some(8), 3));
+        assert_eq!(prepare_box(Fragmentation::new(3, 2)), (Some(2), 1));
+        assert_eq!(prepare_box(Fragmentation::new(5, 5)), (Some(2), 1));
+    }
+}
@@ -0,0 +1,207 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Text preparation: paragraph formatting
+
+use super::Forme;
+use crate::{Align, Direction, Length};
+
+/// Formatting of a paragraph
+///
+/// Paragraph formats are set using
+/// [`Appender::with_paragraph`](super::Appender::with_paragraph) or by
+/// [`FormattableText::paragraph_formats`](crate::format::FormattableText::paragraph_formats).
+///
+/// Margins and indents are measured on the inline axis from the *start* of the
+/// line (the left edge in left-to-right paragraphs, the right edge in
+/// right-to-left paragraphs) and reduce the width available for wrapping and
+/// alignment. Lengths in [`Length::Em`] are resolved using the font size of
+/// the first run of the paragraph.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParagraphFormat {
+    /// Horizontal alignment
+    ///
+    /// If `None`, the `h_align` parameter of [`Forme::prepare_lines`] is used.
+    pub align: Option<Align>,
+    /// Base text direction
+    ///
+    /// If `None`, the direction passed to [`Forme::set_text`] is used.
+    pub direction: Option<Direction>,
+    /// Margin at the start of each line
+    pub start_margin: Length,
+    /// Margin at the end of each line
+    pub end_margin: Length,
+    /// Indentation of the first line, in addition to the start margin
+    ///
+    /// This may be negative (but should not exceed the start margin), giving
+    /// a hanging indent; see [`Self::hanging`]. In this case a tab on the first
+    /// line which would advance beyond the start margin instead stops at it.
+    /// Thus a list marker followed by a tab aligns with the following lines of
+    /// a list item.
+    pub first_line_indent: Length,
+}
+
+impl ParagraphFormat {
+    /// Construct with the given alignment
+    #[inline]
+    pub fn aligned(align: Align) -> Self {
+        ParagraphFormat {
+            align: Some(align),
+            ..Default::default()
+        }
+    }
+
+    /// Construct with a hanging indent
+    ///
+    /// Lines are indented by `margin`, excepting the first line which is
+    /// indented by `margin - hang`.
+    #[inline]
+    pub fn hanging(margin: Length, hang: Length) -> Self {
+        let first_line_indent = match hang {
+            Length::Px(x) => Length::Px(-x),
+            Length::Em(x) => Length::Em(-x),
+        };
+        ParagraphFormat {
+            start_margin: margin,
+            first_line_indent,
+            ..Default::default()
+        }
+    }
+}
+
+/// Resolved format of a line
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(super) struct LineFormat {
+    /// Space before the line (start margin plus indent)
+    pub(super) start: f32,
+    /// Space after the line
+    pub(super) end: f32,
+    /// Alignment override
+    pub(super) align: Option<Align>,
+    /// Position of an implicit tab stop, relative to the line start
+    pub(super) tab_stop: Option<f32>,
+}
+
+impl LineFormat {
+    /// Limit the `advance` of a tab at `caret` to the implicit tab stop
+    pub(super) fn limit_tab(&self, caret: f32, advance: f32) -> f32 {
+        match self.tab_stop {
+            Some(stop) if stop > caret => advance.min(stop - caret),
+            _ => advance,
+        }
+    }
+}
+
+impl Forme {
+    /// Get the format of the paragraph starting at text `index`, if any
+    pub fn paragraph_format(&self, index: usize) -> Option<&ParagraphFormat> {
+        let index = crate::conv::to_u32(index);
+        self.paragraphs
+            .binary_search_by_key(&index, |(i, _)| *i)
+            .ok()
+            .map(|i| &self.paragraphs[i].1)
+    }
+
+    /// Resolve the format of a line of the paragraph starting with run `run_index`
+    pub(super) fn line_format(&self, run_index: usize, first_line: bool) -> LineFormat {
+        let Some(run) = self.runs.get(run_index) else {
+            return LineFormat::default();
+        };
+        let index = crate::conv::to_usize(run.range.start);
+        let Some(format) = self.paragraph_format(index) else {
+            return LineFormat::default();
+        };
+
+        let dpem = run.dpem;
+        let margin = format.start_margin.to_px(dpem);
+        let first = format.first_line_indent.to_px(dpem);
+        LineFormat {
+            start: margin + if first_line { first } else { 0.0 },
+            end: format.end_margin.to_px(dpem),
+            align: format.align,
+            tab_stop: (first_line && first < 0.0).then_some(-first),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::forme::test_util::{glyph_pos, prepare, shape_with};
+
+    #[test]
+    fn margins() {
+        let text = "abc def ghi jkl mno\nabc";
+        let format = ParagraphFormat {
+            start_margin: Length::Px(20.0),
+            end_margin: Length::Px(10.0),
+            first_line_indent: Length::Px(15.0),
+            ..Default::default()
+        };
+        let mut forme = Forme::default();
+        shape_with(&mut forme, text, |appender| {
+            appender.with_paragraph(0, format);
+        });
+        forme.prepare_lines(100.0, 100.0, Align::Default);
+        assert!(forme.num_lines() > 2);
+        assert_eq!(glyph_pos(&forme, 0).0, 35.0);
+        let line = forme.get_line(1).unwrap();
+        assert_eq!(glyph_pos(&forme, line.text_range().start).0, 20.0);
+        assert!(forme.bounding_box().1.0 <= 90.0);
+
+        // The second paragraph is not formatted
+        let start = text.find('\n').unwrap() + 1;
+        assert_eq!(glyph_pos(&forme, start).0, 0.0);
+
+        let width = forme.measure_width(f32::INFINITY);
+        let unformatted = prepare(text, 100.0).measure_width(f32::INFINITY);
+        assert_eq!(width, unformatted + 45.0);
+    }
+
+    #[test]
+    fn align_and_direction() {
+        let text = "ab\ncd\nef";
+        let formats = [
+            (3, ParagraphFormat::aligned(Align::BR)),
+            (
+                7,
+                ParagraphFormat {
+                    direction: Some(Direction::Rtl),
+                    start_margin: Length::Px(20.0),
+                    ..Default::default()
+                },
+            ),
+        ];
+        let mut forme = Forme::default();
+        shape_with(&mut forme, text, |appender| {
+            for (index, format) in formats {
+                appender.with_paragraph(index, format);
+            }
+        });
+        forme.prepare_lines(200.0, 200.0, Align::Default);
+        assert_eq!(glyph_pos(&forme, 0).0, 0.0);
+        assert_eq!(glyph_pos(&forme, 5).0, 200.0);
+        // The last paragraph is right-to-left, right-aligned with a margin
+        assert!(forme.runs[forme.runs.len() - 1].base_level.is_rtl());
+        assert_eq!(glyph_pos(&forme, 8).0, 180.0);
+    }
+
+    #[test]
+    fn hanging_indent() {
+        let text = "•\tabc def ghi jkl mno";
+        let format = ParagraphFormat::hanging(Length::Px(40.0), Length::Px(30.0));
+        let mut forme = Forme::default();
+        shape_with(&mut forme, text, |appender| {
+            appender.with_paragraph(0, format);
+        });
+        forme.prepare_lines(100.0, 100.0, Align::Default);
+        assert!(forme.num_lines() > 1);
+        assert_eq!(glyph_pos(&forme, 0).0, 10.0);
+        assert_eq!(glyph_pos(&forme, "•\t".len()).0, 40.0);
+        let line = forme.get_line(1).unwrap();
+        assert_eq!(glyph_pos(&forme, line.text_range().start).0, 40.0);
+    }
+}
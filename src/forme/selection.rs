@@ -0,0 +1,169 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Text navigation: selection geometry
+
+use super::{Forme, Line};
+use crate::Vec2;
+use crate::conv::to_usize;
+use std::ops::Range;
+
+impl Forme {
+    /// Get rectangles covering the text `range`, for drawing a selection
+    ///
+    /// Expects state [`Status::Ready`](crate::Status::Ready).
+    ///
+    /// Each rectangle is given as `(top_left, bottom_right)`. The result
+    /// contains the minimal set of rectangles for each line: where selected
+    /// text is visually contiguous (e.g. over a change of direction) a single
+    /// rectangle is used, while where a selection ends within bidirectional
+    /// text multiple rectangles may be required. Rectangles are ordered by
+    /// line then visually (left to right), and span from [`Line::top`] to
+    /// [`Line::bottom`].
+    ///
+    /// If `extend_newlines`, the selection of a line break (the end of a
+    /// paragraph) is represented by extending the selection to the edge of
+    /// the [bounding box](Self::bounding_box) in the line's direction, or by
+    /// half the line's height, whichever is further.
+    pub fn selection_rects(&self, range: Range<usize>, extend_newlines: bool) -> Vec<(Vec2, Vec2)> {
+        let mut rects = vec![];
+        if range.is_empty() {
+            return rects;
+        }
+
+        for (n, line) in self.lines.iter().enumerate() {
+            let text_range = line.text_range();
+            let next_start = match self.lines.get(n + 1) {
+                Some(next) => next.text_range().start,
+                None => self.text_len,
+            };
+            if text_range.start >= range.end || next_start.max(text_range.end) <= range.start {
+                continue;
+            }
+
+            let mut spans = self.selected_spans(line, &range);
+
+            let newline = text_range.end..next_start;
+            if extend_newlines
+                && self.line_ends_paragraph(n)
+                && !newline.is_empty()
+                && range.contains(&newline.start)
+            {
+                let mut edges = (f32::INFINITY, f32::NEG_INFINITY);
+                self.visit_carets(line, |x, _, _| edges = (edges.0.min(x), edges.1.max(x)));
+                let extent = 0.5 * (line.bottom - line.top);
+                let span = if self.line_is_rtl(n).unwrap_or_default() {
+                    (self.l_bound.min(edges.0 - extent), edges.0)
+                } else {
+                    (edges.1, self.r_bound.max(edges.1 + extent))
+                };
+                spans.push(span);
+            }
+
+            spans.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let mut merged: Vec<(f32, f32)> = vec![];
+            for span in spans {
+                match merged.last_mut() {
+                    Some(last) if span.0 <= last.1 + 0.01 => last.1 = last.1.max(span.1),
+                    _ => merged.push(span),
+                }
+            }
+
+            rects.extend(merged.into_iter().map(|(l, r)| {
+                let a = self.to_physical(Vec2(l, line.top));
+                let b = self.to_physical(Vec2(r, line.bottom));
+                (a.min(b), a.max(b))
+            }));
+        }
+        rects
+    }
+
+    /// Get horizontal spans `(left, right)` of glyphs in `range` on `line`
+    ///
+    /// At most one span is returned per run part.
+    fn selected_spans(&self, line: &Line, range: &Range<usize>) -> Vec<(f32, f32)> {
+        let mut spans = vec![];
+        for run_part in &self.wrapped_runs[line.run_range.to_std()] {
+            if run_part.is_synthetic() {
+                continue;
+            }
+            let glyph_run = &self.runs[to_usize(run_part.glyph_run)];
+            let glyph_range = run_part.glyph_range.to_std();
+
+            let mut span = (f32::INFINITY, f32::NEG_INFINITY);
+            for gi in glyph_range.clone() {
+                if !range.contains(&to_usize(glyph_run.glyphs[gi].index)) {
+                    continue;
+                }
+                let (start, end) = glyph_run.glyph_span(gi);
+                span = (span.0.min(start), span.1.max(end));
+            }
+
+            if span.0 <= span.1 {
+                let offset = run_part.offset.0;
+                spans.push((offset + span.0, offset + span.1));
+            }
+        }
+        spans
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Vec2;
+    use crate::forme::test_util::{glyph_pos, prepare};
+
+    #[test]
+    fn single_line() {
+        let text = "abc def";
+        let forme = prepare(text, 1000.0);
+        assert!(forme.selection_rects(2..2, true).is_empty());
+
+        let rects = forme.selection_rects(1..5, false);
+        assert_eq!(rects.len(), 1);
+        let line = forme.get_line(0).unwrap();
+        assert_eq!(rects[0].0, Vec2(glyph_pos(&forme, 1).0, line.top()));
+        assert_eq!(rects[0].1, Vec2(glyph_pos(&forme, 5).0, line.bottom()));
+    }
+
+    #[test]
+    fn multi_line() {
+        let text = "abc\ndef\nghi";
+        let forme = prepare(text, 1000.0);
+        let rects = forme.selection_rects(1..9, false);
+        assert_eq!(rects.len(), 3);
+        assert_eq!(rects[0].1.1, rects[1].0.1);
+        assert_eq!(rects[1].0.0, 0.0);
+        assert_eq!(rects[1].1.0, glyph_pos(&forme, 7).0);
+        assert_eq!(rects[2].1.0, glyph_pos(&forme, 9).0);
+
+        // Selected line breaks are extended
+        let extended = forme.selection_rects(1..9, true);
+        assert_eq!(extended.len(), 3);
+        assert_eq!(extended[0].0, rects[0].0);
+        assert!(extended[0].1.0 > rects[0].1.0);
+        assert!(extended[1].1.0 > rects[1].1.0);
+        assert_eq!(extended[2], rects[2]);
+    }
+
+    #[test]
+    fn bidi() {
+        // Visual order: "ab גבא cd"
+        let text = "ab אבג cd";
+        let forme = prepare(text, 1000.0);
+
+        // Selecting all is one rectangle
+        let all = forme.selection_rects(0..text.len(), false);
+        assert_eq!(all.len(), 1);
+
+        // "b א" is split: "b " on the left and "א" at the right of the RTL word
+        let rects = forme.selection_rects(1..5, false);
+        assert_eq!(rects.len(), 2);
+        assert_eq!(rects[0].0.0, glyph_pos(&forme, 1).0);
+        assert!(rects[0].1.0 < rects[1].0.0);
+        assert_eq!(rects[1].0.0, glyph_pos(&forme, 5).0);
+        assert!(rects[1].1.0 > rects[1].0.0);
+    }
+}
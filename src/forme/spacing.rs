@@ -0,0 +1,301 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Text preparation: line spacing
+
+use super::Forme;
+use crate::Length;
+use crate::fonts::FontLibrary;
+use crate::shaper::{GlyphRun, Orientation, RunExtra};
+
+/// Line height
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LineHeight {
+    /// Line height is determined by font metrics
+    ///
+    /// Each line is tall enough for the ascent and descent of each font used
+    /// on the line. Font line gaps are added between lines if
+    /// [`LineSpacing::line_gap`] is enabled.
+    #[default]
+    Normal,
+    /// A fixed line height in pixels
+    Px(f32),
+    /// A line height relative to the font size (units of Em)
+    Em(f32),
+}
+
+/// Line and paragraph spacing options
+///
+/// With an explicit [`LineHeight`], the difference between the line height
+/// and the font's ascent plus descent is distributed equally above and below
+/// the text of each run (as with CSS's "half-leading"); font line gaps are
+/// not used. Where multiple font sizes are used on a line, the line box is
+/// large enough to hold all runs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LineSpacing {
+    /// Line height
+    pub height: LineHeight,
+    /// If true, add font line gaps between lines (default: true)
+    ///
+    /// This only affects [`LineHeight::Normal`].
+    pub line_gap: bool,
+    /// If true, fallback fonts affect line height (default: true)
+    ///
+    /// If false, line metrics are taken from the first face of the selected
+    /// font, even where glyphs are taken from a fallback face (e.g. for
+    /// emoji or other scripts). Such glyphs may then extend outside of the
+    /// line box.
+    pub fallback_metrics: bool,
+    /// Space before each paragraph, excluding the first
+    ///
+    /// Units of [`Length::Em`] are relative to the largest font size on the
+    /// paragraph's first line.
+    pub before_paragraph: Length,
+    /// Space after each paragraph, excluding the last
+    ///
+    /// Units of [`Length::Em`] are relative to the largest font size on the
+    /// paragraph's last line.
+    pub after_paragraph: Length,
+}
+
+impl Default for LineSpacing {
+    fn default() -> Self {
+        LineSpacing {
+            height: LineHeight::Normal,
+            line_gap: true,
+            fallback_metrics: true,
+            before_paragraph: Length::ZERO,
+            after_paragraph: Length::ZERO,
+        }
+    }
+}
+
+/// Vertical metrics of a line
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct LineBox {
+    pub(super) ascent: f32,
+    pub(super) descent: f32,
+    pub(super) line_gap: f32,
+    /// Largest font size (dpem)
+    pub(super) dpem: f32,
+}
+
+impl LineBox {
+    /// Extend to hold `run`
+    pub(super) fn add_run(
+        &mut self,
+        spacing: &LineSpacing,
+        fonts: &FontLibrary,
+        run: &GlyphRun,
+        extra: &RunExtra,
+    ) {
+        let face_id = match spacing.fallback_metrics {
+            true => run.face_id,
+            false => extra.primary_face.unwrap_or(run.face_id),
+        };
+        let sf = fonts.get_face(face_id).scale_by_dpem(run.dpem);
+        let (mut ascent, mut descent) = (sf.ascent(), sf.descent());
+        if let Some(object) = extra.object {
+            (ascent, descent) = (object.ascent, object.descent);
+        }
+        if extra.orientation != Orientation::Horizontal {
+            // Vertical text is centered on the baseline
+            let half = 0.5 * (ascent - descent);
+            (ascent, descent) = (half, -half);
+        }
+        let mut line_gap = 0.0;
+        let height = match spacing.height {
+            LineHeight::Normal => None,
+            LineHeight::Px(px) => Some(px),
+            LineHeight::Em(em) => Some(em * run.dpem),
+        };
+        if extra.object.is_some() {
+            // Objects are boxes: leading is not applied
+        } else if let Some(height) = height {
+            let half_leading = 0.5 * (height - (ascent - descent));
+            ascent += half_leading;
+            descent -= half_leading;
+        } else if spacing.line_gap {
+            line_gap = sf.line_gap();
+        }
+
+        self.ascent = self.ascent.max(ascent);
+        self.descent = self.descent.min(descent);
+        self.line_gap = self.line_gap.max(line_gap);
+        self.dpem = self.dpem.max(run.dpem);
+    }
+}
+
+/// Vertical position tracking for line layout
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct VCaret {
+    spacing: LineSpacing,
+    lines: usize,
+    line_gap: f32,
+    /// Space after the previous paragraph, if it ended on the previous line
+    para_after: Option<f32>,
+    /// Position of the bottom of the last line
+    pub(super) vcaret: f32,
+}
+
+impl VCaret {
+    pub(super) fn new(spacing: LineSpacing) -> Self {
+        VCaret {
+            spacing,
+            ..Default::default()
+        }
+    }
+
+    /// Get the top and bottom positions of the next line, given its box
+    pub(super) fn line_band(&self, line: &LineBox) -> (f32, f32) {
+        let mut top = self.vcaret;
+        if self.lines > 0 {
+            top += line.line_gap.max(self.line_gap);
+            if let Some(after) = self.para_after {
+                top += after + self.spacing.before_paragraph.to_px(line.dpem);
+            }
+        }
+        (top, top + line.ascent - line.descent)
+    }
+
+    /// Begin a line, returning the baseline position
+    pub(super) fn begin_line(&mut self, line: &LineBox) -> f32 {
+        self.vcaret = self.line_band(line).0;
+        self.vcaret + line.ascent
+    }
+
+    /// End a line, returning the (rounded) bottom position
+    ///
+    /// If `is_para_end`, the line ends a paragraph.
+    pub(super) fn end_line(&mut self, line: &LineBox, is_para_end: bool) -> f32 {
+        self.vcaret = self.vcaret + line.ascent - line.descent;
+        // Vertically align lines to the nearest pixel (improves rendering):
+        self.vcaret = self.vcaret.round();
+        self.line_gap = line.line_gap;
+        self.para_after = is_para_end.then(|| self.spacing.after_paragraph.to_px(line.dpem));
+        self.lines += 1;
+        self.vcaret
+    }
+}
+
+impl Forme {
+    /// Get line spacing options
+    #[inline]
+    pub fn line_spacing(&self) -> LineSpacing {
+        self.config.line_spacing
+    }
+
+    /// Set line spacing options
+    ///
+    /// Changes take effect on the next call to [`Self::prepare_lines`] or
+    /// [`Self::measure_height`].
+    #[inline]
+    pub fn set_line_spacing(&mut self, spacing: LineSpacing) {
+        self.config.line_spacing = spacing;
+        self.valid_lines = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fonts::FontSelector;
+    use crate::forme::test_util::shaped;
+    use crate::{Align, Direction};
+
+    fn tops(forme: &Forme) -> Vec<f32> {
+        forme.lines().map(|line| line.top()).collect()
+    }
+
+    #[test]
+    fn line_height() {
+        let text = "One\nTwo\nThree";
+        let spacing = LineSpacing {
+            height: LineHeight::Px(30.0),
+            ..Default::default()
+        };
+        let mut forme = shaped(text, |forme| forme.set_line_spacing(spacing));
+        let height = forme.measure_height(500.0, None);
+        assert_eq!(forme.prepare_lines(500.0, 500.0, Align::Default), height);
+        assert_eq!(tops(&forme), [0.0, 30.0, 60.0]);
+        assert_eq!(height, 90.0);
+
+        let spacing = LineSpacing {
+            height: LineHeight::Em(2.0),
+            ..Default::default()
+        };
+        let mut forme = shaped(text, |forme| forme.set_line_spacing(spacing));
+        let height = forme.measure_height(500.0, None);
+        assert_eq!(forme.prepare_lines(500.0, 500.0, Align::Default), height);
+        assert_eq!(tops(&forme), [0.0, 32.0, 64.0]);
+        assert_eq!(height, 96.0);
+    }
+
+    #[test]
+    fn line_gap() {
+        let text = "One\nTwo";
+        let spacing = LineSpacing {
+            line_gap: false,
+            ..Default::default()
+        };
+        let mut forme = shaped(text, |forme| forme.set_line_spacing(spacing));
+        forme.prepare_lines(500.0, 500.0, Align::Default);
+        let line0 = forme.get_line(0).unwrap();
+        assert_eq!(forme.get_line(1).unwrap().top(), line0.bottom());
+
+        let mut forme = shaped(text, |_| ());
+        forme.prepare_lines(500.0, 500.0, Align::Default);
+        let line0 = forme.get_line(0).unwrap();
+        assert!(forme.get_line(1).unwrap().top() >= line0.bottom());
+    }
+
+    #[test]
+    fn paragraph_spacing() {
+        let text = "One two\nThree";
+        let spacing = LineSpacing {
+            height: LineHeight::Px(20.0),
+            before_paragraph: Length::Px(4.0),
+            after_paragraph: Length::Em(0.5),
+            ..Default::default()
+        };
+        // Wrap "One two" over two lines
+        let mut forme = shaped(text, |forme| forme.set_line_spacing(spacing));
+        let height = forme.measure_height(40.0, None);
+        assert_eq!(forme.prepare_lines(40.0, 40.0, Align::Default), height);
+        assert_eq!(tops(&forme), [0.0, 20.0, 52.0]);
+        assert_eq!(height, 72.0);
+    }
+
+    #[test]
+    fn fallback_metrics() {
+        // The math font does not cover Hebrew, thus a fallback face is used
+        let math = FontSelector::parse_css("math").unwrap();
+        let height = |text: &str, fallback_metrics, split, font| {
+            let mut forme = Forme::default();
+            forme.set_line_spacing(LineSpacing {
+                fallback_metrics,
+                ..Default::default()
+            });
+            let mut appender = forme.set_text(text, Direction::Auto);
+            assert!(
+                appender
+                    .with_font(..split, FontSelector::default(), 16.0)
+                    .unwrap()
+                    .with_font(split.., font, 32.0)
+                    .is_ok()
+            );
+            forme.prepare_lines(500.0, 500.0, Align::Default)
+        };
+
+        let text = "ab אב";
+        let fallback = height("אב", true, 0, FontSelector::default());
+        let primary = height("ab", true, 0, math);
+        assert!(fallback > primary);
+        assert_eq!(height(text, true, 3, math), fallback);
+        assert_eq!(height(text, false, 3, math), primary);
+    }
+}
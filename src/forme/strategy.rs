@@ -0,0 +1,403 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Text preparation: line-breaking strategies
+
+use super::wrap_lines::hyphen_glyph;
+use super::{Forme, RunSpecial};
+use crate::fonts::FontLibrary;
+
+/// Line-breaking strategy
+///
+/// See [`Forme::set_wrap_strategy`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WrapStrategy {
+    /// Each line holds as much content as fits
+    #[default]
+    Greedy,
+    /// Lines of each paragraph have similar lengths
+    ///
+    /// The number of lines is the same as with [`WrapStrategy::Greedy`]. This
+    /// is similar to CSS `text-wrap: balance` and is intended for headings
+    /// and other short texts.
+    Balance,
+    /// Minimize raggedness over each paragraph
+    ///
+    /// This is the total-fit algorithm of Knuth and Plass: line breaks are
+    /// chosen to minimize the sum of *demerits* over all lines of the
+    /// paragraph. A line's demerits increase with the space remaining at its
+    /// end (excepting the last line of a paragraph) and when it ends with a
+    /// hyphen. This is intended for long-form text, especially with
+    /// [`Align::Stretch`](crate::Align::Stretch).
+    Optimal,
+}
+
+/// Demerits per line, favouring fewer lines
+const LINE_PENALTY: f32 = 10.0;
+/// Penalty for wrapping at a hyphenation point
+const HYPHEN_PENALTY: f32 = 50.0;
+/// Demerits per pixel of over-long lines
+const OVERFULL_PENALTY: f32 = 1.0e8;
+
+/// A possible line break
+#[derive(Clone, Copy, Debug)]
+struct Break {
+    /// Position as `(run, part)`
+    pos: (usize, usize),
+    /// Position of the end of content preceding the break, excluding
+    /// trailing white-space
+    end: f32,
+    /// Position of the start of content following the break
+    next: f32,
+    /// Length of the hyphen displayed when wrapping here
+    hyphen: f32,
+}
+
+/// Line breaks planned for a paragraph
+#[derive(Debug)]
+pub(super) struct LinePlan {
+    /// Wrapping positions as `(run, part)`, in order
+    breaks: Vec<(usize, usize)>,
+    next: usize,
+}
+
+impl LinePlan {
+    /// True if the current line should wrap after `pos = (run, part)`
+    #[inline]
+    pub(super) fn wraps_at(&self, pos: (usize, usize)) -> bool {
+        self.breaks.get(self.next).is_some_and(|b| pos >= *b)
+    }
+
+    /// Advance to the next line
+    #[inline]
+    pub(super) fn next_line(&mut self) {
+        self.next += 1;
+    }
+}
+
+impl Forme {
+    /// Get the line-breaking strategy
+    #[inline]
+    pub fn wrap_strategy(&self) -> WrapStrategy {
+        self.config.wrap_strategy
+    }
+
+    /// Set the line-breaking strategy
+    ///
+    /// This affects [`Self::prepare_lines`], [`Self::prepare_box`] and
+    /// [`Self::measure_height`] (which thus agree on the number of lines).
+    /// [`Self::measure_width`] is unaffected since it measures unwrapped
+    /// lines. Changes take effect on the next call to [`Self::prepare_lines`].
+    ///
+    /// Strategies other than [`WrapStrategy::Greedy`] consider the whole of
+    /// each paragraph. They are not applied to paragraphs containing tabs or
+    /// words too long to fit a line (thus broken by
+    /// [`OverflowWrap::Anywhere`](super::OverflowWrap::Anywhere)) or
+    /// paragraphs broken by [`Self::prepare_lines_in`], which use greedy
+    /// wrapping instead.
+    #[inline]
+    pub fn set_wrap_strategy(&mut self, strategy: WrapStrategy) {
+        self.config.wrap_strategy = strategy;
+        self.valid_lines = 0;
+    }
+
+    /// Plan line breaks for the paragraph from `start = (run, part)`
+    ///
+    /// Widths available are `widths.0` for the first line and `widths.1`
+    /// for other lines. Returns `None` when wrapping greedily.
+    pub(super) fn plan_lines(
+        &self,
+        fonts: &FontLibrary,
+        start: (usize, usize),
+        widths: (f32, f32),
+    ) -> Option<LinePlan> {
+        if self.config.wrap_strategy == WrapStrategy::Greedy || !widths.1.is_finite() {
+            return None;
+        }
+        let breaks = self.paragraph_breaks(fonts, start, widths)?;
+        let chosen = match self.config.wrap_strategy {
+            WrapStrategy::Greedy => unreachable!(),
+            WrapStrategy::Balance => balance(&breaks, widths),
+            WrapStrategy::Optimal => optimal(&breaks, widths),
+        };
+        Some(LinePlan {
+            breaks: chosen.into_iter().map(|i| breaks[i].pos).collect(),
+            next: 0,
+        })
+    }
+
+    /// List possible breaks of the paragraph from `start`
+    ///
+    /// The last item is the end of the paragraph. Emergency breaks are
+    /// skipped. Returns `None` if the paragraph contains a tab or a word
+    /// which does not fit `widths` and thus requires emergency breaks.
+    fn paragraph_breaks(
+        &self,
+        fonts: &FontLibrary,
+        start: (usize, usize),
+        widths: (f32, f32),
+    ) -> Option<Vec<Break>> {
+        let min_width = widths.0.min(widths.1);
+        let mut breaks = vec![];
+        let mut caret = 0.0;
+        let mut word_start = 0.0;
+        let mut emergency = false;
+        let mut last_part = start.1;
+        for run_index in start.0..self.runs.len() {
+            let run = &self.runs[run_index];
+            if run.special == RunSpecial::HTab {
+                return None;
+            }
+            let num_parts = run.num_parts();
+            let allow_break = run.allows_break_at_end(self.run_extras.get(run_index).no_wrap);
+
+            for part_index in last_part + 1..=num_parts {
+                if run.part_end_emergency(part_index) {
+                    emergency = true;
+                    continue;
+                }
+                let part = run.part_lengths(last_part..part_index);
+                let end = caret + part.len_no_space;
+                caret += part.len;
+                if part_index < num_parts || allow_break {
+                    if emergency && end - word_start > min_width {
+                        return None;
+                    }
+                    word_start = caret;
+                    emergency = false;
+
+                    let mut hyphen = 0.0;
+                    if run.part_end_hyphen(part_index)
+                        && let Some((_, len)) = hyphen_glyph(fonts, run)
+                    {
+                        hyphen = len;
+                    }
+                    breaks.push(Break {
+                        pos: (run_index, part_index),
+                        end,
+                        next: caret,
+                        hyphen,
+                    });
+                }
+                last_part = part_index;
+            }
+            last_part = 0;
+
+            if run.special == RunSpecial::HardBreak {
+                break;
+            }
+        }
+        Some(breaks)
+    }
+}
+
+/// Length of a line from `node` (`0` or the break `node - 1`) to `breaks[j]`
+fn line_len(breaks: &[Break], node: usize, j: usize) -> f32 {
+    let start = if node == 0 {
+        0.0
+    } else {
+        breaks[node - 1].next
+    };
+    let hyphen = if j + 1 < breaks.len() {
+        breaks[j].hyphen
+    } else {
+        0.0
+    };
+    breaks[j].end - start + hyphen
+}
+
+/// Width available to a line from `node`
+fn line_width(widths: (f32, f32), node: usize) -> f32 {
+    if node == 0 { widths.0 } else { widths.1 }
+}
+
+/// Choose breaks greedily with widths reduced by `d`
+fn greedy(breaks: &[Break], widths: (f32, f32), d: f32) -> Vec<usize> {
+    let mut chosen = vec![];
+    let mut node = 0;
+    while node < breaks.len() {
+        let width = line_width(widths, node) - d;
+        let start = if node == 0 {
+            0.0
+        } else {
+            breaks[node - 1].next
+        };
+        let mut best = node;
+        let mut j = node + 1;
+        while j < breaks.len() && breaks[j].end - start <= width {
+            if line_len(breaks, node, j) <= width {
+                best = j;
+            }
+            j += 1;
+        }
+        if best + 1 == breaks.len() {
+            break;
+        }
+        chosen.push(best);
+        node = best + 1;
+    }
+    chosen
+}
+
+/// Choose breaks to minimize the width used without adding lines
+fn balance(breaks: &[Break], widths: (f32, f32)) -> Vec<usize> {
+    let mut chosen = greedy(breaks, widths, 0.0);
+    if chosen.is_empty() {
+        return chosen;
+    }
+
+    // Binary search for the largest reduction in width preserving the
+    // number of lines
+    let count = chosen.len();
+    let mut low = 0.0;
+    let mut high = widths.0.min(widths.1);
+    for _ in 0..16 {
+        let mid = 0.5 * (low + high);
+        let result = greedy(breaks, widths, mid);
+        if result.len() <= count {
+            low = mid;
+            chosen = result;
+        } else {
+            high = mid;
+        }
+    }
+    chosen
+}
+
+/// Choose breaks to minimize total demerits
+fn optimal(breaks: &[Break], widths: (f32, f32)) -> Vec<usize> {
+    let n = breaks.len();
+    // For each node: (total demerits, previous node)
+    let mut best = vec![(f32::INFINITY, 0); n + 1];
+    best[0].0 = 0.0;
+
+    for j in 0..n {
+        let mut node = j + 1;
+        while node > 0 {
+            node -= 1;
+            let width = line_width(widths, node);
+            let len = line_len(breaks, node, j);
+            let demerits = if len > width {
+                if node < j {
+                    // Longer lines will not fit either, excepting a wider first line
+                    node = node.min(1);
+                    continue;
+                }
+                OVERFULL_PENALTY * (1.0 + len - width)
+            } else if j + 1 == n {
+                0.0
+            } else {
+                let ratio = (width - len) / width.max(1.0);
+                let badness = 100.0 * ratio.powi(3);
+                let penalty = if breaks[j].hyphen > 0.0 {
+                    HYPHEN_PENALTY
+                } else {
+                    0.0
+                };
+                (LINE_PENALTY + badness).powi(2) + penalty.powi(2)
+            };
+
+            let total = best[node].0 + demerits;
+            if total < best[j + 1].0 {
+                best[j + 1] = (total, node);
+            }
+        }
+    }
+
+    let mut chosen = vec![];
+    let mut node = best[n].1;
+    while node > 0 {
+        chosen.push(node - 1);
+        node = best[node].1;
+    }
+    chosen.reverse();
+    chosen
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Align;
+    use crate::forme::test_util::{glyph_pos, line_ranges, shaped};
+
+    /// The greatest inline extent of any line
+    fn max_line_len(forme: &Forme, text: &str) -> f32 {
+        forme
+            .lines()
+            .map(|line| {
+                let end = text[line.text_range()].trim_end().len() + line.text_range().start;
+                glyph_pos(forme, end).0
+            })
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn balance() {
+        let text = "aaa bbb ccc ddd eee fff ggg";
+        let mut greedy = shaped(text, |forme| forme.set_wrap_strategy(WrapStrategy::Greedy));
+        let width = greedy.measure_width(f32::INFINITY) * 0.7;
+        greedy.prepare_lines(width, width, Align::Default);
+        assert_eq!(greedy.num_lines(), 2);
+
+        let mut forme = shaped(text, |forme| forme.set_wrap_strategy(WrapStrategy::Balance));
+        let height = forme.measure_height(width, None);
+        assert_eq!(forme.prepare_lines(width, width, Align::Default), height);
+        assert_eq!(forme.num_lines(), 2);
+        assert_eq!(line_ranges(&forme), [0..11, 12..27]);
+        assert!(max_line_len(&forme, text) < max_line_len(&greedy, text));
+
+        // Text fitting on one line is unaffected
+        forme.prepare_lines(1000.0, 1000.0, Align::Default);
+        assert_eq!(forme.num_lines(), 1);
+    }
+
+    #[test]
+    fn optimal() {
+        // Greedy wrapping leaves a short second line
+        let text = "aaa bb cc ddddd";
+        let mut greedy = shaped(text, |forme| forme.set_wrap_strategy(WrapStrategy::Greedy));
+        let width = greedy.measure_width(f32::INFINITY) * 0.43;
+        greedy.prepare_lines(width, width, Align::Default);
+
+        let mut forme = shaped(text, |forme| forme.set_wrap_strategy(WrapStrategy::Optimal));
+        let height = forme.measure_height(width, None);
+        assert_eq!(forme.prepare_lines(width, width, Align::Default), height);
+        assert_eq!(forme.num_lines(), greedy.num_lines());
+        assert_eq!(line_ranges(&greedy), [0..6, 7..9, 10..15]);
+        assert_eq!(line_ranges(&forme), [0..3, 4..9, 10..15]);
+        assert!(forme.bounding_box().1.0 <= width);
+    }
+
+    #[test]
+    fn hyphen_penalty() {
+        let breaks = |hyphen| {
+            [
+                Break {
+                    pos: (0, 1),
+                    end: 45.0,
+                    next: 50.0,
+                    hyphen: 0.0,
+                },
+                Break {
+                    pos: (0, 2),
+                    end: 55.0,
+                    next: 56.0,
+                    hyphen,
+                },
+                Break {
+                    pos: (0, 3),
+                    end: 110.0,
+                    next: 110.0,
+                    hyphen: 0.0,
+                },
+            ]
+        };
+        let widths = (60.0, 60.0);
+        assert_eq!(super::optimal(&breaks(0.0), widths), [1]);
+        // The hyphenated break fits better but is penalized
+        assert_eq!(super::optimal(&breaks(2.0), widths), [0]);
+    }
+}
@@ -0,0 +1,294 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Text preparation: tab stops
+
+use super::{Forme, RunSpecial};
+use crate::fonts::FontLibrary;
+
+/// Alignment of text at a [`TabStop`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TabAlign {
+    /// Following text starts at the stop
+    ///
+    /// (Following text is left of the stop in right-to-left paragraphs.)
+    #[default]
+    Start,
+    /// Following text ends at the stop
+    End,
+    /// Following text is centered on the stop
+    Center,
+    /// The first instance of the given decimal separator is aligned to the stop
+    ///
+    /// Text without a separator is aligned as with [`TabAlign::End`].
+    Decimal(char),
+}
+
+/// An explicit tab stop
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TabStop {
+    /// Position of the stop (pixels)
+    ///
+    /// This is the distance from the start of the line (the right edge of
+    /// lines in right-to-left paragraphs).
+    pub position: f32,
+    /// Alignment of text following the tab
+    pub align: TabAlign,
+}
+
+/// Interval between uniformly spaced tab stops
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TabInterval {
+    /// An interval of the given number of spaces
+    ///
+    /// The width of a space is taken from the font preceding the tab.
+    Spaces(f32),
+    /// An interval in pixels
+    Px(f32),
+}
+
+impl Default for TabInterval {
+    /// Eight spaces
+    fn default() -> Self {
+        TabInterval::Spaces(8.0)
+    }
+}
+
+/// Tab stop configuration
+///
+/// Each tab advances to the first explicit stop (from [`Self::stops`]) able
+/// to accommodate the preceding and following text, or otherwise to the next
+/// uniformly spaced stop (see [`Self::interval`]) with [`TabAlign::Start`]
+/// alignment.
+///
+/// Text following a tab is measured up to the next tab or the end of the
+/// line. The default configuration has no explicit stops and a uniform
+/// interval of eight spaces.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TabStops {
+    /// Explicit stops, ordered by position
+    pub stops: Vec<TabStop>,
+    /// Interval between uniform stops
+    pub interval: TabInterval,
+}
+
+impl TabStops {
+    /// Construct with a uniform `interval` only
+    #[inline]
+    pub fn uniform(interval: TabInterval) -> Self {
+        TabStops {
+            stops: vec![],
+            interval,
+        }
+    }
+
+    /// Add an explicit stop
+    ///
+    /// Stops must be added in order of increasing `position`.
+    #[inline]
+    pub fn with_stop(mut self, position: f32, align: TabAlign) -> Self {
+        debug_assert!(self.stops.last().is_none_or(|s| s.position < position));
+        self.stops.push(TabStop { position, align });
+        self
+    }
+}
+
+impl Forme {
+    /// Calculate the advance of the tab following run `run_index`
+    ///
+    /// The run must have [`RunSpecial::HTab`]. Input `caret` is the line
+    /// length up to the tab (the end of the run) and `width` is the available
+    /// line width, beyond which following text is not measured.
+    pub(super) fn tab_advance(
+        &self,
+        fonts: &FontLibrary,
+        run_index: usize,
+        caret: f32,
+        width: f32,
+    ) -> f32 {
+        let run = &self.runs[run_index];
+        debug_assert_eq!(run.special, RunSpecial::HTab);
+        let tabs = &self.config.tab_stops;
+
+        if !tabs.stops.is_empty() {
+            // Measure following text up to the next tab or line end, and (if
+            // `decimal` is given) up to the first instance of that separator
+            let measure = |decimal: Option<char>| {
+                let mut len = 0.0;
+                for run in &self.runs[run_index + 1..] {
+                    if caret + len >= width {
+                        break;
+                    }
+
+                    if let Some(c) = decimal {
+                        let face = fonts.get_face(run.face_id);
+                        let id = face.glyph_index(c);
+                        if id.0 != 0
+                            && let Some(glyph) = run.glyphs.iter().find(|g| g.id == id)
+                        {
+                            let mut x = glyph.position.0;
+                            if run.level.is_rtl() {
+                                let advance = face.scale_by_dpu(run.dpu).h_advance(id);
+                                x = run.caret - x - advance;
+                            }
+                            return Some(len + x);
+                        }
+                    }
+
+                    let part = run.part_lengths(0..run.num_parts());
+                    if matches!(run.special, RunSpecial::HTab | RunSpecial::HardBreak) {
+                        len += part.len_no_space;
+                        break;
+                    }
+                    len += part.len;
+                }
+                decimal.is_none().then_some(len)
+            };
+            let len = measure(None).unwrap_or(0.0);
+
+            for stop in &tabs.stops {
+                let advance = stop.position
+                    - caret
+                    - match stop.align {
+                        TabAlign::Start => 0.0,
+                        TabAlign::End => len,
+                        TabAlign::Center => 0.5 * len,
+                        TabAlign::Decimal(c) => measure(Some(c)).unwrap_or(len),
+                    };
+                if advance > 0.0 {
+                    return advance;
+                }
+            }
+        }
+
+        let interval = match tabs.interval {
+            TabInterval::Spaces(n) => {
+                let sf = fonts.get_face(run.face_id).scale_by_dpu(run.dpu);
+                sf.h_advance(sf.face().glyph_index(' ')) * n
+            }
+            TabInterval::Px(px) => px,
+        };
+        if !(interval > 0.0) {
+            return 0.0;
+        }
+        let stops = (caret / interval).floor() + 1.0;
+        interval * stops - caret
+    }
+
+    /// Get tab stops
+    #[inline]
+    pub fn tab_stops(&self) -> &TabStops {
+        &self.config.tab_stops
+    }
+
+    /// Set tab stops
+    ///
+    /// Changes take effect on the next call to [`Self::prepare_lines`].
+    #[inline]
+    pub fn set_tab_stops(&mut self, tab_stops: TabStops) {
+        self.config.tab_stops = tab_stops;
+        self.valid_lines = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Align;
+    use crate::fonts;
+    use crate::forme::test_util::{glyph_pos, shaped};
+
+    fn space_width(forme: &Forme) -> f32 {
+        let run = &forme.runs[0];
+        let sf = fonts::library().get_face(run.face_id).scale_by_dpu(run.dpu);
+        sf.h_advance(sf.face().glyph_index(' '))
+    }
+
+    #[test]
+    fn uniform() {
+        let text = "a\tb\tc";
+        let mut forme = shaped(text, |_| ());
+        forme.prepare_lines(f32::INFINITY, 500.0, Align::Default);
+        let tab = 8.0 * space_width(&forme);
+        assert!((glyph_pos(&forme, 2).0 - tab).abs() < 0.01);
+        assert!((glyph_pos(&forme, 4).0 - 2.0 * tab).abs() < 0.01);
+        assert!(forme.measure_width(f32::INFINITY) > 2.0 * tab);
+
+        let mut forme = shaped(text, |forme| {
+            forme.set_tab_stops(TabStops::uniform(TabInterval::Px(30.0)))
+        });
+        forme.prepare_lines(f32::INFINITY, 500.0, Align::Default);
+        assert!((glyph_pos(&forme, 2).0 - 30.0).abs() < 0.01);
+        assert!((glyph_pos(&forme, 4).0 - 60.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn aligned_stops() {
+        let tabs = TabStops::default()
+            .with_stop(50.0, TabAlign::End)
+            .with_stop(150.0, TabAlign::Decimal('.'))
+            .with_stop(250.0, TabAlign::Center);
+        let text = "a\t12\t3.25\tmid\nb\t345\t67.5\tmiddle";
+        let mut forme = shaped(text, |forme| forme.set_tab_stops(tabs));
+        forme.prepare_lines(f32::INFINITY, 500.0, Align::Default);
+
+        let line2 = text.find('\n').unwrap() + 1;
+        for start in [0, line2] {
+            let end_12 = text[start..].find('\t').unwrap() + 1;
+            let end_12 = start + end_12 + text[start + end_12..].find('\t').unwrap();
+            assert!((glyph_pos(&forme, end_12).0 - 50.0).abs() < 0.01);
+
+            let decimal = start + text[start..].find('.').unwrap();
+            assert!((glyph_pos(&forme, decimal).0 - 150.0).abs() < 0.01);
+
+            let center = start + text[start..].rfind('\t').unwrap() + 1;
+            let center_end = text[center..]
+                .find('\n')
+                .map(|i| center + i)
+                .unwrap_or(text.len());
+            let mid = 0.5 * (glyph_pos(&forme, center).0 + glyph_pos(&forme, center_end).0);
+            assert!((mid - 250.0).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn decimal_separators() {
+        let tabs = TabStops::default()
+            .with_stop(100.0, TabAlign::Decimal('.'))
+            .with_stop(200.0, TabAlign::Decimal(','));
+        let text = "a\t1,5.25\t3,75";
+        let mut forme = shaped(text, |forme| forme.set_tab_stops(tabs));
+        forme.prepare_lines(f32::INFINITY, 500.0, Align::Default);
+
+        let point = text.find('.').unwrap();
+        assert!((glyph_pos(&forme, point).0 - 100.0).abs() < 0.01);
+        let comma = text.rfind(',').unwrap();
+        assert!((glyph_pos(&forme, comma).0 - 200.0).abs() < 0.01);
+
+        // A separator missing from the font does not match other missing
+        // glyphs; text is then end-aligned
+        let tabs = TabStops::default().with_stop(100.0, TabAlign::Decimal('\u{4e01}'));
+        let text = "a\t1\u{4e00}2";
+        let mut forme = shaped(text, |forme| forme.set_tab_stops(tabs));
+        forme.prepare_lines(f32::INFINITY, 500.0, Align::Default);
+        assert!((forme.bounding_box().1.0 - 100.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn rtl() {
+        let tabs = TabStops::default().with_stop(50.0, TabAlign::Start);
+        let text = "אב\tגד";
+        let width = 300.0;
+        let mut forme = shaped(text, |forme| forme.set_tab_stops(tabs));
+        forme.prepare_lines(f32::INFINITY, width, Align::Default);
+        assert_eq!(forme.line_is_rtl(0), Some(true));
+        let index = text.find('ג').unwrap();
+        assert!((glyph_pos(&forme, index).0 - (width - 50.0)).abs() < 0.01);
+    }
+}
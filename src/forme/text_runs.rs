@@ -5,22 +5,25 @@
 
 //! Text preparation: line breaking and BIDI
 
-use super::Forme;
+use super::{Forme, InlineObject, OverflowWrap, ParagraphFormat, WhiteSpace};
 #[allow(unused)]
 use crate::Status;
 use crate::conv::{to_u32, to_usize};
 use crate::fonts::{self, FaceId, FontSelector, NoFontMatch};
-use crate::util::{AnalyzedText, ends_with_hard_break, to_fontique_script};
-use crate::{Direction, FontToken, Range, shaper, shaper::GlyphRun};
+use crate::hyphenation;
+use crate::shaper::{GlyphRun, Orientation, RunExtra};
+use crate::util::{AnalyzedText, ends_with_hard_break, paragraph_range, to_fontique_script};
+use crate::{Direction, FontToken, Range, shaper};
 use icu_properties::CodePointMapData;
 use icu_properties::props::{
     BinaryProperty, DefaultIgnorableCodePoint, EmojiModifier, EmojiPresentation, RegionalIndicator,
-    Script,
+    Script, VerticalOrientation,
 };
-use icu_segmenter::LineSegmenter;
 use icu_segmenter::options::{LineBreakStrictness, LineBreakWordOption};
+use icu_segmenter::{LineSegmenter, LineSegmenterBorrowed};
 use std::ops::Bound;
 use std::sync::OnceLock;
+use unicode_bidi::Level;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub(crate) enum RunSpecial {
@@ -31,6 +34,8 @@ pub(crate) enum RunSpecial {
     NoBreak,
     /// Run is a horizontal tab (run is a single char only)
     HTab,
+    /// Run ends with a break which displays a hyphen when wrapped
+    Hyphen,
 }
 
 impl Forme {
@@ -89,19 +94,132 @@ impl Forme {
     ///    `shaping` crate feature) or just does kerning. (Differences between
     ///    the two methods are most apparent when using emojis or complex
     ///    scripts such as Arabic.)
-    //
-    // Note: the only real difficulty in adding `fn push_text(..)` (to support
-    // using multiple disjoint pieces of text) is that text indices get used in
-    // various places; such a method would need to offset all text indices used
-    // in GlyphRun to make them distinct and correctly ordered.
+    ///
+    /// See also [`Self::edit_text`] and [`Self::append_text`].
     #[must_use = "set_text(..) has no effect without calling with_tokens(..) or with_font(..) on the result"]
     pub fn set_text<'a>(&'a mut self, text: &'a str, direction: Direction) -> Appender<'a> {
         self.clear();
+        self.text_len = text.len();
         Appender {
             forme: self,
             text: AnalyzedText::new(text, direction),
+            range: 0..text.len(),
+            text_len: text.len(),
+            insert_at: 0,
+        }
+    }
+
+    /// Replace a range of text, re-shaping only affected paragraphs
+    ///
+    /// This is an alternative to [`Self::set_text`] for use after small edits.
+    /// `text` is the new text, in which `old_range` of the previous text has
+    /// been replaced by `new_len` bytes of new content.
+    ///
+    /// Paragraphs (as yielded by [`LineIterator`]) containing the edit are
+    /// analyzed and shaped as by [`Self::set_text`] while glyph runs of other
+    /// paragraphs are retained (with adjusted text indices). Call
+    /// [`Appender::with_tokens`] or [`Appender::with_font`] on the result
+    /// using the same font tokens or ranges as for the whole of `text`;
+    /// these are clipped to the affected range (likewise for
+    /// [`Appender::with_paragraph`]; formats of other paragraphs are
+    /// retained). Subsequently,
+    /// [`Self::prepare_lines`] re-wraps lines from the first affected
+    /// paragraph (when called with the same arguments as previously).
+    ///
+    /// The result should be identical to that of [`Self::set_text`] except
+    /// where the [`Direction::AutoRtl`] heuristic would select a different
+    /// direction for the whole `text`.
+    ///
+    /// [`LineIterator`]: crate::LineIterator
+    #[must_use = "edit_text(..) has no effect without calling with_tokens(..) or with_font(..) on the result"]
+    pub fn edit_text<'a>(
+        &'a mut self,
+        text: &'a str,
+        direction: Direction,
+        old_range: std::ops::Range<usize>,
+        new_len: usize,
+    ) -> Appender<'a> {
+        debug_assert!(old_range.start <= old_range.end);
+        let new_end = old_range.start + new_len;
+        let delta = to_u32(new_end).wrapping_sub(to_u32(old_range.end));
+        let range = paragraph_range(text, old_range.start..new_end);
+
+        // Runs are in logical order. Runs starting within range (in the old
+        // text) are replaced. If range reaches the end of text, this includes
+        // any empty run representing the final line.
+        let old_end = to_usize(to_u32(range.end).wrapping_sub(delta));
+        let head = self
+            .runs
+            .iter()
+            .take_while(|run| to_usize(run.range.start) < range.start)
+            .count();
+        let mut tail = self.runs.len();
+        if range.end < text.len() {
+            tail = head
+                + self.runs[head..]
+                    .iter()
+                    .take_while(|run| to_usize(run.range.start) < old_end)
+                    .count();
+        }
+        self.runs.drain(head..tail);
+        for run in &mut self.runs[head..] {
+            run.shift_text(delta);
+        }
+        self.run_extras.remap(|i| match i {
+            _ if i < head => Some(i),
+            _ if i < tail => None,
+            _ => Some(i - (tail - head)),
+        });
+
+        let para_head = self
+            .paragraphs
+            .partition_point(|(i, _)| to_usize(*i) < range.start);
+        let mut para_tail = self.paragraphs.len();
+        if range.end < text.len() {
+            para_tail = self
+                .paragraphs
+                .partition_point(|(i, _)| to_usize(*i) < old_end);
+        }
+        self.paragraphs.drain(para_head..para_tail);
+        for (index, _) in &mut self.paragraphs[para_head..] {
+            *index = index.wrapping_add(delta);
+        }
+
+        let lines = self
+            .lines
+            .iter()
+            .take_while(|line| line.text_range().start < range.start)
+            .count();
+        self.valid_lines = self.valid_lines.min(lines);
+        self.text_len = text.len();
+
+        Appender {
+            forme: self,
+            text: AnalyzedText::new(&text[range.clone()], direction),
+            range,
+            text_len: text.len(),
+            insert_at: head,
         }
     }
+
+    /// Append text, re-shaping only new paragraphs
+    ///
+    /// `text` is the whole text: the previous text (as passed to
+    /// [`Self::set_text`] or a previous edit) followed by new content. This is
+    /// equivalent to [`Self::edit_text`] with an empty `old_range` at the end
+    /// of the previous text. In case the previous text did not end with a
+    /// mandatory line break, its last paragraph is re-shaped.
+    ///
+    /// Call [`Appender::with_tokens`] or [`Appender::with_font`] on the result
+    /// using the same font tokens or ranges as for the whole of `text`.
+    /// Subsequently, [`Self::prepare_lines`] only wraps new paragraphs (when
+    /// called with the same arguments as previously).
+    #[must_use = "append_text(..) has no effect without calling with_tokens(..) or with_font(..) on the result"]
+    pub fn append_text<'a>(&'a mut self, text: &'a str, direction: Direction) -> Appender<'a> {
+        let len = self.text_len;
+        debug_assert!(len <= text.len());
+        self.edit_text(text, direction, len..len, text.len() - len)
+    }
 }
 
 /// A shim for appending text runs
@@ -110,10 +228,36 @@ impl Forme {
 #[must_use]
 pub struct Appender<'a> {
     forme: &'a mut Forme,
+    /// The analyzed range of the text
     text: AnalyzedText<'a>,
+    /// Range of the whole text represented by `text`
+    range: std::ops::Range<usize>,
+    /// Length of the whole text
+    text_len: usize,
+    /// Index in `forme.runs` at which to insert new runs
+    insert_at: usize,
 }
 
 impl<'a> Appender<'a> {
+    /// Move runs pushed since `start` into place
+    fn place_runs(&mut self, start: usize) {
+        let runs = &mut self.forme.runs;
+        if self.range.start > 0 {
+            for run in &mut runs[start..] {
+                run.shift_text(to_u32(self.range.start));
+            }
+        }
+        let len = runs.len() - start;
+        runs[self.insert_at..].rotate_right(len);
+        let insert_at = self.insert_at;
+        self.forme.run_extras.remap(|i| match i {
+            _ if i < insert_at => Some(i),
+            _ if i < start => Some(i + len),
+            _ => Some(i - start + insert_at),
+        });
+        self.insert_at += len;
+    }
+
     /// Use a custom line-break strictness
     ///
     /// This only affects subsequent calls to [`Self::with_tokens`] and [`Self::with_font`].
@@ -132,7 +276,10 @@ impl<'a> Appender<'a> {
         self
     }
 
-    /// Specify a content locale (currently only affects line-breaking)
+    /// Specify a content locale
+    ///
+    /// This affects line-breaking and selects patterns for
+    /// [automatic hyphenation](Self::with_hyphenation).
     ///
     /// This only affects subsequent calls to [`Self::with_tokens`] and [`Self::with_font`].
     #[inline]
@@ -141,6 +288,87 @@ impl<'a> Appender<'a> {
         self
     }
 
+    /// Enable or disable automatic hyphenation
+    ///
+    /// When enabled, hyphenation opportunities are added within words using
+    /// the [`Hyphenator`](crate::hyphenation::Hyphenator)
+    /// [registered](crate::hyphenation::register) for the language of the
+    /// [content locale](Self::with_content_locale), if any. When a line is
+    /// wrapped at such an opportunity a hyphen is displayed, as with soft
+    /// hyphens (U+00AD). Default: disabled.
+    ///
+    /// This only affects subsequent calls to [`Self::with_tokens`] and [`Self::with_font`].
+    #[inline]
+    pub fn with_hyphenation(&mut self, enable: bool) -> &mut Self {
+        self.text.hyphenate = enable;
+        self
+    }
+
+    /// Set the white-space handling mode
+    ///
+    /// This controls collapsing of white-space and whether lines may wrap
+    /// other than at hard breaks; see [`WhiteSpace`]. Default:
+    /// [`WhiteSpace::PreWrap`].
+    ///
+    /// This only affects subsequent calls to [`Self::with_tokens`] and [`Self::with_font`].
+    #[inline]
+    pub fn with_white_space(&mut self, mode: WhiteSpace) -> &mut Self {
+        self.text.set_white_space(mode);
+        self
+    }
+
+    /// Place an inline object at `index`
+    ///
+    /// The char at `index` should be U+FFFC OBJECT REPLACEMENT CHARACTER.
+    /// This char is not displayed; instead space is reserved for `object`.
+    /// Objects are treated as a single unit for line breaking, BiDi
+    /// reordering and alignment; use [`Forme::objects`] to find positions of
+    /// objects after line wrapping.
+    ///
+    /// This only affects subsequent calls to [`Self::with_tokens`] and [`Self::with_font`].
+    pub fn with_object(&mut self, index: usize, object: InlineObject) -> &mut Self {
+        if !self.range.contains(&index) {
+            return self;
+        }
+        let index = index - self.range.start;
+        debug_assert!(self.text[index..].starts_with('\u{FFFC}'));
+        let index = to_u32(index);
+        let objects = &mut self.text.objects;
+        match objects.binary_search_by_key(&index, |(i, _)| *i) {
+            Ok(i) => objects[i].1 = object,
+            Err(i) => objects.insert(i, (index, object)),
+        }
+        self
+    }
+
+    /// Set the format of the paragraph containing `index`
+    ///
+    /// Paragraphs are as yielded by [`LineIterator`]. If
+    /// [`ParagraphFormat::direction`] is set, the paragraph's base direction
+    /// is overridden.
+    ///
+    /// This only affects subsequent calls to [`Self::with_tokens`] and [`Self::with_font`].
+    ///
+    /// [`LineIterator`]: crate::LineIterator
+    pub fn with_paragraph(&mut self, index: usize, format: ParagraphFormat) -> &mut Self {
+        if !(self.range.contains(&index) || index == self.range.end && index == self.text_len) {
+            return self;
+        }
+        let index = index - self.range.start;
+        let range = paragraph_range(&self.text, index..index);
+        if let Some(direction) = format.direction {
+            self.text.set_direction(range.clone(), direction);
+        }
+
+        let start = to_u32(self.range.start + range.start);
+        let paragraphs = &mut self.forme.paragraphs;
+        match paragraphs.binary_search_by_key(&start, |(i, _)| *i) {
+            Ok(i) => paragraphs[i].1 = format,
+            Err(i) => paragraphs.insert(i, (start, format)),
+        }
+        self
+    }
+
     /// Append the entire `text` using fonts inferred from `tokens`
     ///
     /// If `imply_empty_final_line` and `text` ends with a mandatory line-break
@@ -153,13 +381,37 @@ impl<'a> Appender<'a> {
         font_tokens: impl Iterator<Item = FontToken>,
         imply_empty_final_line: bool,
     ) -> Result<(), NoFontMatch> {
-        self.forme
-            .push_text(&self.text, font_tokens, imply_empty_final_line)
+        let start = self.forme.runs.len();
+        let imply_empty_final_line = imply_empty_final_line && self.range.end == self.text_len;
+        if self.range.len() == self.text_len {
+            self.forme
+                .push_text(&self.text, font_tokens, imply_empty_final_line)?;
+        } else {
+            // Clip tokens to self.range
+            let mut tokens: Vec<FontToken> = vec![];
+            for mut token in font_tokens {
+                let index = to_usize(token.start);
+                if index <= self.range.start {
+                    token.start = 0;
+                    tokens.clear();
+                } else if index < self.range.end {
+                    token.start = to_u32(index - self.range.start);
+                } else {
+                    break;
+                }
+                tokens.push(token);
+            }
+            self.forme
+                .push_text(&self.text, tokens.into_iter(), imply_empty_final_line)?;
+        }
+        self.place_runs(start);
+        Ok(())
     }
 
     /// Append `&text[range]` using a single font
     ///
     /// This method may be called multiple times with non-overlapping ranges.
+    /// After [`Forme::edit_text`], `range` is clipped to the affected range.
     #[inline]
     pub fn with_font(
         &mut self,
@@ -175,9 +427,26 @@ impl<'a> Appender<'a> {
         let h = match range.end_bound() {
             Bound::Included(x) => x + 1,
             Bound::Excluded(x) => *x,
-            Bound::Unbounded => self.text.len(),
+            Bound::Unbounded => self.text_len,
+        };
+        let (mut l, mut h) = (l, h);
+        if self.range.len() != self.text_len {
+            // Clip to self.range
+            (l, h) = (l.max(self.range.start), h.min(self.range.end));
+            if l > h || l == h && !self.text.is_empty() {
+                return Ok(self);
+            }
+            (l, h) = (l - self.range.start, h - self.range.start);
+        }
+        let token = FontToken {
+            start: to_u32(l),
+            dpem,
+            font,
+            ..Default::default()
         };
-        self.forme.push_text_range(&self.text, l..h, font, dpem)?;
+        let start = self.forme.runs.len();
+        self.forme.push_text_range(&self.text, l..h, &token)?;
+        self.place_runs(start);
         Ok(self)
     }
 }
@@ -216,6 +485,7 @@ impl Forme {
             }
         };
         let mut face = preferred_face;
+        let primary_face = fonts.first_face_for(font_id).expect("invalid FontId");
 
         let mut start = 0;
         #[cfg_attr(test, allow(unused_variables))]
@@ -252,13 +522,10 @@ impl Forme {
                         rest.remove(0);
                     }
 
-                    self.push_run(shaper::shape(
-                        input,
-                        sub_range,
-                        face,
-                        breaks,
-                        RunSpecial::NoBreak,
-                    ));
+                    let mut run =
+                        shaper::shape(input, sub_range, face, breaks, RunSpecial::NoBreak);
+                    run.1.primary_face = (run.0.face_id != primary_face).then_some(primary_face);
+                    self.push_run(run);
                     breaks = rest;
                     start = index;
                 }
@@ -271,7 +538,9 @@ impl Forme {
             start: range.start + start,
             end: range.end,
         };
-        self.push_run(shaper::shape(input, sub_range, face, breaks, special));
+        let mut run = shaper::shape(input, sub_range, face, breaks, special);
+        run.1.primary_face = (run.0.face_id != primary_face).then_some(primary_face);
+        self.push_run(run);
         Ok(())
     }
 
@@ -287,34 +556,41 @@ impl Forme {
         mut font_tokens: impl Iterator<Item = FontToken>,
         imply_empty_final_line: bool,
     ) -> Result<(), NoFontMatch> {
-        let (mut dpem, mut font) = read_initial_token(&mut font_tokens);
+        let mut token = read_initial_token(&mut font_tokens);
 
         let mut start = 0;
-        for token in font_tokens {
-            let end = to_usize(token.start);
-            self.push_text_range(text, start..end, font, dpem)?;
+        for next in font_tokens {
+            let end = to_usize(next.start);
+            self.push_text_range(text, start..end, &token)?;
 
             start = end;
-            dpem = token.dpem;
-            font = token.font;
+            token = next;
         }
 
         let len = text.len();
         if start < len || len == 0 {
-            self.push_text_range(text, start..len, font, dpem)?;
+            self.push_text_range(text, start..len, &token)?;
         }
 
         // Following a hard break we have an implied empty line.
-        if imply_empty_final_line && ends_with_hard_break(text) {
+        if imply_empty_final_line
+            && !text.white_space().collapses_newlines()
+            && ends_with_hard_break(text)
+        {
             let input = shaper::Input {
                 text,
-                dpem,
+                dpem: token.dpem,
                 base_level: text.default_level(),
                 level: text.default_level(),
                 script: Script::Unknown,
+                letter_spacing: 0.0,
+                word_spacing: 0.0,
+                orientation: self.orientation(text.default_level(), None),
+                white_space: WhiteSpace::default(),
             };
             let range = (text.len()..text.len()).into();
             let breaks = Default::default();
+            let font = token.font;
             self.select_font_and_push_run(font, input, range, breaks, RunSpecial::None, None)?;
         }
 
@@ -358,25 +634,32 @@ impl Forme {
     }
 
     /// Break `&text[range]` into runs and push
+    ///
+    /// Formatting is taken from `token`; its `start` field is ignored.
     fn push_text_range(
         &mut self,
         text: &AnalyzedText<'_>,
         range: std::ops::Range<usize>,
-        font: FontSelector,
-        dpem: f32,
+        token: &FontToken,
     ) -> Result<(), NoFontMatch> {
         let starting_para_i = text.find_paragraph(range.start);
+        let font = token.font;
 
         let mut input = shaper::Input {
             text,
-            dpem,
+            dpem: token.dpem,
             base_level: text
                 .paragraph(starting_para_i)
                 .map(|p| p.level)
                 .unwrap_or(text.default_level()),
             level: text.level(range.start).unwrap_or(text.default_level()),
             script: Script::Unknown,
+            letter_spacing: token.letter_spacing.to_px(token.dpem),
+            word_spacing: token.word_spacing.to_px(token.dpem),
+            orientation: Orientation::Horizontal,
+            white_space: text.white_space(),
         };
+        input.orientation = self.orientation(input.level, text[range.clone()].chars().next());
         let mut next_para_i = starting_para_i + 1;
 
         let mut breaks = Default::default();
@@ -386,6 +669,9 @@ impl Forme {
         let mut break_iter = segmenter.segment_str(&input.text[range.clone()]);
         let mut next_break = break_iter.next();
 
+        let hyphen_points = find_hyphen_points(text, range.clone(), &segmenter);
+        let mut hyphen_points = hyphen_points.into_iter().peekable();
+
         let mut first_real = None;
         let mut emoji_state = EmojiState::None;
         let mut emoji_start = 0;
@@ -393,6 +679,7 @@ impl Forme {
 
         let mut last_is_control = false;
         let mut last_is_htab = false;
+        let mut last_object = None;
         let mut non_control_end = 0;
 
         for (sub_index, c) in input.text[range.clone()]
@@ -405,18 +692,38 @@ impl Forme {
             if !last_is_control {
                 non_control_end = text_index;
             }
-            let is_htab = c == '\t';
-            let mut require_break = last_is_htab;
+            let is_htab = c == '\t' && !text.white_space().collapses_spaces();
+            let object = text.object(text_index).filter(|_| c == '\u{FFFC}');
+            let mut require_break = last_is_htab || last_object.is_some();
+            require_break |= object.is_some() && text_index > start;
             let is_control = c.is_control();
 
             // Is wrapping allowed at this position?
-            let is_break = next_break == Some(sub_index);
-            let hard_break = is_break && sub_index > 0 && ends_with_hard_break(&text[..text_index]);
+            let mut is_break = next_break == Some(sub_index);
+            let hard_break = is_break
+                && sub_index > 0
+                && !text.white_space().collapses_newlines()
+                && ends_with_hard_break(&text[..text_index]);
             if is_break {
                 next_break = break_iter.next();
             }
+            let mut hyphen = is_break && text[..text_index].ends_with('\u{AD}');
+            if hyphen_points.next_if_eq(&sub_index).is_some() {
+                is_break = true;
+                hyphen = true;
+            }
+            if !text.white_space().wraps() && !hard_break && text_index < text.len() {
+                is_break = false;
+                hyphen = false;
+            }
 
             let script = CodePointMapData::<Script>::new().get(c);
+            let orientation = if c.is_whitespace() || is_control || script == Script::Inherited {
+                input.orientation
+            } else {
+                let level = text.level(text_index).unwrap_or(input.level);
+                self.orientation(level, Some(c))
+            };
 
             let emoji_break = emoji_state.advance(c);
             let mut new_emoji_start = emoji_start;
@@ -463,20 +770,35 @@ impl Forme {
             if is_real(script) && script != input.script {
                 require_break |= is_real(input.script);
             }
+            require_break |= orientation != input.orientation;
 
             let is_end = text_index == range.end;
             if is_end || !prohibit_break && (hard_break || require_break) {
                 let special = match () {
                     _ if hard_break => RunSpecial::HardBreak,
                     _ if last_is_htab => RunSpecial::HTab,
-                    _ if last_is_control || is_break => RunSpecial::None,
+                    _ if hyphen => RunSpecial::Hyphen,
+                    _ if is_break => RunSpecial::None,
+                    _ if last_is_control && text.white_space().wraps() => RunSpecial::None,
                     _ => RunSpecial::NoBreak,
                 };
 
-                if is_emoji {
+                if let Some(object) = last_object {
+                    let range = (start..text_index).into();
+                    let fonts = fonts::library();
+                    let font_id = fonts.select_font(&font, to_fontique_script(input.script))?;
+                    let face = fonts.first_face_for(font_id).expect("invalid FontId");
+                    let run = shaper::shape_object(input, range, face, object, special);
+                    self.push_run(run);
+                } else if is_emoji {
                     let range = (emoji_start..emoji_end).into();
                     let face = emoji_face_id()?;
-                    self.push_run(shaper::shape(input, range, face, breaks, special));
+                    let mut run = shaper::shape(input, range, face, breaks, special);
+                    let fonts = fonts::library();
+                    let font_id = fonts.select_font(&font, to_fontique_script(input.script))?;
+                    let primary_face = fonts.first_face_for(font_id).expect("invalid FontId");
+                    run.1.primary_face = (run.0.face_id != primary_face).then_some(primary_face);
+                    self.push_run(run);
                 } else {
                     // NOTE: the range may be empty; we need it anyway (unless
                     // we modify the last run's special property).
@@ -497,10 +819,11 @@ impl Forme {
                     input.level = level;
                 }
                 input.script = script;
+                input.orientation = orientation;
                 breaks = Default::default();
             } else {
                 if is_break && !is_control && text_index > start {
-                    breaks.push(shaper::GlyphBreak::new(to_u32(text_index)));
+                    breaks.push(shaper::GlyphBreak::new(to_u32(text_index), hyphen));
                 }
 
                 if input.script == Script::Unknown
@@ -516,6 +839,7 @@ impl Forme {
 
             last_is_control = is_control;
             last_is_htab = is_htab;
+            last_object = object;
             emoji_start = new_emoji_start;
         }
 
@@ -523,18 +847,83 @@ impl Forme {
     }
 
     #[inline]
-    fn push_run(&mut self, run: GlyphRun) {
+    /// Get the orientation of `c` in the current writing mode
+    fn orientation(&self, level: Level, c: Option<char>) -> Orientation {
+        if !self.config.writing_mode.is_vertical() {
+            return Orientation::Horizontal;
+        } else if level.is_rtl() {
+            // We do not support upright right-to-left text
+            return Orientation::Rotated;
+        }
+
+        let vo = c.map(|c| CodePointMapData::<VerticalOrientation>::new().get(c));
+        match vo {
+            Some(VerticalOrientation::Rotated | VerticalOrientation::TransformedRotated) => {
+                Orientation::Rotated
+            }
+            _ => Orientation::Upright,
+        }
+    }
+
+    fn push_run(&mut self, (mut run, extra): (GlyphRun, RunExtra)) {
+        if self.config.overflow_wrap == OverflowWrap::Anywhere && !extra.no_wrap {
+            run.add_emergency_breaks();
+        }
+        self.run_extras.set(self.runs.len(), extra);
         self.runs.push(run);
     }
 }
 
-fn read_initial_token(iter: &mut impl Iterator<Item = FontToken>) -> (f32, FontSelector) {
-    let Some(FontToken { start, dpem, font }) = iter.next() else {
+/// Find automatic hyphenation opportunities within `&text[range]`
+///
+/// Returns indices relative to `range.start`, in increasing order.
+fn find_hyphen_points(
+    text: &AnalyzedText<'_>,
+    range: std::ops::Range<usize>,
+    segmenter: &LineSegmenterBorrowed<'static>,
+) -> Vec<usize> {
+    let mut points = vec![];
+    if !text.hyphenate {
+        return points;
+    }
+    let Some(hyphenator) = text
+        .lb_opts
+        .content_locale
+        .and_then(|locale| hyphenation::get(locale.language))
+    else {
+        return points;
+    };
+
+    let slice = &text[range];
+    let mut start = 0;
+    let mut after_soft_hyphen = false;
+    for end in segmenter.segment_str(slice).skip(1) {
+        let segment = &slice[start..end];
+        // Hyphenate the alphabetic core of each segment. As in TeX, words
+        // containing explicit soft hyphens are not hyphenated automatically.
+        let soft_hyphen = segment.ends_with('\u{AD}');
+        let word = segment.trim_matches(|c: char| !c.is_alphabetic());
+        if !soft_hyphen
+            && !after_soft_hyphen
+            && !word.is_empty()
+            && word.chars().all(|c| c.is_alphabetic())
+        {
+            let offset = start + (word.as_ptr() as usize - segment.as_ptr() as usize);
+            points.extend(hyphenator.hyphenate(word).into_iter().map(|i| offset + i));
+        }
+        after_soft_hyphen = soft_hyphen;
+        start = end;
+    }
+    points
+}
+
+fn read_initial_token(iter: &mut impl Iterator<Item = FontToken>) -> FontToken {
+    let Some(token) = iter.next() else {
         debug_assert!(false, "iterator font_tokens is empty");
-        return (16.0, FontSelector::default());
+        return FontToken::default();
     };
-    debug_assert_eq!(start, 0, "iterator font_tokens does not start at 0");
-    (dpem, font)
+    debug_assert_eq!(token.start, 0, "iterator font_tokens does not start at 0");
+    token
 }
 
 fn is_real(script: Script) -> bool {
@@ -679,18 +1068,14 @@ impl EmojiState {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::forme::test_util::shape;
     use std::iter;
     use std::ops::Range;
-    use unicode_bidi::Level;
 
     type Expected<'a> = &'a [(Range<usize>, RunSpecial, Level, Level, Script, &'a [u32])];
 
     fn test_breaking(text: &str, dir: Direction, expected: Expected) {
-        let fonts = iter::once(FontToken {
-            start: 0,
-            dpem: 16.0,
-            font: Default::default(),
-        });
+        let fonts = iter::once(FontToken::default());
 
         let mut forme = Forme::default();
         assert!(forme.set_text(text, dir).with_tokens(fonts, false).is_ok());
@@ -1078,4 +1463,123 @@ mod test {
             ],
         );
     }
+
+    fn prepare(forme: &mut Forme) {
+        forme.prepare_lines(60.0, 80.0, crate::Align::Default);
+        forme.vertically_align(400.0, crate::Align::Center);
+    }
+
+    /// Summarise runs and lines for comparison
+    fn layout(forme: &Forme) -> Vec<String> {
+        let mut v = vec![];
+        for run in &forme.runs {
+            let glyphs: Vec<_> = run
+                .glyphs
+                .iter()
+                .map(|g| (g.index, g.id.0, g.position.0))
+                .collect();
+            let breaks: Vec<_> = run.breaks.iter().map(|b| b.index).collect();
+            v.push(format!(
+                "{:?} {:?} {:?} {glyphs:?} {breaks:?}",
+                run.range, run.special, run.level
+            ));
+        }
+        v.push(format!("{:?}", forme.run_extras));
+        for line in forme.lines() {
+            v.push(format!("{line:?}"));
+        }
+        for part in &forme.wrapped_runs {
+            v.push(format!("{part:?}"));
+        }
+        v
+    }
+
+    #[test]
+    fn edit_text() {
+        let text = "One two three\nfour five six\n\nשלום עולם\nseven\nبسم الله";
+        let edits: &[(Range<usize>, &str)] = &[
+            (19..23, "FIVE!!"),
+            (0..3, ""),
+            (13..14, " "),
+            (16..16, "\n"),
+            (47..47, "\neight\n"),
+            (29..29, "א"),
+            (0..47, "x"),
+        ];
+        for (range, replacement) in edits.iter().cloned() {
+            let mut new_text = text.to_string();
+            new_text.replace_range(range.clone(), replacement);
+
+            let mut expected = Forme::default();
+            shape(&mut expected, &new_text);
+            prepare(&mut expected);
+
+            let mut forme = Forme::default();
+            shape(&mut forme, text);
+            prepare(&mut forme);
+            let mut appender =
+                forme.edit_text(&new_text, Direction::Auto, range.clone(), replacement.len());
+            assert!(
+                appender
+                    .with_font(.., FontSelector::default(), 16.0)
+                    .is_ok()
+            );
+            if range.start >= 14 {
+                // Lines of the first paragraph are re-used
+                assert!(forme.valid_lines >= 3);
+            }
+            prepare(&mut forme);
+
+            assert_eq!(
+                layout(&forme),
+                layout(&expected),
+                "edit {range:?} -> {replacement:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn append_text() {
+        let pieces = [
+            "Log line one\n",
+            "second",
+            " line is longer\n",
+            "שלום\n\n",
+            "tail",
+        ];
+        let tokens = |text: &str| {
+            // Use a larger font from index 4 onwards
+            let mut tokens = vec![FontToken::default(), FontToken::default()];
+            tokens[1].start = to_u32(4.min(text.len()));
+            tokens[1].dpem = 20.0;
+            tokens.into_iter()
+        };
+
+        let mut text = String::new();
+        let mut forme = Forme::default();
+        for piece in pieces {
+            let len = text.len();
+            text.push_str(piece);
+
+            let mut expected = Forme::default();
+            let mut appender = expected.set_text(&text, Direction::Auto);
+            assert!(appender.with_tokens(tokens(&text), true).is_ok());
+            prepare(&mut expected);
+
+            let lines = forme.num_lines();
+            let mut appender = forme.append_text(&text, Direction::Auto);
+            assert!(appender.with_tokens(tokens(&text), true).is_ok());
+            if text[..len].ends_with('\n') {
+                // Previous lines are re-used
+                assert_eq!(forme.valid_lines, lines - 1);
+            }
+            prepare(&mut forme);
+
+            assert_eq!(
+                layout(&forme),
+                layout(&expected),
+                "after appending {piece:?}"
+            );
+        }
+    }
 }
@@ -0,0 +1,362 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Text preparation: truncation
+
+use super::wrap_lines::PartInfo;
+use super::{Forme, WhiteSpace};
+use crate::conv::to_usize;
+use crate::fonts::FontLibrary;
+use crate::shaper::{self, GlyphRun, Orientation, RunExtra};
+use crate::{Range, RunSpecial};
+use icu_properties::props::Script;
+use std::num::NonZeroUsize;
+
+/// Position of content removed by truncation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TruncatePosition {
+    /// Remove content from the logical end of the line
+    #[default]
+    End,
+    /// Remove content from the logical start of the line
+    Start,
+    /// Remove content from the middle of the line
+    ///
+    /// This is useful for file paths and similar where both the start and the
+    /// end are significant.
+    Middle,
+}
+
+/// Truncation options
+///
+/// Where content is truncated, it is replaced by an [ellipsis](Self::ellipsis)
+/// shaped using the font face of adjacent text. Truncation happens in logical
+/// order: with [`TruncatePosition::End`] the ellipsis follows the last
+/// displayed character in logical order (thus is left of this character when
+/// it is right-to-left).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Truncate {
+    /// Maximum number of lines (default: unlimited)
+    ///
+    /// If further content follows the last allowed line, the end of this
+    /// line is truncated. [`Self::position`] does not apply here.
+    pub max_lines: Option<NonZeroUsize>,
+    /// If true, truncate lines which do not fit the alignment width
+    /// (default: false)
+    ///
+    /// This is normally used without line wrapping.
+    pub width: bool,
+    /// Position of content removed from lines which are too long
+    pub position: TruncatePosition,
+    /// Text replacing truncated content (default: `"…"`)
+    ///
+    /// If the font does not support U+2026 HORIZONTAL ELLIPSIS, the default
+    /// is replaced by `"..."`.
+    pub ellipsis: String,
+}
+
+impl Default for Truncate {
+    fn default() -> Self {
+        Truncate {
+            max_lines: None,
+            width: false,
+            position: TruncatePosition::End,
+            ellipsis: "…".to_string(),
+        }
+    }
+}
+
+impl Truncate {
+    /// Shape the ellipsis for display adjacent to `run`
+    pub(super) fn shape_ellipsis(
+        &self,
+        fonts: &FontLibrary,
+        run: &GlyphRun,
+        orientation: Orientation,
+    ) -> (GlyphRun, RunExtra) {
+        let mut text = self.ellipsis.as_str();
+        if text == "…" && fonts.get_face(run.face_id).glyph_index('…').0 == 0 {
+            text = "...";
+        }
+        let input = shaper::Input {
+            text,
+            dpem: run.dpem,
+            base_level: run.base_level,
+            level: run.level,
+            script: Script::Unknown,
+            letter_spacing: 0.0,
+            word_spacing: 0.0,
+            orientation,
+            white_space: WhiteSpace::default(),
+        };
+        let range = Range::from(0..text.len());
+        let breaks = Default::default();
+        shaper::shape(input, range, run.face_id, breaks, RunSpecial::NoBreak)
+    }
+}
+
+/// Inline position of the boundary before glyph `i` (in logical order)
+fn edge(run: &GlyphRun, i: usize) -> f32 {
+    if run.level.is_ltr() {
+        run.glyphs.get(i).map(|g| g.position.0).unwrap_or(run.caret)
+    } else if i == 0 {
+        run.caret
+    } else {
+        run.glyphs[i - 1].position.0
+    }
+}
+
+/// True if `run` may be cut before glyph `i` (i.e. not within a cluster)
+fn is_boundary(run: &GlyphRun, i: usize) -> bool {
+    i == 0 || i >= run.glyphs.len() || run.glyphs[i].index != run.glyphs[i - 1].index
+}
+
+/// Reduce `part` to the glyphs `start..end`
+fn set_glyph_range(run: &GlyphRun, part: &mut PartInfo, start: usize, end: usize) {
+    let (a, b) = (edge(run, start), edge(run, end));
+    part.glyph_range = Range::from(start..end);
+    part.offset = a.min(b);
+    part.len = (b - a).abs();
+    part.len_no_space = part.len;
+    part.extra = 0.0;
+    part.kashida = false;
+    part.hyphen = 0.0;
+}
+
+fn part_len(part: &PartInfo) -> f32 {
+    part.len + part.extra
+}
+
+/// Keep a logical prefix of `parts` no longer than `max`
+///
+/// Trailing spaces of the cut part are removed. At least one (possibly
+/// empty) part is retained. Returns the length of retained parts.
+pub(super) fn keep_start(
+    fonts: &FontLibrary,
+    runs: &[GlyphRun],
+    parts: &mut Vec<PartInfo>,
+    max: f32,
+) -> f32 {
+    let mut len = 0.0;
+    for i in 0..parts.len() {
+        if len + part_len(&parts[i]) <= max {
+            len += part_len(&parts[i]);
+            continue;
+        }
+
+        let part = &mut parts[i];
+        let run = &runs[to_usize(part.run)];
+        let space = fonts.get_face(run.face_id).glyph_index(' ');
+        let start = part.glyph_range.start();
+        let mut end = part.glyph_range.end();
+        while end > start
+            && (!is_boundary(run, end) || (edge(run, end) - edge(run, start)).abs() > max - len)
+        {
+            end -= 1;
+        }
+        while end > start && run.glyphs[end - 1].id == space {
+            end -= 1;
+        }
+        set_glyph_range(run, part, start, end);
+        len += part.len;
+
+        parts.truncate(if i > 0 && start == end { i } else { i + 1 });
+        break;
+    }
+    len
+}
+
+/// Keep a logical suffix of `parts` no longer than `max`
+///
+/// Leading spaces of the cut part are removed. At least one (possibly
+/// empty) part is retained. Returns the length of retained parts.
+pub(super) fn keep_end(
+    fonts: &FontLibrary,
+    runs: &[GlyphRun],
+    parts: &mut Vec<PartInfo>,
+    max: f32,
+) -> f32 {
+    let mut len = 0.0;
+    for i in (0..parts.len()).rev() {
+        if len + part_len(&parts[i]) <= max {
+            len += part_len(&parts[i]);
+            continue;
+        }
+
+        let part = &mut parts[i];
+        let run = &runs[to_usize(part.run)];
+        let space = fonts.get_face(run.face_id).glyph_index(' ');
+        let mut start = part.glyph_range.start();
+        let end = part.glyph_range.end();
+        while start < end
+            && (!is_boundary(run, start) || (edge(run, end) - edge(run, start)).abs() > max - len)
+        {
+            start += 1;
+        }
+        while start < end && run.glyphs[start].id == space {
+            start += 1;
+        }
+        set_glyph_range(run, part, start, end);
+        len += part.len;
+
+        let keep = if i + 1 < parts.len() && start == end {
+            i + 1
+        } else {
+            i
+        };
+        parts.drain(..keep);
+        break;
+    }
+    len
+}
+
+impl Forme {
+    /// Get truncation options
+    #[inline]
+    pub fn truncate(&self) -> &Truncate {
+        &self.config.truncate
+    }
+
+    /// Set truncation options
+    ///
+    /// Changes take effect on the next call to [`Self::prepare_lines`].
+    #[inline]
+    pub fn set_truncate(&mut self, truncate: Truncate) {
+        self.config.truncate = truncate;
+        self.valid_lines = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Align;
+    use crate::forme::test_util::{glyph_pos, shaped};
+
+    /// Text ranges of displayed (non-synthetic) runs
+    fn text_ranges(forme: &Forme) -> Vec<std::ops::Range<u32>> {
+        forme
+            .wrapped_runs
+            .iter()
+            .filter(|part| !part.glyph_range.is_empty() && !part.is_synthetic())
+            .map(|part| {
+                let run = forme.part_run(part);
+                run.glyphs[part.glyph_range.start()].index..part.text_end
+            })
+            .collect()
+    }
+
+    /// Inline positions of ellipsis glyphs
+    fn ellipses(forme: &Forme) -> Vec<f32> {
+        let mut positions = vec![];
+        for part in forme.wrapped_runs.iter().filter(|part| part.is_synthetic()) {
+            let run = forme.part_run(part);
+            positions.extend(run.glyphs.iter().map(|g| part.offset.0 + g.position.0));
+        }
+        positions
+    }
+
+    #[test]
+    fn max_lines() {
+        let text = "One two three four five six";
+        let truncate = Truncate {
+            max_lines: NonZeroUsize::new(2),
+            ..Default::default()
+        };
+        let mut forme = shaped(text, |forme| forme.set_truncate(truncate.clone()));
+        let height = forme.measure_height(80.0, NonZeroUsize::new(3));
+        assert_eq!(forme.prepare_lines(80.0, 80.0, Align::Default), height);
+        assert_eq!(forme.num_lines(), 2);
+        assert_eq!(ellipses(&forme).len(), 1);
+        assert!(forme.bounding_box().1.0 <= 80.0);
+        let end = forme.get_line(1).unwrap().text_range().end;
+        assert!(end < text.len());
+        assert_ne!(text.as_bytes()[end - 1], b' ');
+
+        // No ellipsis is needed when all content fits
+        let mut forme = shaped("One\ntwo\n", |forme| forme.set_truncate(truncate));
+        forme.prepare_lines(80.0, 80.0, Align::Default);
+        assert_eq!(forme.num_lines(), 2);
+        assert!(ellipses(&forme).is_empty());
+    }
+
+    #[test]
+    fn width() {
+        let text = "abcdefghijklmnopqrstuvwxyz";
+        let truncate = Truncate {
+            width: true,
+            ..Default::default()
+        };
+        let mut forme = shaped(text, |forme| forme.set_truncate(truncate.clone()));
+        forme.prepare_lines(f32::INFINITY, 100.0, Align::Default);
+        assert_eq!(forme.num_lines(), 1);
+        let ranges = text_ranges(&forme);
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].start, 0);
+        let ellipsis = ellipses(&forme);
+        assert_eq!(ellipsis.len(), 1);
+        assert!(ellipsis[0] >= glyph_pos(&forme, to_usize(ranges[0].end)).0);
+        assert!(forme.bounding_box().1.0 <= 100.0);
+
+        // Short text is not truncated
+        let mut forme = shaped("abc", |forme| forme.set_truncate(truncate));
+        forme.prepare_lines(f32::INFINITY, 100.0, Align::Default);
+        assert!(ellipses(&forme).is_empty());
+    }
+
+    #[test]
+    fn start_and_middle() {
+        let text = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf";
+        let truncate = Truncate {
+            width: true,
+            position: TruncatePosition::Start,
+            ..Default::default()
+        };
+        let mut forme = shaped(text, |forme| forme.set_truncate(truncate.clone()));
+        forme.prepare_lines(f32::INFINITY, 150.0, Align::Default);
+        let ranges = text_ranges(&forme);
+        assert_eq!(ranges.len(), 1);
+        assert!(ranges[0].start > 0 && to_usize(ranges[0].end) == text.len());
+        assert!(ellipses(&forme)[0] < glyph_pos(&forme, to_usize(ranges[0].start)).0);
+        assert!(forme.bounding_box().1.0 <= 150.0);
+
+        let truncate = Truncate {
+            position: TruncatePosition::Middle,
+            ..truncate
+        };
+        let mut forme = shaped(text, |forme| forme.set_truncate(truncate));
+        forme.prepare_lines(f32::INFINITY, 150.0, Align::Default);
+        let parts = text_ranges(&forme);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].start, 0);
+        assert!(parts[0].end < parts[1].start);
+        assert_eq!(to_usize(parts[1].end), text.len());
+        let x = ellipses(&forme)[0];
+        assert!(glyph_pos(&forme, to_usize(parts[0].end)).0 <= x);
+        assert!(x < glyph_pos(&forme, to_usize(parts[1].start)).0);
+        assert!(forme.bounding_box().1.0 <= 150.0);
+    }
+
+    #[test]
+    fn rtl() {
+        // Hebrew: the ellipsis is at the left (logical end)
+        let text = "שלום עולם שלום עולם שלום עולם";
+        let truncate = Truncate {
+            width: true,
+            ..Default::default()
+        };
+        let mut forme = shaped(text, |forme| forme.set_truncate(truncate));
+        forme.prepare_lines(f32::INFINITY, 100.0, Align::Default);
+        let ranges = text_ranges(&forme);
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].start, 0);
+        let ellipsis = ellipses(&forme);
+        assert_eq!(ellipsis.len(), 1);
+        let end = glyph_pos(&forme, to_usize(ranges[0].end)).0;
+        assert!(ellipsis[0] < end);
+    }
+}
@@ -0,0 +1,247 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Text preparation: white-space handling
+
+use crate::util::is_hard_break;
+use std::ops::Range;
+
+/// White-space handling mode
+///
+/// This determines whether white-space is collapsed and whether lines may
+/// wrap, as with the CSS `white-space` property. See
+/// [`Appender::with_white_space`](super::Appender::with_white_space).
+///
+/// Collapsed white-space is not removed from the text; instead, each
+/// sequence of collapsible white-space is displayed as a single space (or
+/// not at all at the start of a line) using zero-width glyphs for the other
+/// chars. Thus text indices (e.g. as returned by
+/// [`Forme::text_index_nearest`](super::Forme::text_index_nearest)) always
+/// refer to the original text.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WhiteSpace {
+    /// Collapse white-space (including newlines); wrap lines
+    Normal,
+    /// Collapse white-space (including newlines); do not wrap lines
+    Nowrap,
+    /// Preserve white-space; lines only break at newlines
+    Pre,
+    /// Preserve white-space; wrap lines
+    #[default]
+    PreWrap,
+    /// Collapse spaces and tabs but preserve newlines; wrap lines
+    PreLine,
+}
+
+/// Result of white-space collapsing for a char
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Collapse {
+    /// Display as normal
+    Keep,
+    /// Display as a space
+    Space,
+    /// Do not display
+    Remove,
+}
+
+/// True for chars treated as white-space by [`WhiteSpace`] modes
+fn is_white(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r')
+}
+
+/// True for chars treated as newlines by [`WhiteSpace`] modes
+fn is_newline(c: char) -> bool {
+    matches!(c, '\n' | '\r')
+}
+
+impl WhiteSpace {
+    /// True if lines may wrap at soft line-break opportunities
+    #[inline]
+    pub fn wraps(self) -> bool {
+        !matches!(self, WhiteSpace::Nowrap | WhiteSpace::Pre)
+    }
+
+    /// True if sequences of spaces and tabs are collapsed
+    #[inline]
+    pub fn collapses_spaces(self) -> bool {
+        matches!(
+            self,
+            WhiteSpace::Normal | WhiteSpace::Nowrap | WhiteSpace::PreLine
+        )
+    }
+
+    /// True if newlines are collapsed (treated as spaces)
+    #[inline]
+    pub fn collapses_newlines(self) -> bool {
+        matches!(self, WhiteSpace::Normal | WhiteSpace::Nowrap)
+    }
+
+    /// Determine collapsing of the chars of `range` in `text`
+    ///
+    /// The result is indexed by byte offset from `range.start`, or is empty
+    /// when nothing is collapsed. Collapsible chars are single-byte; other
+    /// entries are [`Collapse::Keep`].
+    ///
+    /// A sequence of collapsible white-space is displayed as a single space.
+    /// Sequences at the start of `text` or following a hard break are
+    /// removed, as are sequences preceding a preserved hard break.
+    pub(crate) fn collapse(self, text: &str, range: Range<usize>) -> Vec<Collapse> {
+        if !self.collapses_spaces() {
+            return vec![];
+        }
+        let collapsible = |c: char| is_white(c) && (self.collapses_newlines() || !is_newline(c));
+
+        let mut result = vec![Collapse::Keep; range.len()];
+        let mut prev = text[..range.start].chars().next_back();
+        let mut in_sequence = prev.is_some_and(collapsible);
+        for (i, c) in text[range.clone()].char_indices() {
+            if !collapsible(c) {
+                prev = Some(c);
+                in_sequence = false;
+                continue;
+            }
+
+            // The first char of a sequence determines its display
+            let at_line_start = prev.is_none_or(|c| is_white(c) || is_hard_break(c));
+            let before_break = || {
+                !self.collapses_newlines()
+                    && text[range.start + i..]
+                        .chars()
+                        .find(|c| !matches!(c, ' ' | '\t'))
+                        .is_some_and(is_hard_break)
+            };
+            result[i] = if in_sequence || at_line_start || before_break() {
+                Collapse::Remove
+            } else if c == ' ' {
+                Collapse::Keep
+            } else {
+                Collapse::Space
+            };
+            in_sequence = true;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::forme::test_util::{glyph_pos, line_ranges, shape_with};
+    use crate::{Align, Forme, Vec2};
+
+    fn collapse(mode: WhiteSpace, text: &str) -> String {
+        let collapse = mode.collapse(text, 0..text.len());
+        text.char_indices()
+            .map(|(i, c)| match collapse.get(i).unwrap_or(&Collapse::Keep) {
+                Collapse::Keep => c,
+                Collapse::Space => '_',
+                Collapse::Remove => '#',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn collapse_chars() {
+        let text = "  a  b\t\n c \n";
+        assert_eq!(collapse(WhiteSpace::PreWrap, text), text);
+        assert_eq!(collapse(WhiteSpace::Pre, text), text);
+        assert_eq!(collapse(WhiteSpace::Normal, text), "##a #b_##c #");
+        assert_eq!(collapse(WhiteSpace::Nowrap, text), "##a #b_##c #");
+        assert_eq!(collapse(WhiteSpace::PreLine, text), "##a #b#\n#c#\n");
+
+        // Other hard breaks
+        let text = "a\u{2028} b \u{85}c";
+        assert_eq!(collapse(WhiteSpace::PreLine, text), "a\u{2028}#b#\u{85}c");
+        assert_eq!(collapse(WhiteSpace::Normal, text), "a\u{2028}#b \u{85}c");
+    }
+
+    #[test]
+    fn normal() {
+        let text = "ab   cd\nef";
+        let mut forme = Forme::default();
+        shape_with(&mut forme, text, |appender| {
+            appender.with_white_space(WhiteSpace::Normal);
+        });
+        forme.prepare_lines(1000.0, 1000.0, Align::Default);
+        assert_eq!(forme.num_lines(), 1);
+        let mut pre = Forme::default();
+        shape_with(&mut pre, text, |appender| {
+            appender.with_white_space(WhiteSpace::PreWrap);
+        });
+        pre.prepare_lines(1000.0, 1000.0, Align::Default);
+        assert_eq!(pre.num_lines(), 2);
+
+        // The collapsed sequence is displayed as a single space
+        let x = |index| glyph_pos(&forme, index).0;
+        assert!(x(3) > x(2));
+        assert_eq!(x(4), x(3));
+        assert_eq!(x(5), x(3));
+        // The newline is displayed as a space
+        assert_eq!(x(8) - x(7), x(3) - x(2));
+
+        // Indices refer to the original text
+        assert_eq!(forme.text_index_nearest(Vec2(x(6) + 1.0, 5.0)), 6);
+        assert_eq!(forme.text_index_nearest(Vec2(x(8) + 1.0, 5.0)), 8);
+    }
+
+    #[test]
+    fn wrapping() {
+        let text = "aaa bbb ccc\tddd\neee";
+        let mut nowrap = Forme::default();
+        shape_with(&mut nowrap, text, |appender| {
+            appender.with_white_space(WhiteSpace::Nowrap);
+        });
+        nowrap.prepare_lines(30.0, 30.0, Align::Default);
+        assert_eq!(nowrap.num_lines(), 1);
+        let mut pre = Forme::default();
+        shape_with(&mut pre, text, |appender| {
+            appender.with_white_space(WhiteSpace::Pre);
+        });
+        pre.prepare_lines(30.0, 30.0, Align::Default);
+        assert_eq!(line_ranges(&pre), [0..15, 16..19]);
+        let mut pre_line = Forme::default();
+        shape_with(&mut pre_line, text, |appender| {
+            appender.with_white_space(WhiteSpace::PreLine);
+        });
+        pre_line.prepare_lines(30.0, 30.0, Align::Default);
+        assert!(pre_line.num_lines() > 2);
+        assert_eq!(
+            pre_line
+                .get_line(pre_line.num_lines() - 1)
+                .unwrap()
+                .text_range(),
+            16..19
+        );
+    }
+
+    #[test]
+    fn bidi() {
+        // The collapsed newline does not start a new BiDi paragraph
+        let text = "abc\nאבג def";
+        for mode in [WhiteSpace::Normal, WhiteSpace::Nowrap] {
+            let mut forme = Forme::default();
+            shape_with(&mut forme, text, |appender| {
+                appender.with_white_space(mode);
+            });
+            forme.prepare_lines(1000.0, 1000.0, Align::Default);
+            assert_eq!(forme.num_lines(), 1);
+            assert!(forme.runs.iter().all(|run| run.base_level.is_ltr()));
+
+            // Visual order: "abc גבא def"
+            let x = |index| glyph_pos(&forme, index).0;
+            assert!(x(3) < x(8) && x(8) < x(6) && x(6) < x(11));
+        }
+
+        // Newlines are preserved by PreLine
+        let mut forme = Forme::default();
+        shape_with(&mut forme, text, |appender| {
+            appender.with_white_space(WhiteSpace::PreLine);
+        });
+        forme.prepare_lines(1000.0, 1000.0, Align::Default);
+        assert_eq!(forme.num_lines(), 2);
+        assert!(forme.runs.iter().any(|run| run.base_level.is_rtl()));
+    }
+}
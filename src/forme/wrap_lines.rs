@@ -5,15 +5,19 @@
 
 //! Text preparation: wrapping
 
-use super::{Forme, RunSpecial};
+#[allow(unused)]
+use super::Appender;
+use super::spacing::{LineBox, VCaret};
+use super::strategy::LinePlan;
+use super::truncate::{self, Truncate, TruncatePosition};
+use super::{Forme, Justify, LineFormat, LineSpacing, RunSpecial};
 #[allow(unused)]
 use crate::Status;
 use crate::conv::{to_u32, to_usize};
 use crate::fonts::{self, FontLibrary};
-use crate::shaper::{GlyphRun, PartMetrics};
-use crate::{Align, Range, Vec2};
+use crate::shaper::{GlyphRun, Orientation, PartMetrics, RunExtra, RunExtras};
+use crate::{Align, Glyph, GlyphId, Range, Vec2};
 use core::f32;
-use smallvec::SmallVec;
 use std::num::NonZeroUsize;
 use tinyvec::TinyVec;
 use unicode_bidi::{LTR_LEVEL, Level};
@@ -26,6 +30,20 @@ pub struct RunPart {
     pub offset: Vec2,
 }
 
+impl RunPart {
+    /// Flag on `glyph_run` indicating an index into `Forme::synthetic_runs`
+    pub const SYNTHETIC: u32 = 1 << 31;
+
+    /// True if this part references a synthetic run
+    ///
+    /// Synthetic runs contain glyphs added during line wrapping (e.g. kashida)
+    /// and are excluded from text navigation.
+    #[inline]
+    pub fn is_synthetic(&self) -> bool {
+        self.glyph_run & Self::SYNTHETIC != 0
+    }
+}
+
 /// Per-line data (post wrapping)
 #[derive(Clone, Debug, Default)]
 pub struct Line {
@@ -64,46 +82,56 @@ impl Forme {
     /// without full wrapping and glyph placement. Whenever the requirement
     /// exceeds `max_width`, the algorithm stops early, returning `max_width`.
     ///
-    /// The return value is unaffected by alignment and wrap configuration.
+    /// The return value is unaffected by alignment and wrap configuration
+    /// but includes [paragraph](super::ParagraphFormat) margins and indents.
+    /// See also [`Self::measure_min_width`].
     pub fn measure_width(&self, max_width: f32) -> f32 {
         let mut max_line_len = 0.0f32;
         let mut caret = 0.0;
         let mut line_len = 0.0;
+        let mut format = self.line_format(0, true);
 
-        for run in self.runs.iter() {
+        let fonts = fonts::library();
+        for (run_index, run) in self.runs.iter().enumerate() {
             let num_parts = run.num_parts();
-            let part = run.part_lengths(0..num_parts);
+            let mut part = run.part_lengths(0..num_parts);
+            if run.special == RunSpecial::HTab {
+                let x = caret + part.len;
+                let width = max_width - format.start - format.end;
+                let advance = self.tab_advance(fonts, run_index, x, width);
+                part.len += format.limit_tab(x, advance);
+            }
 
             if part.len_no_space > 0.0 {
                 line_len = caret + part.len_no_space;
-                if line_len >= max_width {
+                if format.start + line_len + format.end >= max_width {
                     return max_width;
                 }
             }
             caret += part.len;
 
             if run.special == RunSpecial::HardBreak {
-                max_line_len = max_line_len.max(line_len);
+                max_line_len = max_line_len.max(format.start + line_len + format.end);
                 caret = 0.0;
                 line_len = 0.0;
+                format = self.line_format(run_index + 1, true);
             }
         }
 
-        max_line_len.max(line_len)
+        max_line_len.max(format.start + line_len + format.end)
     }
 
     /// Measure required vertical height, wrapping as configured
     ///
-    /// Stops after `max_lines`, if provided.
+    /// Stops after `max_lines` or [`Truncate::max_lines`], if provided.
     ///
     /// Expects state [`Status::Shaped`] or higher.
     pub fn measure_height(&self, wrap_width: f32, max_lines: Option<NonZeroUsize>) -> f32 {
-        #[derive(Default)]
         struct MeasureAdder {
+            spacing: LineSpacing,
             parts: Vec<usize>, // run index for each part
             lines: usize,
-            line_gap: f32,
-            vcaret: f32,
+            vcaret: VCaret,
         }
 
         impl PartAccumulator for MeasureAdder {
@@ -115,6 +143,34 @@ impl Forme {
                 self.lines
             }
 
+            fn vcaret(&self) -> &VCaret {
+                &self.vcaret
+            }
+
+            fn line_box(
+                &self,
+                fonts: &FontLibrary,
+                runs: &[GlyphRun],
+                extras: &RunExtras,
+                parts_end: usize,
+            ) -> LineBox {
+                let mut last_run = usize::MAX;
+                let mut line_box = LineBox::default();
+                for run_index in self.parts[..parts_end].iter().cloned() {
+                    if last_run == run_index {
+                        continue;
+                    }
+                    last_run = run_index;
+                    let extra = extras.get(last_run);
+                    line_box.add_run(&self.spacing, fonts, &runs[last_run], extra);
+                }
+                line_box
+            }
+
+            fn clear_parts(&mut self) {
+                self.parts.clear();
+            }
+
             fn add_part(
                 &mut self,
                 _: &[GlyphRun],
@@ -137,46 +193,34 @@ impl Forme {
                 &mut self,
                 fonts: &FontLibrary,
                 runs: &[GlyphRun],
+                extras: &RunExtras,
                 parts_end: usize,
-                _: bool,
+                is_wrap: bool,
             ) {
                 debug_assert!(parts_end > 0);
-                let parts = &mut self.parts[..parts_end];
-
-                // Iterate runs to determine max ascent, level, etc.
-                let mut last_run = usize::MAX;
-                let (mut ascent, mut descent, mut line_gap) = (0f32, 0f32, 0f32);
-                for run_index in parts.iter().cloned() {
-                    if last_run == run_index {
-                        continue;
-                    }
-                    last_run = run_index;
-                    let run = &runs[last_run];
-
-                    let scale_font = fonts.get_face(run.face_id).scale_by_dpu(run.dpu);
-                    ascent = ascent.max(scale_font.ascent());
-                    descent = descent.min(scale_font.descent());
-                    line_gap = line_gap.max(scale_font.line_gap());
-                }
-
-                if self.lines > 0 {
-                    self.vcaret += line_gap.max(self.line_gap);
-                }
-                self.vcaret += ascent - descent;
-                self.line_gap = line_gap;
+                let line_box = self.line_box(fonts, runs, extras, parts_end);
 
-                // Vertically align lines to the nearest pixel (improves rendering):
-                self.vcaret = self.vcaret.round();
+                self.vcaret.begin_line(&line_box);
+                self.vcaret.end_line(&line_box, !is_wrap);
 
                 self.lines += 1;
                 self.parts.clear();
             }
         }
 
-        let mut adder = MeasureAdder::default();
+        let mut adder = MeasureAdder {
+            spacing: self.config.line_spacing,
+            parts: vec![],
+            lines: 0,
+            vcaret: VCaret::new(self.config.line_spacing),
+        };
+        let max_lines = match (max_lines, self.config.truncate.max_lines) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
         let max_lines = max_lines.map(|n| n.get()).unwrap_or(0);
-        self.wrap_lines(&mut adder, wrap_width, max_lines);
-        adder.vcaret
+        self.wrap_lines(&mut adder, wrap_width, max_lines, (0, 0), None);
+        adder.vcaret.vcaret
     }
 
     /// Flow text content into lines
@@ -191,6 +235,7 @@ impl Forme {
     ///
     /// This method is fairly fast, allowing immediate re-wrapping of content
     /// whenever the `wrap_width` changes.
+    /// See [`Self::prepare_lines_in`] for wrapping to variable line widths.
     ///
     /// ## Horizontal alignment
     ///
@@ -211,26 +256,160 @@ impl Forme {
     /// -   [`Align::Stretch`]: this is the most complex mode. For lines which
     ///     wrap and where the line length does not exceed `align_width`, the
     ///     text is aligned to `0` *and* `align_width` (if possible) by
-    ///     stretching spaces within the text. Other lines are aligned in the
-    ///     same way as [`Align::Default`].
+    ///     stretching spaces within the text. Other lines are aligned
+    ///     according to [`Justify::last_line`]. See [`Self::set_justify`].
+    ///
+    /// A [paragraph format](super::ParagraphFormat) may override `h_align`
+    /// and reduce the width available to lines using margins and indents.
+    ///
+    /// ## Hyphenation
+    ///
+    /// Soft hyphens (U+00AD) and [automatic hyphenation](Appender::with_hyphenation)
+    /// points are invisible unless a line is wrapped there, in which case a
+    /// hyphen glyph is displayed at the end of the line. This glyph is not
+    /// associated with any text index.
+    ///
+    /// ## Truncation
+    ///
+    /// Lines may be limited and over-long lines shortened using
+    /// [`Self::set_truncate`]. An ellipsis is displayed in place of removed
+    /// content; like hyphens, this is not associated with any text index.
     ///
     /// ## Vertical alignment
     ///
-    /// Vertically, content is top-aligned. Call [`Self::vertically_align`]
+    /// Line heights and paragraph spacing are determined by
+    /// [`Self::set_line_spacing`]. Vertically, content is top-aligned. Call [`Self::vertically_align`]
     /// after this method to use a different alignment.
     ///
+    /// ## Incremental wrapping
+    ///
+    /// When called with the same arguments as previously, lines preceding
+    /// any paragraph replaced by [`Self::edit_text`] are re-used. This is
+    /// not possible when [`Truncate::max_lines`] is used.
+    ///
     /// Returns the required height.
     pub fn prepare_lines(&mut self, wrap_width: f32, align_width: f32, h_align: Align) -> f32 {
+        let options = WrapOptions::default();
+        self.prepare_lines_impl(wrap_width, align_width, h_align, options)
+    }
+
+    pub(super) fn prepare_lines_impl(
+        &mut self,
+        wrap_width: f32,
+        align_width: f32,
+        h_align: Align,
+        options: WrapOptions<'_>,
+    ) -> f32 {
         debug_assert!(align_width.is_finite());
-        let mut adder = LineAdder::new(align_width, h_align);
+        let mut adder = LineAdder::new(
+            align_width,
+            h_align,
+            self.config.justify,
+            self.config.line_spacing,
+            self.config.truncate.clone(),
+        );
+
+        let max_lines = adder.max_lines;
+        adder.page = options.page;
+
+        // Re-use lines where possible (see Self::edit_text)
+        let params = (wrap_width, align_width, h_align);
+        let mut start = self.line_start_at(options.start);
+        let reuse = options.bounds.is_none() && options.start == 0 && options.page.is_none();
+        if reuse && self.wrap_params == Some(params) && self.valid_lines > 0 && max_lines == 0 {
+            let n = self.valid_lines;
+            let state = self.line_states[n - 1];
+            let run_end = to_usize(self.lines[n - 1].run_range.end);
+
+            self.wrapped_runs.truncate(run_end);
+            for run in &mut self.wrapped_runs {
+                run.offset -= self.offset;
+            }
+            self.synthetic_runs.truncate(to_usize(state.synthetic_runs));
+            self.lines.truncate(n);
+            for line in &mut self.lines {
+                line.top -= self.offset.1;
+                line.bottom -= self.offset.1;
+            }
+            self.line_states.truncate(n);
+
+            adder.wrapped_runs = std::mem::take(&mut self.wrapped_runs);
+            adder.synthetic_runs = std::mem::take(&mut self.synthetic_runs);
+            adder.lines = std::mem::take(&mut self.lines);
+            adder.states = std::mem::take(&mut self.line_states);
+            adder.vcaret = state.vcaret;
+            adder.l_bound = state.l_bound;
+            adder.r_bound = state.r_bound;
+            start = (to_usize(state.next_run), 0);
+        }
 
-        self.wrap_lines(&mut adder, wrap_width, 0);
+        self.wrap_lines(&mut adder, wrap_width, max_lines, start, options.bounds);
 
         self.wrapped_runs = adder.wrapped_runs;
+        self.synthetic_runs = adder.synthetic_runs;
         self.lines = adder.lines;
+        self.line_states = adder.states;
+        self.valid_lines = self.lines.len();
+        self.wrap_params = reuse.then_some(params);
+        self.offset = Vec2::ZERO;
         self.l_bound = adder.l_bound.min(adder.r_bound);
         self.r_bound = adder.r_bound;
-        adder.vcaret
+        self.block_size = adder.vcaret.vcaret;
+        adder.vcaret.vcaret
+    }
+
+    /// Find the wrapping position `(run, part)` for a line starting at text `index`
+    fn line_start_at(&self, index: usize) -> (usize, usize) {
+        let index = to_u32(index);
+        let i = self.runs.partition_point(|run| run.range.start < index);
+        if i == 0 || self.runs.get(i).is_some_and(|run| run.range.start == index) {
+            return (i, 0);
+        }
+        let run = &self.runs[i - 1];
+        if index >= run.range.end {
+            return (i, 0);
+        }
+        let part = run.breaks.iter().take_while(|b| b.index <= index).count();
+        (i - 1, part)
+    }
+
+    /// True if text `index` is the start of a paragraph
+    pub(super) fn is_paragraph_start(&self, index: usize) -> bool {
+        let (run, part) = self.line_start_at(index);
+        part == 0 && (run == 0 || self.runs[run - 1].special == RunSpecial::HardBreak)
+    }
+
+    /// Remove lines from `n` onwards
+    pub(super) fn truncate_lines(&mut self, n: usize) {
+        if n >= self.lines.len() {
+            return;
+        } else if n == 0 {
+            self.wrapped_runs.clear();
+            self.synthetic_runs.clear();
+            self.lines.clear();
+            self.line_states.clear();
+            self.valid_lines = 0;
+            self.l_bound = 0.0;
+            self.r_bound = 0.0;
+            self.block_size = 0.0;
+            return;
+        }
+
+        let state = self.line_states[n - 1];
+        self.wrapped_runs
+            .truncate(to_usize(self.lines[n - 1].run_range.end));
+        self.synthetic_runs.truncate(to_usize(state.synthetic_runs));
+        self.lines.truncate(n);
+        self.line_states.truncate(n);
+        self.valid_lines = self.valid_lines.min(n);
+        self.l_bound = state.l_bound.min(state.r_bound) + self.offset.0;
+        self.r_bound = state.r_bound + self.offset.0;
+        self.block_size = state.vcaret.vcaret + self.offset.1;
+    }
+
+    /// True if line `index` ends a paragraph
+    pub(super) fn line_ends_paragraph(&self, index: usize) -> bool {
+        self.line_states[index].para_end
     }
 
     fn wrap_lines(
@@ -238,15 +417,38 @@ impl Forme {
         accumulator: &mut impl PartAccumulator,
         wrap_width: f32,
         max_lines: usize,
+        start: (usize, usize),
+        bounds: Option<LineBounds<'_>>,
     ) {
         let fonts = fonts::library();
 
+        // The first run of the paragraph containing start
+        let mut para_run = start.0.min(self.runs.len());
+        while para_run > 0 && self.runs[para_run - 1].special != RunSpecial::HardBreak {
+            para_run -= 1;
+        }
+
         // Tuples: (index, part_index, num_parts)
-        let mut start = (0, 0, 0);
+        let mut start = (start.0, start.1, 0);
         let mut end = start;
+        let mut line_start = start;
 
         let mut caret = 0.0;
         let mut run_index = start.0;
+        // True if the line has a break point other than an emergency break
+        let mut has_break = false;
+
+        let mut fit = LineFit {
+            bounds,
+            wrap_width,
+            first_line: para_run == start.0 && start.1 == 0,
+            para_run,
+            format: LineFormat::default(),
+            width: wrap_width,
+            bottom: f32::INFINITY,
+        };
+        fit.begin(self, fonts, accumulator, run_index);
+        let mut plan = fit.plan(self, fonts, (start.0, start.1));
 
         let end_index = self.runs.len();
         'a: while run_index < end_index {
@@ -254,45 +456,73 @@ impl Forme {
             let num_parts = run.num_parts();
 
             let hard_break = run.special == RunSpecial::HardBreak;
-            let allow_break = run.special != RunSpecial::NoBreak;
+            let allow_break = run.allows_break_at_end(self.run_extras.get(run_index).no_wrap);
             let tab = run.special == RunSpecial::HTab;
 
             let mut last_part = start.1;
             let mut part_index = last_part + 1;
             while part_index <= num_parts {
                 let mut part = run.part_lengths(last_part..part_index);
-                if tab {
-                    // Tab runs have no glyph; instead we calculate part.len
-                    // based on the current line length.
-
-                    // TODO(bidi): we should really calculate this after
-                    // re-ordering the line based on full line contents,
-                    // then use a checkpoint reset if too long.
-
-                    let sf = fonts.get_face(run.face_id).scale_by_dpu(run.dpu);
-                    // TODO: custom tab sizes?
-                    let tab_size = sf.h_advance(sf.face().glyph_index(' ')) * 8.0;
-                    let stops = (caret / tab_size).floor() + 1.0;
-                    part.len = tab_size * stops - caret;
+                if tab && part_index == num_parts {
+                    // The tab follows the last part of the run.
+                    let x = caret + part.len;
+                    let advance = self.tab_advance(fonts, run_index, x, fit.width);
+                    part.len += fit.format.limit_tab(x, advance);
                 }
 
                 let line_len = caret + part.len_no_space;
-                if line_len > wrap_width && end.2 > 0 {
+                let wrap = match &plan {
+                    None => line_len > fit.width,
+                    Some(plan) => plan.wraps_at((end.0, end.1)),
+                };
+                if wrap && end.2 > 0 {
+                    if fit.refit(self, fonts, accumulator, end.2) {
+                        // The line is narrower than expected: start again
+                        start = line_start;
+                        end = (start.0, start.1, 0);
+                        caret = 0.0;
+                        run_index = start.0;
+                        has_break = false;
+                        continue 'a;
+                    }
+
                     // Add up to last valid break point then wrap and reset
-                    accumulator.add_line(fonts, &self.runs, end.2, true);
+                    accumulator.add_line(fonts, &self.runs, &self.run_extras, end.2, true);
 
-                    if accumulator.num_lines() == max_lines {
+                    if accumulator.num_lines() == max_lines || accumulator.is_full() {
                         return;
                     }
 
+                    if let Some(plan) = plan.as_mut() {
+                        plan.next_line();
+                    }
                     end.2 = 0;
                     start = end;
+                    line_start = start;
                     caret = 0.0;
                     run_index = start.0;
+                    has_break = false;
+                    fit.first_line = false;
+                    fit.begin(self, fonts, accumulator, run_index);
                     continue 'a;
                 }
                 caret += part.len;
-                let checkpoint = part_index < num_parts || allow_break;
+                let mut checkpoint = part_index < num_parts || allow_break;
+                let emergency = run.part_end_emergency(part_index);
+                if emergency && has_break {
+                    // Emergency breaks are only used when nothing else fits
+                    checkpoint = false;
+                }
+                if checkpoint
+                    && end.2 > 0
+                    && plan.is_none()
+                    && run.part_end_hyphen(part_index)
+                    && let Some((_, hyphen_len)) = hyphen_glyph(fonts, run)
+                    && line_len + hyphen_len > fit.width
+                {
+                    // We cannot wrap here since the hyphen would not fit
+                    checkpoint = false;
+                }
                 accumulator.add_part(
                     &self.runs,
                     run_index,
@@ -302,6 +532,7 @@ impl Forme {
                 );
                 if checkpoint {
                     end = (run_index, part_index, accumulator.num_parts());
+                    has_break |= !emergency;
                 }
                 last_part = part_index;
                 part_index += 1;
@@ -316,18 +547,33 @@ impl Forme {
                     // It should not be possible for a line to end with a no-break, so:
                     debug_assert_eq!(num_parts, end.2);
 
-                    accumulator.add_line(fonts, &self.runs, num_parts, false);
+                    if fit.refit(self, fonts, accumulator, num_parts) {
+                        start = line_start;
+                        end = (start.0, start.1, 0);
+                        caret = 0.0;
+                        run_index = start.0;
+                        has_break = false;
+                        continue 'a;
+                    }
+
+                    accumulator.add_line(fonts, &self.runs, &self.run_extras, num_parts, false);
 
-                    if accumulator.num_lines() == max_lines {
+                    if accumulator.num_lines() == max_lines || accumulator.is_full() {
                         return;
                     }
                 }
 
                 start = (run_index, 0, 0);
                 end = start;
+                line_start = start;
 
                 caret = 0.0;
                 run_index = start.0;
+                has_break = false;
+                fit.para_run = run_index;
+                fit.first_line = true;
+                fit.begin(self, fonts, accumulator, run_index);
+                plan = fit.plan(self, fonts, (run_index, 0));
             }
         }
     }
@@ -336,6 +582,10 @@ impl Forme {
     ///
     /// This is a low-level method which can be used for alignment in some
     /// cases. Cost is `O(w)` where `w` is the number of wrapped runs.
+    ///
+    /// In vertical [writing modes](Self::set_writing_mode), `offset` is
+    /// logical: `offset.0` is on the inline axis and `offset.1` on the block
+    /// axis.
     pub fn apply_offset(&mut self, offset: Vec2) {
         for run in &mut self.wrapped_runs {
             run.offset += offset;
@@ -346,6 +596,7 @@ impl Forme {
         }
         self.l_bound += offset.0;
         self.r_bound += offset.0;
+        self.offset += offset;
     }
 
     /// Adjust horizontal alignment to avoid left-overhangs
@@ -363,6 +614,7 @@ impl Forme {
             for run in &mut self.wrapped_runs {
                 run.offset.0 -= self.l_bound;
             }
+            self.offset.0 -= self.l_bound;
             self.l_bound = 0.0;
             self.r_bound -= self.l_bound;
         }
@@ -389,6 +641,7 @@ impl Forme {
             Align::BR => bound - height,
         };
         let offset = new_offset - top;
+        self.block_size = bound;
 
         if offset != 0.0 {
             self.apply_offset(Vec2(0.0, offset));
@@ -396,9 +649,153 @@ impl Forme {
     }
 }
 
+/// Callback supplying the inline interval available to a line
+///
+/// See [`Forme::prepare_lines_in`].
+pub(super) type LineBounds<'b> = &'b mut dyn FnMut(f32, f32) -> (f32, f32);
+
+/// Limit on the height of lines; see [`Forme::prepare_box`]
+#[derive(Clone, Copy, Debug)]
+pub(super) struct PageLimit {
+    pub(super) height: f32,
+    /// Minimum number of lines of a paragraph to wrap after the limit
+    pub(super) widows: usize,
+}
+
+/// Options for [`Forme::prepare_lines_impl`]
+#[derive(Default)]
+pub(super) struct WrapOptions<'b> {
+    /// Text index of the first line
+    pub(super) start: usize,
+    pub(super) bounds: Option<LineBounds<'b>>,
+    pub(super) page: Option<PageLimit>,
+}
+
+/// Line width tracking for [`Forme::wrap_lines`]
+struct LineFit<'b> {
+    bounds: Option<LineBounds<'b>>,
+    wrap_width: f32,
+    /// The first run of the current paragraph
+    para_run: usize,
+    first_line: bool,
+    format: LineFormat,
+    /// Width available to the current line
+    width: f32,
+    /// Bottom of the band used to query `bounds`
+    bottom: f32,
+}
+
+impl<'b> LineFit<'b> {
+    /// Resolve the format of a line occupying `band`
+    fn resolve(&mut self, forme: &Forme, band: (f32, f32)) -> LineFormat {
+        let mut format = forme.line_format(self.para_run, self.first_line);
+        if let Some(bounds) = self.bounds.as_mut() {
+            let (x0, x1) = bounds(band.0, band.1);
+            let left = x0.max(0.0);
+            let right = (self.wrap_width - x1).max(0.0);
+            let is_rtl = forme
+                .runs
+                .get(self.para_run)
+                .is_some_and(|run| run.base_level.is_rtl());
+            match is_rtl {
+                false => (format.start, format.end) = (format.start + left, format.end + right),
+                true => (format.start, format.end) = (format.start + right, format.end + left),
+            }
+        }
+        format
+    }
+
+    fn set_format(&mut self, accumulator: &mut impl PartAccumulator, format: LineFormat) {
+        self.format = format;
+        self.width = self.wrap_width - format.start - format.end;
+        accumulator.set_format(format);
+    }
+
+    /// Begin a line starting with run `run_index`
+    ///
+    /// The line height is estimated from the run.
+    fn begin(
+        &mut self,
+        forme: &Forme,
+        fonts: &FontLibrary,
+        accumulator: &mut impl PartAccumulator,
+        run_index: usize,
+    ) {
+        let mut band = (0.0, f32::INFINITY);
+        if self.bounds.is_some() {
+            let mut line_box = LineBox::default();
+            if let Some(run) = forme.runs.get(run_index) {
+                let extra = forme.run_extras.get(run_index);
+                line_box.add_run(&forme.config.line_spacing, fonts, run, extra);
+            }
+            band = accumulator.vcaret().line_band(&line_box);
+        }
+        self.bottom = band.1;
+        let format = self.resolve(forme, band);
+        self.set_format(accumulator, format);
+    }
+
+    /// Plan line breaks for the paragraph from `start = (run, part)`
+    ///
+    /// Returns `None` when wrapping greedily.
+    fn plan(&self, forme: &Forme, fonts: &FontLibrary, start: (usize, usize)) -> Option<LinePlan> {
+        if self.bounds.is_some() {
+            return None;
+        }
+        let width = |first_line| {
+            let format = forme.line_format(self.para_run, first_line);
+            self.wrap_width - format.start - format.end
+        };
+        forme.plan_lines(fonts, start, (width(self.first_line), width(false)))
+    }
+
+    /// Check the width of a line ending at `parts_end`, given its actual height
+    ///
+    /// Returns true when the line is narrower than expected and must be
+    /// re-wrapped; in this case parts are cleared.
+    fn refit(
+        &mut self,
+        forme: &Forme,
+        fonts: &FontLibrary,
+        accumulator: &mut impl PartAccumulator,
+        parts_end: usize,
+    ) -> bool {
+        if self.bounds.is_none() {
+            return false;
+        }
+        let line_box = accumulator.line_box(fonts, &forme.runs, &forme.run_extras, parts_end);
+        let band = accumulator.vcaret().line_band(&line_box);
+        if band.1 <= self.bottom {
+            return false;
+        }
+
+        self.bottom = band.1;
+        let format = self.resolve(forme, band);
+        let narrower = format.start + format.end > self.format.start + self.format.end;
+        self.set_format(accumulator, format);
+        if narrower {
+            accumulator.clear_parts();
+        }
+        narrower
+    }
+}
+
 trait PartAccumulator {
     fn num_parts(&self) -> usize;
     fn num_lines(&self) -> usize;
+    fn vcaret(&self) -> &VCaret;
+
+    /// Get the line box of parts up to `parts_end`
+    fn line_box(
+        &self,
+        fonts: &FontLibrary,
+        runs: &[GlyphRun],
+        extras: &RunExtras,
+        parts_end: usize,
+    ) -> LineBox;
+
+    /// Discard all parts of the current line
+    fn clear_parts(&mut self);
 
     fn add_part(
         &mut self,
@@ -409,44 +806,340 @@ trait PartAccumulator {
         checkpoint: bool,
     );
 
-    fn add_line(&mut self, fonts: &FontLibrary, runs: &[GlyphRun], parts_end: usize, is_wrap: bool);
+    /// Set the format of the current line
+    fn set_format(&mut self, _: LineFormat) {}
+
+    /// True when no further lines are required
+    fn is_full(&self) -> bool {
+        false
+    }
+
+    fn add_line(
+        &mut self,
+        fonts: &FontLibrary,
+        runs: &[GlyphRun],
+        extras: &RunExtras,
+        parts_end: usize,
+        is_wrap: bool,
+    );
 }
 
 #[derive(Clone, Debug)]
-struct PartInfo {
-    run: u32,
-    offset: f32,
-    len: f32,
-    len_no_space: f32,
-    glyph_range: Range,
-    end_space: bool,
+pub(super) struct PartInfo {
+    pub(super) run: u32,
+    pub(super) offset: f32,
+    pub(super) len: f32,
+    pub(super) len_no_space: f32,
+    pub(super) glyph_range: Range,
+    pub(super) end_space: bool,
+    /// Space added at the logical end of this part (justification)
+    pub(super) extra: f32,
+    /// If true, `extra` space is filled with tatweel glyphs
+    pub(super) kashida: bool,
+    /// Length of a hyphen displayed at the logical end of this part
+    pub(super) hyphen: f32,
+    /// Length of an ellipsis displayed adjacent to this part
+    pub(super) ellipsis: f32,
+    /// If true, the ellipsis is at the logical start of this part
+    pub(super) ellipsis_start: bool,
+}
+
+/// State of line wrapping after a line
+#[derive(Clone, Copy, Debug)]
+pub(super) struct LineState {
+    vcaret: VCaret,
+    l_bound: f32,
+    r_bound: f32,
+    synthetic_runs: u32,
+    /// Index of the next run (valid where the line ends a paragraph)
+    next_run: u32,
+    /// True if the line ends a paragraph
+    para_end: bool,
 }
 
 struct LineAdder {
     wrapped_runs: TinyVec<[RunPart; 1]>,
+    synthetic_runs: Vec<(GlyphRun, RunExtra)>,
     parts: Vec<PartInfo>,
     lines: TinyVec<[Line; 1]>,
-    line_gap: f32,
+    states: Vec<LineState>,
     l_bound: f32,
     r_bound: f32,
-    vcaret: f32,
+    vcaret: VCaret,
+    baseline: f32,
     h_align: Align,
     align_width: f32,
+    justify: Justify,
+    spacing: LineSpacing,
+    truncate: Truncate,
+    max_lines: usize,
+    /// Shaped ellipsis for the current line
+    ellipsis: Option<(GlyphRun, RunExtra)>,
+    /// Format of the current line
+    format: LineFormat,
+    page: Option<PageLimit>,
+    /// Index of the first line exceeding the page limit
+    overflow: Option<usize>,
 }
 impl LineAdder {
-    fn new(align_width: f32, h_align: Align) -> Self {
+    fn new(
+        align_width: f32,
+        h_align: Align,
+        justify: Justify,
+        spacing: LineSpacing,
+        truncate: Truncate,
+    ) -> Self {
+        let max_lines = truncate.max_lines.map(|n| n.get()).unwrap_or(0);
         LineAdder {
             wrapped_runs: Default::default(),
+            synthetic_runs: vec![],
             parts: Vec::with_capacity(16),
             lines: Default::default(),
-            line_gap: 0.0,
+            states: vec![],
             l_bound: align_width,
             r_bound: 0.0,
-            vcaret: 0.0,
+            vcaret: VCaret::new(spacing),
+            baseline: 0.0,
             h_align,
             align_width,
+            justify,
+            spacing,
+            truncate,
+            max_lines,
+            ellipsis: None,
+            format: LineFormat::default(),
+            page: None,
+            overflow: None,
         }
     }
+
+    /// Width available to the current line
+    fn line_width(&self) -> f32 {
+        self.align_width - self.format.start - self.format.end
+    }
+
+    /// Alignment of the current line
+    fn align(&self) -> Align {
+        self.format.align.unwrap_or(self.h_align)
+    }
+
+    /// Truncate the current line, if required
+    ///
+    /// Parts are reduced and an ellipsis is attached to one part.
+    fn truncate_line(
+        &mut self,
+        fonts: &FontLibrary,
+        runs: &[GlyphRun],
+        extras: &RunExtras,
+        is_wrap: bool,
+    ) {
+        let last_run = to_usize(self.parts.last().unwrap().run);
+        let limit = self.lines.len() + 1 == self.max_lines
+            && (is_wrap || runs[last_run + 1..].iter().any(|run| !run.range.is_empty()));
+        let position = if limit {
+            TruncatePosition::End
+        } else if self.truncate.width && line_len(&mut self.parts) > self.line_width() {
+            self.truncate.position
+        } else {
+            return;
+        };
+
+        let run_index = match position {
+            TruncatePosition::Start => to_usize(self.parts[0].run),
+            _ => last_run,
+        };
+        let orientation = extras.get(run_index).orientation;
+        let ellipsis = self
+            .truncate
+            .shape_ellipsis(fonts, &runs[run_index], orientation);
+        let width = ellipsis.0.caret;
+        let budget = (self.line_width() - width).max(0.0);
+        self.ellipsis = Some(ellipsis);
+        self.parts.last_mut().unwrap().hyphen = 0.0;
+
+        let part = match position {
+            TruncatePosition::End => {
+                truncate::keep_start(fonts, runs, &mut self.parts, budget);
+                self.parts.last_mut().unwrap()
+            }
+            TruncatePosition::Start => {
+                truncate::keep_end(fonts, runs, &mut self.parts, budget);
+                &mut self.parts[0]
+            }
+            TruncatePosition::Middle => {
+                let mut head = self.parts.clone();
+                let len = truncate::keep_start(fonts, runs, &mut head, 0.5 * budget);
+                truncate::keep_end(fonts, runs, &mut self.parts, budget - len);
+                let i = head.len() - 1;
+                head.append(&mut self.parts);
+                self.parts = head;
+                &mut self.parts[i]
+            }
+        };
+        part.ellipsis = width;
+        part.ellipsis_start = position == TruncatePosition::Start;
+    }
+
+    /// Fill `part.extra` space at `x` with tatweel glyphs
+    fn push_tatweel(
+        &mut self,
+        fonts: &FontLibrary,
+        run: &GlyphRun,
+        orientation: Orientation,
+        part: &PartInfo,
+        x: f32,
+    ) {
+        let face = fonts.get_face(run.face_id);
+        let id = face.glyph_index('\u{0640}');
+        let advance = face.scale_by_dpu(run.dpu).h_advance(id);
+        if !(advance > 0.0) {
+            return;
+        }
+
+        let width = part.extra;
+        let n = (width / advance).ceil().max(1.0);
+        let (mut x0, step) = if n > 1.0 {
+            (0.0, (width - advance) / (n - 1.0))
+        } else {
+            (0.5 * (width - advance), 0.0)
+        };
+        let mut positions = Vec::with_capacity(n as usize);
+        for _ in 0..(n as usize) {
+            positions.push((id, x0));
+            x0 += step;
+        }
+        self.push_synthetic(run, orientation, part, positions, width, x);
+    }
+
+    /// Push a hyphen at `x` following `part`
+    fn push_hyphen(
+        &mut self,
+        fonts: &FontLibrary,
+        run: &GlyphRun,
+        orientation: Orientation,
+        part: &PartInfo,
+        x: f32,
+    ) {
+        if let Some((id, _)) = hyphen_glyph(fonts, run) {
+            self.push_synthetic(run, orientation, part, vec![(id, 0.0)], part.hyphen, x);
+        }
+    }
+
+    /// Push a synthetic run of `glyphs` at the logical end of `part`
+    ///
+    /// Glyphs use the face and text index of the last glyph of `part`.
+    /// Synthetic runs are positioned at `x` and are `width` long.
+    fn push_synthetic(
+        &mut self,
+        run: &GlyphRun,
+        orientation: Orientation,
+        part: &PartInfo,
+        glyphs: Vec<(GlyphId, f32)>,
+        width: f32,
+        x: f32,
+    ) {
+        let index = run.glyphs[part.glyph_range.end() - 1].index;
+        let glyphs: Vec<_> = glyphs
+            .into_iter()
+            .map(|(id, x)| Glyph {
+                index,
+                id,
+                position: Vec2(x, 0.0),
+            })
+            .collect();
+
+        let mut text_end = run.range.end;
+        if part.glyph_range.end() < run.glyphs.len() {
+            text_end = run.glyphs[part.glyph_range.end()].index;
+        }
+        let glyph_run = GlyphRun {
+            range: Range::from(index..text_end),
+            dpem: run.dpem,
+            dpu: run.dpu,
+            face_id: run.face_id,
+            special: RunSpecial::NoBreak,
+            base_level: run.base_level,
+            level: run.level,
+            glyphs,
+            breaks: Default::default(),
+            no_space_end: width,
+            caret: width,
+        };
+        let extra = RunExtra {
+            orientation,
+            ..Default::default()
+        };
+        self.push_synthetic_run((glyph_run, extra), x);
+    }
+
+    /// Push a synthetic `run` at `x`
+    ///
+    /// The run's text range should be set by the caller.
+    fn push_synthetic_run(&mut self, run: (GlyphRun, RunExtra), x: f32) {
+        let text_end = run.0.range.end;
+        let glyph_range = Range::from(0..run.0.glyphs.len());
+        let glyph_run = to_u32(self.synthetic_runs.len()) | RunPart::SYNTHETIC;
+        self.synthetic_runs.push(run);
+        self.wrapped_runs.push(RunPart {
+            text_end,
+            glyph_run,
+            glyph_range,
+            offset: Vec2(x, self.baseline),
+        });
+    }
+
+    /// Push the ellipsis at `x` with text index `index`
+    fn push_ellipsis(&mut self, index: u32, x: f32) {
+        if let Some(mut run) = self.ellipsis.take() {
+            for glyph in &mut run.0.glyphs {
+                glyph.index = index;
+            }
+            run.0.range = Range::from(index..index);
+            self.push_synthetic_run(run, x);
+        }
+    }
+}
+
+/// Calculate the length of a line, setting [`PartInfo::end_space`]
+fn line_len(parts: &mut [PartInfo]) -> f32 {
+    // With bidi text, the logical end may not actually be at the end;
+    // we must not allow spaces here to move other content.
+    for part in parts.iter_mut() {
+        part.end_space = false;
+    }
+    for part in parts.iter_mut().rev() {
+        part.end_space = true;
+
+        if part.len_no_space > 0.0 {
+            break;
+        }
+    }
+
+    let mut line_len = 0.0;
+    for part in parts.iter() {
+        line_len += if part.end_space {
+            part.len_no_space
+        } else {
+            part.len + part.extra
+        };
+        line_len += part.hyphen + part.ellipsis;
+    }
+    line_len
+}
+
+/// Get the hyphen glyph and its advance for `run`
+///
+/// Uses U+2010 HYPHEN where available, otherwise U+002D HYPHEN-MINUS.
+pub(super) fn hyphen_glyph(fonts: &FontLibrary, run: &GlyphRun) -> Option<(GlyphId, f32)> {
+    let face = fonts.get_face(run.face_id);
+    let mut id = face.glyph_index('\u{2010}');
+    if id.0 == 0 {
+        id = face.glyph_index('-');
+    }
+    if id.0 == 0 {
+        return None;
+    }
+    Some((id, face.scale_by_dpu(run.dpu).h_advance(id)))
 }
 
 impl PartAccumulator for LineAdder {
@@ -458,6 +1151,50 @@ impl PartAccumulator for LineAdder {
         self.lines.len()
     }
 
+    fn vcaret(&self) -> &VCaret {
+        &self.vcaret
+    }
+
+    fn line_box(
+        &self,
+        fonts: &FontLibrary,
+        runs: &[GlyphRun],
+        extras: &RunExtras,
+        parts_end: usize,
+    ) -> LineBox {
+        let mut last_run = u32::MAX;
+        let mut line_box = LineBox::default();
+        for part in &self.parts[..parts_end] {
+            if last_run == part.run {
+                continue;
+            }
+            last_run = part.run;
+            let run_index = to_usize(last_run);
+            line_box.add_run(
+                &self.spacing,
+                fonts,
+                &runs[run_index],
+                extras.get(run_index),
+            );
+        }
+        line_box
+    }
+
+    fn clear_parts(&mut self) {
+        self.parts.clear();
+    }
+
+    fn set_format(&mut self, format: LineFormat) {
+        self.format = format;
+    }
+
+    fn is_full(&self) -> bool {
+        let (Some(page), Some(index)) = (self.page, self.overflow) else {
+            return false;
+        };
+        self.states.last().unwrap().para_end || self.lines.len() - index >= page.widows
+    }
+
     fn add_part(
         &mut self,
         runs: &[GlyphRun],
@@ -468,11 +1205,21 @@ impl PartAccumulator for LineAdder {
     ) {
         let glyph_run = &runs[run_index];
         let run = to_u32(run_index);
-        let glyph_range = glyph_run.to_glyph_range(part_range);
+        let glyph_range = glyph_run.to_glyph_range(part_range.clone());
+
+        // Tab advance is stored as extra space
+        let mut part = part;
+        let mut extra = 0.0;
+        if glyph_run.special == RunSpecial::HTab && part_range.end == glyph_run.num_parts() {
+            let len = glyph_run.part_lengths(part_range).len;
+            extra = part.len - len;
+            part.len = len;
+        }
 
-        let justify = self.h_align == Align::Stretch && part.len_no_space > 0.0;
+        let justify = self.align() == Align::Stretch && part.len_no_space > 0.0;
         if checkpoint
             && !justify
+            && extra == 0.0
             && let Some(info) = self.parts.last_mut()
             && info.run == run
         {
@@ -499,6 +1246,11 @@ impl PartAccumulator for LineAdder {
             len_no_space: part.len_no_space,
             glyph_range,
             end_space: false, // set later
+            extra,
+            kashida: false,
+            hyphen: 0.0,
+            ellipsis: 0.0,
+            ellipsis_start: false,
         });
     }
 
@@ -506,30 +1258,27 @@ impl PartAccumulator for LineAdder {
         &mut self,
         fonts: &FontLibrary,
         runs: &[GlyphRun],
+        extras: &RunExtras,
         parts_end: usize,
         is_wrap: bool,
     ) {
         debug_assert!(parts_end > 0);
         let line_start = self.wrapped_runs.len();
-        let parts = &mut self.parts[..parts_end];
+        // Parts after parts_end are discarded (re-added on the next line)
+        self.parts.truncate(parts_end);
+        let line_box = self.line_box(fonts, runs, extras, parts_end);
 
-        // Iterate runs to determine max ascent, level, etc.
+        // Iterate runs to determine level, etc.
         let mut last_run = u32::MAX;
-        let (mut ascent, mut descent, mut line_gap) = (0f32, 0f32, 0f32);
         let mut base_level = LTR_LEVEL;
         let mut max_level = LTR_LEVEL;
-        for (i, part) in parts.iter().enumerate() {
+        for (i, part) in self.parts.iter().enumerate() {
             if last_run == part.run {
                 continue;
             }
             last_run = part.run;
             let run = &runs[to_usize(last_run)];
 
-            let scale_font = fonts.get_face(run.face_id).scale_by_dpu(run.dpu);
-            ascent = ascent.max(scale_font.ascent());
-            descent = descent.min(scale_font.descent());
-            line_gap = line_gap.max(scale_font.line_gap());
-
             if i > 0 {
                 // All runs on a line should have the same base (paragraph) level
                 debug_assert_eq!(base_level, run.base_level);
@@ -538,26 +1287,10 @@ impl PartAccumulator for LineAdder {
             max_level = max_level.max(run.level);
         }
 
-        if !self.lines.is_empty() {
-            self.vcaret += line_gap.max(self.line_gap);
-        }
-        self.vcaret += ascent;
-        self.line_gap = line_gap;
+        self.baseline = self.vcaret.begin_line(&line_box);
+        let parts = self.parts.as_mut_slice();
 
-        let line_text_start = {
-            let part = &parts[0];
-            let run = &runs[to_usize(part.run)];
-            if part.glyph_range.start() < run.glyphs.len() {
-                run.glyphs[part.glyph_range.start()].index
-            } else {
-                run.range.start
-            }
-        };
-
-        // Adjust the (logical) tail: optionally exclude last glyph, calculate
-        // line_text_end, trim whitespace for the purposes of layout.
-        let mut line_text_end;
-        let mut line_len = 0.0;
+        // Adjust the (logical) tail: optionally exclude last glyph, add hyphen.
         {
             let part = &mut parts[parts.len() - 1];
             let run = &runs[to_usize(part.run)];
@@ -573,114 +1306,65 @@ impl PartAccumulator for LineAdder {
                 }
             }
 
-            line_text_end = run.range.end;
-            if part.glyph_range.end() < run.glyphs.len() {
-                line_text_end = run.glyphs[part.glyph_range.end()].index;
+            if is_wrap
+                && !part.glyph_range.is_empty()
+                && run.hyphen_before(part.glyph_range.end)
+                && let Some((_, len)) = hyphen_glyph(fonts, run)
+            {
+                part.hyphen = len;
             }
+        }
 
-            // With bidi text, the logical end may not actually be at the end;
-            // we must not allow spaces here to move other content.
-            for part in parts.iter_mut().rev() {
-                part.end_space = true;
+        self.truncate_line(fonts, runs, extras, is_wrap);
+        let parts = self.parts.as_mut_slice();
 
-                if part.len_no_space > 0.0 {
-                    break;
-                }
+        let line_text_start = {
+            let part = &parts[0];
+            let run = &runs[to_usize(part.run)];
+            if part.glyph_range.start() < run.glyphs.len() {
+                run.glyphs[part.glyph_range.start()].index
+            } else {
+                run.range.start
             }
-            for part in parts.iter() {
-                line_len += if part.end_space {
-                    part.len_no_space
-                } else {
-                    part.len
-                };
+        };
+        let line_text_end = {
+            let part = &parts[parts.len() - 1];
+            let run = &runs[to_usize(part.run)];
+            if part.glyph_range.end() < run.glyphs.len() {
+                run.glyphs[part.glyph_range.end()].index
+            } else {
+                run.range.end
             }
-        }
+        };
 
-        // Unic TR#9 L2: reverse items on the line
-        // This implementation does not correspond directly to the Unicode
-        // algorithm, which assumes that shaping happens *after* re-arranging
-        // chars (but also *before*, in order to calculate line-wrap points).
-        // Our shaper(s) accept both LTR and RTL input; additionally, our line
-        // wrapping must explicitly handle both LTR and RTL lines; the missing
-        // step is to rearrange non-wrapped runs on the line.
+        // Trim whitespace for the purposes of layout.
+        let line_len = line_len(parts);
 
-        {
-            let mut level = max_level;
-            while level > Level::ltr() {
-                let mut start = None;
-                for i in 0..parts.len() {
-                    let part_level = runs[to_usize(parts[i].run)].level;
-                    if let Some(s) = start {
-                        if part_level < level {
-                            parts[s..i].reverse();
-                            start = None;
-                        }
-                    } else if part_level >= level {
-                        start = Some(i);
-                    }
-                }
-                if let Some(s) = start {
-                    parts[s..].reverse();
-                }
-                level.lower(1).unwrap();
-            }
+        let line_is_rtl = base_level.is_rtl();
+        let mut spare = self.line_width() - line_len;
+        let mut align = self.align();
+        if align == Align::Stretch && !is_wrap {
+            align = self.justify.last_line;
         }
+        if align == Align::Stretch && spare > 0.0 {
+            // Justify: distribute space (in logical order) then re-order.
+            spare -= self
+                .justify
+                .apply(fonts, runs, extras, &mut self.parts, spare);
+        }
+        // Unic TR#9 L2: reverse items on the line
+        reorder_parts(runs, &mut self.parts, max_level);
 
-        let line_is_rtl = base_level.is_rtl();
-        let spare = self.align_width - line_len;
-        let mut is_gap = SmallVec::<[bool; 16]>::new();
-        let mut per_gap = 0.0;
-        let mut caret = match self.h_align {
+        let mut caret = match line_is_rtl {
+            false => self.format.start,
+            true => self.format.end,
+        };
+        caret += match align {
             Align::Default if line_is_rtl => spare,
             Align::Default => 0.0,
             Align::TL => 0.0,
             Align::Center => 0.5 * spare,
             Align::BR => spare,
-            Align::Stretch if is_wrap && spare > 0.0 => {
-                let len = parts.len();
-                is_gap.resize(len, false);
-                let mut num_gaps = 0;
-                for (i, part) in parts[..len - 1].iter().enumerate() {
-                    let run = &runs[to_usize(part.run)];
-                    let not_at_end = part.glyph_range.end() < run.glyphs.len();
-                    if not_at_end || run.special != RunSpecial::NoBreak {
-                        is_gap[i] = true;
-                        num_gaps += 1;
-                    }
-                }
-
-                // Apply per-level reversing to is_gap
-                let mut start = 0;
-                let mut level = runs[to_usize(parts[0].run)].level;
-                for i in 1..len {
-                    let new_level = runs[to_usize(parts[i].run)].level;
-                    if level != new_level {
-                        if level > base_level {
-                            is_gap[start..i].reverse();
-                        }
-                        start = i;
-                        level = new_level;
-                    }
-                }
-                if level > base_level {
-                    is_gap[start..len - 1].reverse();
-                }
-
-                // Shift left 1 part for RTL lines
-                if line_is_rtl {
-                    is_gap.copy_within(1..len, 0);
-                }
-
-                if num_gaps == 0 {
-                    match line_is_rtl {
-                        false => 0.0,
-                        true => spare,
-                    }
-                } else {
-                    per_gap = spare / (num_gaps as f32);
-                    0.0
-                }
-            }
             Align::Stretch if line_is_rtl => spare,
             Align::Stretch => 0.0,
         };
@@ -688,7 +1372,8 @@ impl PartAccumulator for LineAdder {
         self.l_bound = self.l_bound.min(caret);
         let mut end_caret = caret;
 
-        for (i, part) in parts.iter().enumerate() {
+        for i in 0..self.parts.len() {
+            let part = self.parts[i].clone();
             let run = &runs[to_usize(part.run)];
 
             let mut text_end = run.range.end;
@@ -698,12 +1383,36 @@ impl PartAccumulator for LineAdder {
             debug_assert!(text_end <= line_text_end);
 
             let mut offset = 0.0;
+            let mut hyphen_x = None;
+            let mut ellipsis_x = None;
+            let ellipsis_is_left = run.level.is_rtl() != part.ellipsis_start;
             if run.level.is_rtl() {
                 offset = part.len_no_space - part.len;
+                if part.hyphen > 0.0 {
+                    // The hyphen is left of content
+                    hyphen_x = Some(if part.end_space { end_caret } else { caret });
+                    caret += part.hyphen;
+                    end_caret += part.hyphen;
+                }
+            }
+            if part.ellipsis > 0.0 && ellipsis_is_left {
+                ellipsis_x = Some(if part.end_space { end_caret } else { caret });
+                caret += part.ellipsis;
+                end_caret += part.ellipsis;
             }
 
+            // Extra space is at the logical end of the part, except that tab
+            // space is at the logical end of the paragraph.
+            let is_tab =
+                run.special == RunSpecial::HTab && part.glyph_range.end() == run.glyphs.len();
+            let extra_is_left = match is_tab {
+                false => run.level.is_rtl(),
+                true => line_is_rtl,
+            };
             let xoffset = if part.end_space {
                 end_caret - part.offset + offset
+            } else if extra_is_left {
+                caret + part.extra - part.offset
             } else {
                 caret - part.offset
             };
@@ -712,20 +1421,51 @@ impl PartAccumulator for LineAdder {
                 text_end,
                 glyph_run: part.run,
                 glyph_range: part.glyph_range,
-                offset: Vec2(xoffset, self.vcaret),
+                offset: Vec2(xoffset, self.baseline),
             });
+            if run.level.is_ltr() && part.hyphen > 0.0 {
+                // The hyphen is right of content
+                hyphen_x = Some(if part.end_space { end_caret } else { caret } + part.len_no_space);
+                caret += part.hyphen;
+                end_caret += part.hyphen;
+            }
+            if part.ellipsis > 0.0 && !ellipsis_is_left {
+                ellipsis_x =
+                    Some(if part.end_space { end_caret } else { caret } + part.len_no_space);
+                caret += part.ellipsis;
+                end_caret += part.ellipsis;
+            }
+            if let Some(x) = hyphen_x {
+                let orientation = extras.get(to_usize(part.run)).orientation;
+                self.push_hyphen(fonts, run, orientation, &part, x);
+            }
+            if let Some(x) = ellipsis_x {
+                let index = match part.ellipsis_start {
+                    false => text_end,
+                    true => run
+                        .glyphs
+                        .get(part.glyph_range.start())
+                        .map(|g| g.index)
+                        .unwrap_or(text_end),
+                };
+                self.push_ellipsis(index, x);
+            }
+
             if part.end_space {
                 caret += part.len_no_space;
                 end_caret += part.len;
             } else {
-                caret += part.len;
+                if part.kashida {
+                    let x = match run.level.is_rtl() {
+                        false => caret + part.len,
+                        true => caret,
+                    };
+                    let orientation = extras.get(to_usize(part.run)).orientation;
+                    self.push_tatweel(fonts, run, orientation, &part, x);
+                }
+                caret += part.len + part.extra;
                 end_caret = caret;
             }
-
-            if is_gap.len() > 0 && is_gap[i] {
-                caret += per_gap;
-                end_caret += per_gap;
-            }
         }
 
         self.r_bound = self.r_bound.max(caret);
@@ -735,17 +1475,135 @@ impl PartAccumulator for LineAdder {
         // TODO: should we change this, e.g. for visual-order navigation?
         self.wrapped_runs[line_start..].sort_by_key(|run| run.text_end);
 
-        let top = self.vcaret - ascent;
-        self.vcaret -= descent;
-        // Vertically align lines to the nearest pixel (improves rendering):
-        self.vcaret = self.vcaret.round();
+        let top = self.baseline - line_box.ascent;
+        let bottom = self.vcaret.end_line(&line_box, !is_wrap);
 
         self.lines.push(Line {
             text_range: Range::from(line_text_start..line_text_end),
             run_range: (line_start..self.wrapped_runs.len()).into(),
             top,
-            bottom: self.vcaret,
+            bottom,
+        });
+        self.states.push(LineState {
+            vcaret: self.vcaret,
+            l_bound: self.l_bound,
+            r_bound: self.r_bound,
+            synthetic_runs: to_u32(self.synthetic_runs.len()),
+            next_run: self.parts.iter().map(|part| part.run).max().unwrap() + 1,
+            para_end: !is_wrap,
         });
+        if let Some(page) = self.page
+            && self.overflow.is_none()
+            && bottom > page.height
+        {
+            self.overflow = Some(self.lines.len() - 1);
+        }
         self.parts.clear();
     }
 }
+
+/// Unic TR#9 L2: reverse items on the line
+///
+/// This implementation does not correspond directly to the Unicode
+/// algorithm, which assumes that shaping happens *after* re-arranging
+/// chars (but also *before*, in order to calculate line-wrap points).
+/// Our shaper(s) accept both LTR and RTL input; additionally, our line
+/// wrapping must explicitly handle both LTR and RTL lines; the missing
+/// step is to rearrange non-wrapped runs on the line.
+fn reorder_parts(runs: &[GlyphRun], parts: &mut [PartInfo], max_level: Level) {
+    let mut level = max_level;
+    while level > Level::ltr() {
+        let mut start = None;
+        for i in 0..parts.len() {
+            let part_level = runs[to_usize(parts[i].run)].level;
+            if let Some(s) = start {
+                if part_level < level {
+                    parts[s..i].reverse();
+                    start = None;
+                }
+            } else if part_level >= level {
+                start = Some(i);
+            }
+        }
+        if let Some(s) = start {
+            parts[s..].reverse();
+        }
+        level.lower(1).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fonts;
+    use crate::forme::test_util::{glyph_pos, shape, shape_with};
+    use crate::hyphenation::{self, Hyphenator};
+    use crate::{Align, Forme, Vec2};
+
+    /// Count hyphen glyphs per line
+    fn hyphens(forme: &Forme) -> Vec<usize> {
+        let mut counts = vec![0; forme.num_lines()];
+        for run in forme.runs::<()>(Vec2::ZERO, &[]) {
+            let face = fonts::library().get_face(run.face_id());
+            let hyphen = face.glyph_index('\u{2010}');
+            let n = run.glyphs().filter(|g| g.id == hyphen).count();
+            let line = forme
+                .lines()
+                .position(|line| line.top() == run.line_top())
+                .unwrap();
+            counts[line] += n;
+        }
+        counts
+    }
+
+    #[test]
+    fn soft_hyphen() {
+        let text = "An extra\u{AD}ordinary word";
+        let mut forme = Forme::default();
+        shape(&mut forme, text);
+        let width = forme.measure_width(f32::INFINITY);
+
+        forme.prepare_lines(width, width, Align::Default);
+        assert_eq!(forme.num_lines(), 1);
+        assert_eq!(hyphens(&forme), [0]);
+
+        let wrap_width = 0.6 * width;
+        let height = forme.prepare_lines(wrap_width, wrap_width, Align::Default);
+        assert_eq!(forme.num_lines(), 3);
+        assert_eq!(forme.get_line(0).unwrap().text_range(), 0..10);
+        assert_eq!(hyphens(&forme), [1, 0, 0]);
+        assert!(forme.bounding_box().1.0 <= wrap_width);
+        assert_eq!(forme.measure_height(wrap_width, None), height);
+
+        // The hyphen follows the line-end caret position
+        assert!(glyph_pos(&forme, 10).0 < forme.bounding_box().1.0);
+    }
+
+    #[test]
+    fn auto_hyphenation() {
+        let patterns = ".hy3ph he2n hena4 hen5at 1na n2at 1tio 2io o2n";
+        let hyphenator = Hyphenator::new(patterns, "").unwrap();
+        let locale = icu_locale::langid!("en");
+        hyphenation::register(locale.language, hyphenator);
+
+        let text = "Automatic hyphenation";
+        let prepare = |enable: bool| {
+            let mut forme = Forme::default();
+            shape_with(&mut forme, text, |appender| {
+                appender
+                    .with_content_locale(&locale)
+                    .with_hyphenation(enable);
+            });
+            let width = forme.measure_width(f32::INFINITY);
+            forme.prepare_lines(0.8 * width, width, Align::Default);
+            forme
+        };
+
+        let forme = prepare(false);
+        assert_eq!(forme.get_line(0).unwrap().text_range(), 0..9);
+        assert_eq!(hyphens(&forme), [0, 0]);
+
+        let forme = prepare(true);
+        assert_eq!(forme.get_line(0).unwrap().text_range(), 0..12);
+        assert_eq!(hyphens(&forme), [1, 0]);
+    }
+}
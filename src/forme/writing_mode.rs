@@ -0,0 +1,195 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Text preparation: writing modes
+
+use super::Forme;
+use crate::Vec2;
+
+/// Writing mode
+///
+/// This determines the direction of lines (the *inline* axis) and the
+/// direction in which lines are stacked (the *block* axis).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WritingMode {
+    /// Horizontal lines, stacked top-to-bottom
+    #[default]
+    HorizontalTb,
+    /// Vertical lines, stacked right-to-left
+    ///
+    /// This is the usual mode for vertical Chinese, Japanese and Korean text.
+    VerticalRl,
+    /// Vertical lines, stacked left-to-right
+    ///
+    /// This is the usual mode for Mongolian script.
+    VerticalLr,
+}
+
+impl WritingMode {
+    /// True for vertical modes
+    #[inline]
+    pub fn is_vertical(self) -> bool {
+        self != WritingMode::HorizontalTb
+    }
+
+    /// Map a logical position to a physical position
+    ///
+    /// A logical position is `(inline, block)`.
+    #[inline]
+    pub(crate) fn to_physical(self, pos: Vec2, block_size: f32) -> Vec2 {
+        match self {
+            WritingMode::HorizontalTb => pos,
+            WritingMode::VerticalRl => Vec2(block_size - pos.1, pos.0),
+            WritingMode::VerticalLr => Vec2(pos.1, pos.0),
+        }
+    }
+
+    /// Map a physical position to a logical position
+    #[inline]
+    pub(crate) fn to_logical(self, pos: Vec2, block_size: f32) -> Vec2 {
+        match self {
+            WritingMode::HorizontalTb => pos,
+            WritingMode::VerticalRl => Vec2(pos.1, block_size - pos.0),
+            WritingMode::VerticalLr => Vec2(pos.1, pos.0),
+        }
+    }
+}
+
+impl Forme {
+    /// Get the writing mode
+    #[inline]
+    pub fn writing_mode(&self) -> WritingMode {
+        self.config.writing_mode
+    }
+
+    /// Set the writing mode
+    ///
+    /// If the mode changes, the text is cleared (see [`Self::clear`]) since
+    /// shaping depends on the writing mode. It is necessary to call
+    /// [`Self::set_text`] after calling this.
+    ///
+    /// ## Vertical writing modes
+    ///
+    /// In vertical modes, characters are displayed upright or rotated
+    /// according to their
+    /// [Vertical_Orientation](https://www.unicode.org/reports/tr50/) property.
+    /// Upright glyphs use vertical metrics and alternates (the `vert` and
+    /// `vrt2` features, when shaping is enabled) while rotated glyphs must be
+    /// drawn rotated 90° clockwise (see [`GlyphRun::is_rotated`]).
+    ///
+    /// Preparation methods use logical axes: `width` parameters refer to the
+    /// inline (vertical) axis while `height` refers to the block (horizontal)
+    /// axis. Thus [`Self::prepare_lines`] wraps lines to a given height and
+    /// aligns them on the vertical axis while [`Self::vertically_align`]
+    /// aligns columns within a given width. [`Line::top`] and
+    /// [`Line::bottom`] are also block-axis positions, measured from the
+    /// block-start edge (the right edge in [`WritingMode::VerticalRl`]).
+    ///
+    /// Other query methods including [`Self::text_glyph_pos`],
+    /// [`Self::text_index_nearest`], [`Self::bounding_box`] and
+    /// [`Self::runs`] use physical coordinates.
+    ///
+    /// [`GlyphRun::is_rotated`]: super::GlyphRun::is_rotated
+    /// [`Line::top`]: super::Line::top
+    /// [`Line::bottom`]: super::Line::bottom
+    #[inline]
+    pub fn set_writing_mode(&mut self, mode: WritingMode) {
+        if mode != self.config.writing_mode {
+            self.config.writing_mode = mode;
+            self.clear();
+        }
+    }
+
+    /// Map a logical position to a physical position
+    #[inline]
+    pub(super) fn to_physical(&self, pos: Vec2) -> Vec2 {
+        self.config.writing_mode.to_physical(pos, self.block_size)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Align;
+    use crate::forme::test_util::{glyph_pos, shaped};
+    use crate::shaper::Orientation;
+
+    #[test]
+    fn orientation() {
+        let mut forme = shaped("ab§", |forme| {
+            forme.set_writing_mode(WritingMode::VerticalRl)
+        });
+        forme.prepare_lines(f32::INFINITY, 300.0, Align::Default);
+        let orientations: Vec<_> = (0..forme.runs.len())
+            .map(|i| forme.run_extras.get(i).orientation)
+            .collect();
+        assert_eq!(orientations, [Orientation::Rotated, Orientation::Upright]);
+        let rotated: Vec<_> = forme
+            .runs::<()>(Vec2::ZERO, &[])
+            .map(|r| r.is_rotated())
+            .collect();
+        assert_eq!(rotated, [true, false]);
+
+        let mut forme = shaped("ab§", |forme| {
+            forme.set_writing_mode(WritingMode::HorizontalTb)
+        });
+        forme.prepare_lines(f32::INFINITY, 300.0, Align::Default);
+        assert_eq!(forme.raw_runs().len(), 1);
+        assert_eq!(forme.run_extras.get(0).orientation, Orientation::Horizontal);
+
+        // Changing the mode discards the shaped text
+        forme.set_writing_mode(WritingMode::VerticalRl);
+        assert!(forme.raw_runs().is_empty());
+        assert_eq!(forme.num_lines(), 0);
+    }
+
+    #[test]
+    fn vertical_rl() {
+        let text = "ab\ncd";
+        let mut forme = shaped(text, |forme| {
+            forme.set_writing_mode(WritingMode::VerticalRl)
+        });
+        forme.prepare_lines(f32::INFINITY, 300.0, Align::Default);
+        forme.vertically_align(200.0, Align::TL);
+        assert_eq!(forme.num_lines(), 2);
+
+        let (a, b, d) = (
+            glyph_pos(&forme, 0),
+            glyph_pos(&forme, 1),
+            glyph_pos(&forme, 4),
+        );
+        assert_eq!(a.1, 0.0);
+        assert!(b.1 > 0.0);
+        assert_eq!(a.0, b.0);
+        assert!(d.0 < b.0);
+
+        let (tl, br) = forme.bounding_box();
+        assert_eq!(br.0, 200.0);
+        assert!(tl.0 < d.0 && d.0 < br.0);
+        assert_eq!(tl.1, 0.0);
+
+        let line = forme.get_line(0).unwrap();
+        // The central baseline is mid-line (before rounding)
+        assert!((a.0 - (200.0 - 0.5 * (line.top() + line.bottom()))).abs() <= 0.5);
+
+        assert_eq!(forme.text_index_nearest(b), 1);
+        assert_eq!(forme.text_index_nearest(d), 4);
+    }
+
+    #[test]
+    fn vertical_lr() {
+        let mut forme = shaped("ab\ncd", |forme| {
+            forme.set_writing_mode(WritingMode::VerticalLr)
+        });
+        forme.prepare_lines(f32::INFINITY, 300.0, Align::Default);
+        forme.vertically_align(200.0, Align::TL);
+        let (b, d) = (glyph_pos(&forme, 1), glyph_pos(&forme, 4));
+        assert!(d.0 > b.0);
+        assert_eq!(forme.bounding_box().0.0, 0.0);
+        assert_eq!(forme.text_index_nearest(b), 1);
+        assert_eq!(forme.text_index_nearest(d), 4);
+    }
+}
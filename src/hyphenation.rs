@@ -0,0 +1,218 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Pattern-based hyphenation
+//!
+//! This module implements Frank Liang's hyphenation algorithm, as used by TeX.
+//! No patterns are bundled with this library; patterns in TeX format (for
+//! example from the [hyph-utf8](https://www.hyphenation.org/) project) may be
+//! loaded using [`Hyphenator::new`] and [registered](register) for a language.
+//!
+//! Automatic hyphenation must be enabled with
+//! [`Appender::with_hyphenation`](crate::Appender::with_hyphenation); patterns
+//! are then selected by the
+//! [content locale](crate::Appender::with_content_locale).
+//! Explicit soft hyphens (U+00AD) are supported regardless.
+
+use icu_locale::subtags::Language;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Error type for invalid hyphenation patterns or exceptions
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("invalid hyphenation pattern: {0:?}")]
+pub struct InvalidPattern(pub String);
+
+/// A hyphenation dictionary
+///
+/// This consists of a set of Liang patterns plus a list of exceptions.
+#[derive(Clone, Debug, Default)]
+pub struct Hyphenator {
+    patterns: HashMap<Box<str>, Box<[u8]>>,
+    max_len: usize,
+    exceptions: HashMap<Box<str>, Box<[usize]>>,
+    left_min: usize,
+    right_min: usize,
+}
+
+impl Hyphenator {
+    /// Construct from TeX-format patterns and exceptions
+    ///
+    /// The `patterns` string is a whitespace-separated list of patterns such
+    /// as `.hy3ph` or `n2at`. The `exceptions` string is a whitespace-separated
+    /// list of words with explicit hyphenation such as `ta-ble`.
+    ///
+    /// Minimum fragment lengths default to two characters before the first
+    /// hyphen and three after the last (see [`Self::with_min`]).
+    pub fn new(patterns: &str, exceptions: &str) -> Result<Self, InvalidPattern> {
+        let mut hyphenator = Hyphenator {
+            left_min: 2,
+            right_min: 3,
+            ..Default::default()
+        };
+
+        for pattern in patterns.split_whitespace() {
+            let mut letters = String::with_capacity(pattern.len());
+            let mut values = vec![0];
+            for c in pattern.chars() {
+                if let Some(d) = c.to_digit(10) {
+                    let last = values.last_mut().unwrap();
+                    if *last != 0 {
+                        return Err(InvalidPattern(pattern.to_string()));
+                    }
+                    *last = d as u8;
+                } else {
+                    letters.extend(c.to_lowercase());
+                    values.push(0);
+                }
+            }
+            if letters.is_empty() {
+                return Err(InvalidPattern(pattern.to_string()));
+            }
+            hyphenator.max_len = hyphenator.max_len.max(values.len() - 1);
+            hyphenator
+                .patterns
+                .insert(letters.into(), values.into_boxed_slice());
+        }
+
+        for word in exceptions.split_whitespace() {
+            let mut letters = String::with_capacity(word.len());
+            let mut points = vec![];
+            let mut n = 0;
+            for c in word.chars() {
+                if c == '-' {
+                    if n == 0 || points.last() == Some(&n) {
+                        return Err(InvalidPattern(word.to_string()));
+                    }
+                    points.push(n);
+                } else {
+                    letters.extend(c.to_lowercase());
+                    n += 1;
+                }
+            }
+            if points.last() == Some(&n) {
+                return Err(InvalidPattern(word.to_string()));
+            }
+            hyphenator
+                .exceptions
+                .insert(letters.into(), points.into_boxed_slice());
+        }
+
+        Ok(hyphenator)
+    }
+
+    /// Set minimum fragment lengths
+    ///
+    /// Words are not hyphenated within `left` characters of the start or
+    /// `right` characters of the end. These limits do not apply to exceptions.
+    #[inline]
+    pub fn with_min(mut self, left: usize, right: usize) -> Self {
+        self.left_min = left.max(1);
+        self.right_min = right.max(1);
+        self
+    }
+
+    /// Find hyphenation points in a `word`
+    ///
+    /// Returns byte indices within `word` before which a hyphenated break may
+    /// occur, in increasing order. Matching is case-insensitive.
+    pub fn hyphenate(&self, word: &str) -> Vec<usize> {
+        let mut lower = String::with_capacity(word.len());
+        let mut chars = Vec::with_capacity(word.len() + 2);
+        let mut offsets = Vec::with_capacity(word.len());
+        chars.push('.');
+        for (i, c) in word.char_indices() {
+            let mut iter = c.to_lowercase();
+            let c = match (iter.next(), iter.next()) {
+                (Some(l), None) => l,
+                _ => c,
+            };
+            lower.push(c);
+            chars.push(c);
+            offsets.push(i);
+        }
+        chars.push('.');
+        let n = offsets.len();
+
+        if let Some(points) = self.exceptions.get(lower.as_str()) {
+            return points.iter().map(|p| offsets[*p]).collect();
+        }
+        if n < self.left_min + self.right_min {
+            return vec![];
+        }
+
+        // values[k] is the priority of the position before chars[k]
+        let mut values = vec![0u8; chars.len() + 1];
+        let mut key = String::new();
+        for i in 0..chars.len() {
+            key.clear();
+            let end = chars.len().min(i + self.max_len);
+            for c in &chars[i..end] {
+                key.push(*c);
+                if let Some(pattern) = self.patterns.get(key.as_str()) {
+                    for (k, v) in pattern.iter().enumerate() {
+                        values[i + k] = values[i + k].max(*v);
+                    }
+                }
+            }
+        }
+
+        // Break before word char k (chars[k + 1]) if values[k + 1] is odd
+        (self.left_min..=(n - self.right_min))
+            .filter(|k| values[k + 1] % 2 == 1)
+            .map(|k| offsets[k])
+            .collect()
+    }
+}
+
+static REGISTRY: RwLock<Vec<(Language, Arc<Hyphenator>)>> = RwLock::new(Vec::new());
+
+/// Register a [`Hyphenator`] for the given `language`
+///
+/// This replaces any existing hyphenator for the `language`.
+pub fn register(language: Language, hyphenator: Hyphenator) {
+    let mut registry = REGISTRY.write().unwrap();
+    let hyphenator = Arc::new(hyphenator);
+    if let Some(entry) = registry.iter_mut().find(|entry| entry.0 == language) {
+        entry.1 = hyphenator;
+    } else {
+        registry.push((language, hyphenator));
+    }
+}
+
+/// Get the [`Hyphenator`] registered for `language`, if any
+pub fn get(language: Language) -> Option<Arc<Hyphenator>> {
+    let registry = REGISTRY.read().unwrap();
+    registry
+        .iter()
+        .find(|entry| entry.0 == language)
+        .map(|entry| entry.1.clone())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Patterns from Liang's thesis example (a small subset of the English set)
+    const PATTERNS: &str = ".hy3ph he2n hena4 hen5at 1na n2at 1tio 2io o2n";
+
+    #[test]
+    fn liang() {
+        let hyphenator = Hyphenator::new(PATTERNS, "").unwrap();
+        assert_eq!(hyphenator.hyphenate("hyphenation"), [2, 6]);
+        assert_eq!(hyphenator.hyphenate("Hyphenation"), [2, 6]);
+        assert!(hyphenator.hyphenate("hyp").is_empty());
+    }
+
+    #[test]
+    fn exceptions() {
+        let hyphenator = Hyphenator::new(PATTERNS, "ta-ble").unwrap();
+        assert_eq!(hyphenator.hyphenate("table"), [2]);
+
+        assert!(Hyphenator::new("a1b2", "").is_ok());
+        assert!(Hyphenator::new("a12b", "").is_err());
+        assert!(Hyphenator::new("", "-ab").is_err());
+    }
+}
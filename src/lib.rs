@@ -15,6 +15,9 @@
 //! The [`Forme`] struct is able to transform an `&str` into a set of type-set
 //! glyphs, and rapidly re-flow these glyphs to meet any page width.
 //!
+//! For very large texts, [`Document`] lays out paragraphs lazily as required
+//! by a viewport.
+//!
 //! ## Formatted text
 //!
 //! The [`Text`] struct provides a slightly higher-level API, using the
@@ -29,9 +32,12 @@ pub use env::*;
 mod conv;
 pub use conv::{DPU, LineMetrics};
 
+mod document;
+pub use document::Document;
+
 mod data;
 use data::Range;
-pub use data::Vec2;
+pub use data::{Length, Vec2};
 
 mod forme;
 pub use forme::*;
@@ -39,6 +45,8 @@ pub use forme::*;
 pub mod fonts;
 #[cfg(feature = "text")]
 pub mod format;
+pub mod hyphenation;
+pub mod segment;
 
 #[cfg(feature = "text")]
 mod text;
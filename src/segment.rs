@@ -0,0 +1,396 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Text segmentation and logical cursor navigation
+//!
+//! These functions find boundaries within a text using
+//! [Unicode text segmentation](https://www.unicode.org/reports/tr29/) rules.
+//! Navigation is in logical order (the order of the text); see
+//! [`Forme::move_left`](crate::Forme::move_left) and
+//! [`Forme::move_right`](crate::Forme::move_right) for visual-order navigation.
+//!
+//! Functions [`word_at`], [`sentence_at`] and [`paragraph_at`] find the
+//! segment around an index, for example to select text on a double- or
+//! triple-click.
+//!
+//! All indices are byte indices into the text. An `index` beyond the length of
+//! the text is clamped.
+
+use crate::util::{is_hard_break, paragraph_range};
+use icu_segmenter::options::{SentenceBreakInvariantOptions, WordBreakInvariantOptions};
+use icu_segmenter::{GraphemeClusterSegmenter, SentenceSegmenter, WordSegmenter};
+use std::ops::Range;
+
+/// Find the next grapheme cluster boundary after `index`
+///
+/// A grapheme cluster is a user-perceived character, for example a base
+/// character with combining marks or an emoji ZWJ sequence.
+/// Returns `text.len()` if there is no such boundary.
+pub fn next_grapheme(text: &str, index: usize) -> usize {
+    let index = index.min(text.len());
+    let start = floor_char_boundary(text, index);
+    let range = paragraph_range(text, start..start);
+    GraphemeClusterSegmenter::new()
+        .segment_str(&text[range.clone()])
+        .map(|b| range.start + b)
+        .find(|b| *b > index)
+        .unwrap_or(text.len())
+}
+
+/// Find the previous grapheme cluster boundary before `index`
+///
+/// Returns `0` if there is no such boundary.
+pub fn prev_grapheme(text: &str, index: usize) -> usize {
+    let index = index.min(text.len());
+    let Some(range) = paragraph_before(text, ceil_char_boundary(text, index)) else {
+        return 0;
+    };
+    GraphemeClusterSegmenter::new()
+        .segment_str(&text[range.clone()])
+        .map(|b| range.start + b)
+        .take_while(|b| *b < index)
+        .last()
+        .unwrap_or(0)
+}
+
+/// Find the end of the next word after `index`
+///
+/// Words are as determined by the word segmenter (with dictionary support for
+/// languages such as Thai and Japanese); white-space and punctuation are
+/// skipped. Returns `text.len()` if there is no such word.
+pub fn next_word_end(text: &str, index: usize) -> usize {
+    let index = index.min(text.len());
+    let segmenter = WordSegmenter::new_auto(WordBreakInvariantOptions::default());
+    let mut start = floor_char_boundary(text, index);
+    loop {
+        // Words do not span paragraphs, thus we segment one at a time
+        let range = paragraph_range(text, start..start);
+        let end = segmenter
+            .segment_str(&text[range.clone()])
+            .iter_with_word_type()
+            .find(|(b, ty)| *b > 0 && range.start + b > index && ty.is_word_like());
+        if let Some((b, _)) = end {
+            return range.start + b;
+        } else if range.end == text.len() {
+            return text.len();
+        }
+        start = range.end;
+    }
+}
+
+/// Find the start of the previous word before `index`
+///
+/// Words are as determined by the word segmenter; white-space and
+/// punctuation are skipped. Returns `0` if there is no such word.
+pub fn prev_word_start(text: &str, index: usize) -> usize {
+    let index = index.min(text.len());
+    let segmenter = WordSegmenter::new_auto(WordBreakInvariantOptions::default());
+    let mut end = ceil_char_boundary(text, index);
+    while let Some(range) = paragraph_before(text, end) {
+        let mut start = None;
+        let mut last = range.start;
+        let iter = segmenter
+            .segment_str(&text[range.clone()])
+            .iter_with_word_type();
+        for (b, ty) in iter {
+            if b > 0 && ty.is_word_like() {
+                if last >= index {
+                    break;
+                }
+                start = Some(last);
+            }
+            last = range.start + b;
+        }
+        if let Some(start) = start {
+            return start;
+        }
+        end = range.start;
+    }
+    0
+}
+
+/// Get the range of the paragraph containing the char before `index`
+fn paragraph_before(text: &str, index: usize) -> Option<Range<usize>> {
+    let c = text[..index].chars().next_back()?;
+    let index = index - c.len_utf8();
+    Some(paragraph_range(text, index..index))
+}
+
+/// Find the largest char boundary not greater than `index`
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// Find the smallest char boundary not less than `index`
+fn ceil_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
+/// Find the end of the next paragraph after `index`
+///
+/// Paragraphs are separated by mandatory line breaks (as with
+/// [`LineIterator`](crate::LineIterator)); the paragraph end is before its
+/// break. Returns `text.len()` if there is no such paragraph.
+pub fn next_paragraph_end(text: &str, index: usize) -> usize {
+    let index = index.min(text.len());
+    let mut start = index;
+    loop {
+        let range = paragraph_range(text, start..start);
+        let mut end = range.end;
+        while let Some(c) = text[range.start..end].chars().next_back()
+            && is_hard_break(c)
+        {
+            end -= c.len_utf8();
+        }
+        if end > index || range.end == text.len() {
+            return end.max(index);
+        }
+        start = range.end;
+    }
+}
+
+/// Find the start of the previous paragraph before `index`
+///
+/// Returns `0` if there is no such paragraph.
+pub fn prev_paragraph_start(text: &str, index: usize) -> usize {
+    let index = index.min(text.len());
+    let start = paragraph_range(text, index..index).start;
+    if start < index {
+        return start;
+    }
+    match text[..index].chars().next_back() {
+        Some(c) => paragraph_range(text, index - c.len_utf8()..index - c.len_utf8()).start,
+        None => 0,
+    }
+}
+
+/// Options for [`word_at`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WordOptions {
+    /// Treat punctuation as separate words
+    ///
+    /// If true, each punctuation segment (e.g. the `-` in `"well-known"`) is a
+    /// word. If false, punctuation is part of adjacent words and punctuation,
+    /// thus words are delimited only by white-space.
+    pub punctuation: bool,
+    /// Treat white-space as separate words
+    ///
+    /// If true, each run of white-space is a word. If false, white-space is
+    /// included in the preceding word (if any), as is usual for selection on
+    /// Windows.
+    pub whitespace: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SegmentKind {
+    Word,
+    Punctuation,
+    Space,
+    Break,
+}
+
+/// Find the range of the word at `index`
+///
+/// Words are as determined by the word segmenter (with dictionary support for
+/// languages such as Thai and Japanese), adjusted by `options`. Line breaks
+/// are never part of a word. Where `index` is at the boundary of two words the
+/// following word is returned, except at the end of the text.
+pub fn word_at(text: &str, index: usize, options: WordOptions) -> Range<usize> {
+    let index = index.min(text.len());
+    let mut segments: Vec<(Range<usize>, SegmentKind)> = vec![];
+    let mut start = 0;
+    let iter = WordSegmenter::new_auto(WordBreakInvariantOptions::default())
+        .segment_str(text)
+        .iter_with_word_type();
+    for (end, ty) in iter {
+        if end == 0 {
+            continue;
+        }
+        let segment = &text[start..end];
+        let kind = if ty.is_word_like() {
+            SegmentKind::Word
+        } else if segment.starts_with(is_hard_break) {
+            SegmentKind::Break
+        } else if segment.chars().all(char::is_whitespace) {
+            SegmentKind::Space
+        } else {
+            SegmentKind::Punctuation
+        };
+
+        let merge = match (segments.last(), kind) {
+            (Some((_, SegmentKind::Word | SegmentKind::Punctuation)), SegmentKind::Space) => {
+                !options.whitespace
+            }
+            (
+                Some((_, SegmentKind::Word | SegmentKind::Punctuation)),
+                SegmentKind::Word | SegmentKind::Punctuation,
+            ) => !options.punctuation,
+            _ => false,
+        };
+        match segments.last_mut() {
+            Some(last) if merge => {
+                last.0.end = end;
+                if kind == SegmentKind::Space {
+                    // Further text is not merged into the word
+                    last.1 = SegmentKind::Space;
+                }
+            }
+            _ => segments.push((start..end, kind)),
+        }
+        start = end;
+    }
+
+    segments
+        .iter()
+        .find(|(range, _)| range.contains(&index))
+        .or(segments.last())
+        .map(|(range, _)| range.clone())
+        .unwrap_or(index..index)
+}
+
+/// Find the range of the sentence at `index`
+///
+/// Sentences are as determined by the sentence segmenter. A sentence includes
+/// trailing white-space and any trailing line break. Where `index` is at the
+/// boundary of two sentences the following sentence is returned, except at
+/// the end of the text.
+pub fn sentence_at(text: &str, index: usize) -> Range<usize> {
+    let index = index.min(text.len());
+    let mut start = 0;
+    for end in SentenceSegmenter::new(SentenceBreakInvariantOptions::default()).segment_str(text) {
+        if end > index || (end == text.len() && end > 0) {
+            return start..end;
+        }
+        start = end;
+    }
+    index..index
+}
+
+/// Find the range of the paragraph at `index`
+///
+/// Paragraphs are separated by mandatory line breaks (as with
+/// [`LineIterator`](crate::LineIterator)). The range includes the paragraph's
+/// trailing break, if any. Where `index` is at the end of text following a
+/// line break, the result is the empty range `index..index`.
+pub fn paragraph_at(text: &str, index: usize) -> Range<usize> {
+    let index = index.min(text.len());
+    paragraph_range(text, index..index)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn graphemes() {
+        // e + combining acute, then a family emoji (ZWJ sequence)
+        let text = "e\u{301}👨\u{200D}👩\u{200D}👧x";
+        let family = 3 + "👨\u{200D}👩\u{200D}👧".len();
+        assert_eq!(next_grapheme(text, 0), 3);
+        assert_eq!(next_grapheme(text, 3), family);
+        assert_eq!(next_grapheme(text, family), text.len());
+        assert_eq!(next_grapheme(text, text.len()), text.len());
+        assert_eq!(prev_grapheme(text, text.len()), family);
+        assert_eq!(prev_grapheme(text, family), 3);
+        assert_eq!(prev_grapheme(text, 2), 0);
+        assert_eq!(prev_grapheme(text, 0), 0);
+
+        // CR LF is a single cluster at the end of a paragraph
+        let text = "a\r\nb";
+        assert_eq!(next_grapheme(text, 1), 3);
+        assert_eq!(next_grapheme(text, 3), 4);
+        assert_eq!(prev_grapheme(text, 3), 1);
+        assert_eq!(prev_grapheme(text, 4), 3);
+    }
+
+    #[test]
+    fn words() {
+        let text = "One, two  three.";
+        assert_eq!(next_word_end(text, 0), 3);
+        assert_eq!(next_word_end(text, 3), 8);
+        assert_eq!(next_word_end(text, 8), 15);
+        assert_eq!(next_word_end(text, 15), text.len());
+        assert_eq!(prev_word_start(text, text.len()), 10);
+        assert_eq!(prev_word_start(text, 10), 5);
+        assert_eq!(prev_word_start(text, 6), 5);
+        assert_eq!(prev_word_start(text, 5), 0);
+        assert_eq!(prev_word_start(text, 0), 0);
+
+        // Navigation continues over empty paragraphs
+        let text = "One\n\n Two\n";
+        assert_eq!(next_word_end(text, 3), 9);
+        assert_eq!(next_word_end(text, 9), text.len());
+        assert_eq!(prev_word_start(text, 6), 0);
+        assert_eq!(prev_word_start(text, text.len()), 6);
+    }
+
+    #[test]
+    fn paragraphs() {
+        let text = "ab\r\ncd\n\nef";
+        assert_eq!(next_paragraph_end(text, 0), 2);
+        assert_eq!(next_paragraph_end(text, 2), 6);
+        assert_eq!(next_paragraph_end(text, 6), 7);
+        assert_eq!(next_paragraph_end(text, 7), text.len());
+        assert_eq!(prev_paragraph_start(text, text.len()), 8);
+        assert_eq!(prev_paragraph_start(text, 8), 7);
+        assert_eq!(prev_paragraph_start(text, 7), 4);
+        assert_eq!(prev_paragraph_start(text, 5), 4);
+        assert_eq!(prev_paragraph_start(text, 4), 0);
+        assert_eq!(prev_paragraph_start(text, 0), 0);
+
+        assert_eq!(paragraph_at(text, 0), 0..4);
+        assert_eq!(paragraph_at(text, 4), 4..7);
+        assert_eq!(paragraph_at(text, 7), 7..8);
+        assert_eq!(paragraph_at(text, text.len()), 8..text.len());
+    }
+
+    #[test]
+    fn word_selection() {
+        let text = "A well-known  fact.\nNext";
+        let mac = WordOptions {
+            punctuation: true,
+            whitespace: true,
+        };
+        assert_eq!(word_at(text, 3, mac), 2..6);
+        assert_eq!(word_at(text, 6, mac), 6..7);
+        assert_eq!(word_at(text, 12, mac), 12..14);
+        assert_eq!(word_at(text, 18, mac), 18..19);
+        assert_eq!(word_at(text, 19, mac), 19..20);
+        assert_eq!(word_at(text, text.len(), mac), 20..text.len());
+
+        let windows = WordOptions::default();
+        assert_eq!(word_at(text, 0, windows), 0..2);
+        assert_eq!(word_at(text, 3, windows), 2..14);
+        assert_eq!(word_at(text, 12, windows), 2..14);
+        assert_eq!(word_at(text, 15, windows), 14..19);
+        assert_eq!(word_at(text, 19, windows), 19..20);
+
+        // Dictionary segmentation splits Thai text without spaces
+        let thai = "สวัสดีครับ";
+        let first = word_at(thai, 0, mac);
+        assert!(first.start == 0 && first.end < thai.len());
+        assert_eq!(word_at(thai, first.end, mac).start, first.end);
+
+        assert_eq!(word_at("", 0, mac), 0..0);
+    }
+
+    #[test]
+    fn sentences() {
+        let text = "Hello there. How are you? Fine.";
+        assert_eq!(sentence_at(text, 0), 0..13);
+        assert_eq!(sentence_at(text, 12), 0..13);
+        assert_eq!(sentence_at(text, 13), 13..26);
+        assert_eq!(sentence_at(text, 27), 26..text.len());
+        assert_eq!(sentence_at(text, text.len()), 26..text.len());
+        assert_eq!(sentence_at("", 0), 0..0);
+    }
+}
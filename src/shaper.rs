@@ -19,7 +19,7 @@
 
 use crate::conv::{DPU, to_u32, to_usize};
 use crate::fonts::{self, FaceId};
-use crate::forme::RunSpecial;
+use crate::forme::{Collapse, InlineObject, RunSpecial, WhiteSpace};
 use crate::{Range, Vec2};
 use icu_properties::props::Script;
 use tinyvec::TinyVec;
@@ -45,22 +45,77 @@ pub struct Glyph {
 pub(crate) struct GlyphBreak {
     /// Index of char in source text
     pub index: u32,
-    /// Position in sequence of glyphs
-    pub gi: u32,
+    /// Position in sequence of glyphs (low bits) and flags (high bits)
+    gi_flags: u32,
     /// End position of previous "word" excluding space
     pub no_space_end: f32,
 }
 impl GlyphBreak {
-    /// Constructs with first field only
+    /// Flag: a hyphen should be displayed when wrapping here
+    const HYPHEN: u32 = 1 << 31;
+    /// Flag: this is an emergency break (see `OverflowWrap::Anywhere`)
+    const EMERGENCY: u32 = 1 << 30;
+    /// Mask of the glyph index
+    const GI_MASK: u32 = Self::EMERGENCY - 1;
+
+    /// Constructs with `index` and `hyphen` fields only
     ///
     /// Other fields are set later by shaper.
-    pub(crate) fn new(index: u32) -> Self {
+    pub(crate) fn new(index: u32, hyphen: bool) -> Self {
+        let hyphen = if hyphen { Self::HYPHEN } else { 0 };
         GlyphBreak {
             index,
-            gi: u32::MAX,
+            gi_flags: Self::GI_MASK | hyphen,
             no_space_end: f32::NAN,
         }
     }
+
+    /// Constructs an emergency break
+    pub(crate) fn new_emergency(index: u32, gi: u32, no_space_end: f32) -> Self {
+        debug_assert!(gi <= Self::GI_MASK);
+        GlyphBreak {
+            index,
+            gi_flags: gi | Self::EMERGENCY,
+            no_space_end,
+        }
+    }
+
+    /// Position in sequence of glyphs
+    #[inline]
+    pub(crate) fn gi(&self) -> u32 {
+        self.gi_flags & Self::GI_MASK
+    }
+
+    /// Set the position in sequence of glyphs
+    #[inline]
+    pub(crate) fn set_gi(&mut self, gi: u32) {
+        debug_assert!(gi <= Self::GI_MASK);
+        self.gi_flags = (self.gi_flags & !Self::GI_MASK) | gi;
+    }
+
+    /// True if a hyphen should be displayed when wrapping here
+    #[inline]
+    pub(crate) fn hyphen(&self) -> bool {
+        self.gi_flags & Self::HYPHEN != 0
+    }
+
+    /// True if this is an emergency break (see `OverflowWrap::Anywhere`)
+    #[inline]
+    pub(crate) fn emergency(&self) -> bool {
+        self.gi_flags & Self::EMERGENCY != 0
+    }
+}
+
+/// Glyph orientation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Orientation {
+    /// Horizontal text
+    #[default]
+    Horizontal,
+    /// Upright glyphs in vertical text
+    Upright,
+    /// Rotated (90° clockwise) glyphs in vertical text
+    Rotated,
 }
 
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
@@ -105,6 +160,10 @@ pub(crate) struct GlyphRun {
     pub level: Level,
 
     /// Sequence of all glyphs, in left-to-right order
+    ///
+    /// With [`Orientation::Upright`], the `x` component of positions is the
+    /// pen position on the vertical axis while the `y` component is zero;
+    /// see [`RunExtra::v_offsets`].
     pub glyphs: Vec<Glyph>,
     /// All soft-breaks within this run, in logical order
     ///
@@ -120,7 +179,115 @@ pub(crate) struct GlyphRun {
     pub caret: f32,
 }
 
+/// Rarely used properties of a [`GlyphRun`]
+///
+/// These are stored separately (see [`RunExtras`]) to keep [`GlyphRun`] small.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct RunExtra {
+    /// If true, lines may not wrap within this run or after a tab
+    ///
+    /// This is set for runs with a non-wrapping [`WhiteSpace`] mode.
+    /// Otherwise, the run has no soft breaks and `special` is
+    /// [`RunSpecial::NoBreak`] unless the run ends the text or paragraph.
+    pub no_wrap: bool,
+    pub orientation: Orientation,
+    /// Glyph offsets for upright vertical text
+    ///
+    /// Empty unless `orientation` is [`Orientation::Upright`], otherwise
+    /// these give the physical offset of each glyph's origin from its pen
+    /// position (at the top of the glyph, on the central baseline).
+    pub v_offsets: Vec<Vec2>,
+    /// Inline object
+    ///
+    /// If set, the run represents an inline object (a single char) and has
+    /// a single placeholder glyph.
+    pub object: Option<InlineObject>,
+    /// Kashida opportunities
+    ///
+    /// Each entry is an index into `glyphs` before which a tatweel (U+0640)
+    /// may be inserted for justification. This is empty unless the run is
+    /// Arabic text and the font provides a tatweel glyph.
+    pub kashida: Vec<u32>,
+    /// The first face of the selected font
+    ///
+    /// This is set only where a fallback face is used, thus where it differs
+    /// from [`GlyphRun::face_id`].
+    pub primary_face: Option<FaceId>,
+}
+
+static NO_EXTRA: RunExtra = RunExtra {
+    no_wrap: false,
+    orientation: Orientation::Horizontal,
+    v_offsets: Vec::new(),
+    object: None,
+    kashida: Vec::new(),
+    primary_face: None,
+};
+
+/// A side table of [`RunExtra`], keyed by run index
+///
+/// Only runs with non-default properties have an entry.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RunExtras(Vec<(u32, RunExtra)>);
+
+impl RunExtras {
+    /// Get properties of run `index`
+    pub fn get(&self, index: usize) -> &RunExtra {
+        let index = to_u32(index);
+        match self.0.binary_search_by_key(&index, |(i, _)| *i) {
+            Ok(i) => &self.0[i].1,
+            Err(_) => &NO_EXTRA,
+        }
+    }
+
+    /// Set properties of run `index`
+    pub fn set(&mut self, index: usize, extra: RunExtra) {
+        let index = to_u32(index);
+        match self.0.binary_search_by_key(&index, |(i, _)| *i) {
+            Ok(i) if extra == NO_EXTRA => {
+                self.0.remove(i);
+            }
+            Ok(i) => self.0[i].1 = extra,
+            Err(_) if extra == NO_EXTRA => (),
+            Err(i) => self.0.insert(i, (index, extra)),
+        }
+    }
+
+    /// Remove all entries
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// Re-key entries after runs are moved
+    ///
+    /// The entry for run `i` is moved to `map(i)`, or removed if `None`.
+    pub fn remap(&mut self, map: impl Fn(usize) -> Option<usize>) {
+        self.0.retain_mut(|(i, _)| match map(to_usize(*i)) {
+            Some(index) => {
+                *i = to_u32(index);
+                true
+            }
+            None => false,
+        });
+        self.0.sort_unstable_by_key(|(i, _)| *i);
+    }
+}
+
 impl GlyphRun {
+    /// Add `delta` to all text indices
+    ///
+    /// Uses wrapping arithmetic, thus `delta` may represent a negative shift.
+    pub fn shift_text(&mut self, delta: u32) {
+        self.range.start = self.range.start.wrapping_add(delta);
+        self.range.end = self.range.end.wrapping_add(delta);
+        for glyph in &mut self.glyphs {
+            glyph.index = glyph.index.wrapping_add(delta);
+        }
+        for b in &mut self.breaks {
+            b.index = b.index.wrapping_add(delta);
+        }
+    }
+
     /// Number of parts
     ///
     /// Parts are in logical order
@@ -128,6 +295,17 @@ impl GlyphRun {
         self.breaks.len() + 1
     }
 
+    /// True if lines may wrap after the end of this run
+    ///
+    /// Input `no_wrap` is [`RunExtra::no_wrap`].
+    pub fn allows_break_at_end(&self, no_wrap: bool) -> bool {
+        match self.special {
+            RunSpecial::NoBreak => false,
+            RunSpecial::HTab => !no_wrap,
+            _ => true,
+        }
+    }
+
     /// Calculate lengths for a part range
     ///
     /// Parts are identified in logical order with end index up to
@@ -146,15 +324,15 @@ impl GlyphRun {
             if range.end <= self.breaks.len() {
                 let b = self.breaks[range.end - 1];
                 part.len_no_space = b.no_space_end;
-                if to_usize(b.gi) < self.glyphs.len() {
-                    part.len = self.glyphs[to_usize(b.gi)].position.0
+                if to_usize(b.gi()) < self.glyphs.len() {
+                    part.len = self.glyphs[to_usize(b.gi())].position.0
                 } else {
                     debug_assert!(false);
                 }
             }
 
             if range.start > 0 {
-                let glyph = to_usize(self.breaks[range.start - 1].gi);
+                let glyph = to_usize(self.breaks[range.start - 1].gi());
                 part.offset = self.glyphs[glyph].position.0;
                 part.len_no_space -= part.offset;
                 part.len -= part.offset;
@@ -162,7 +340,7 @@ impl GlyphRun {
         } else {
             part.len = self.caret;
             if range.start > 0 {
-                let gi = to_usize(self.breaks[range.start - 1].gi);
+                let gi = to_usize(self.breaks[range.start - 1].gi());
                 if gi > 0 {
                     part.len = self.glyphs[gi - 1].position.0;
                 } else {
@@ -176,8 +354,8 @@ impl GlyphRun {
                 debug_assert!(range.end > 0);
                 let b = self.breaks[range.end - 1];
                 part.len_no_space -= b.no_space_end;
-                if b.gi > 0 {
-                    part.offset = self.glyphs[to_usize(b.gi) - 1].position.0;
+                if b.gi() > 0 {
+                    part.offset = self.glyphs[to_usize(b.gi()) - 1].position.0;
                 } else {
                     debug_assert!(false);
                 }
@@ -192,6 +370,157 @@ impl GlyphRun {
         part
     }
 
+    /// True if a hyphen is displayed when wrapping at the end of part `end - 1`
+    pub fn part_end_hyphen(&self, end: usize) -> bool {
+        if end == 0 {
+            false
+        } else if end <= self.breaks.len() {
+            self.breaks[end - 1].hyphen()
+        } else {
+            self.special == RunSpecial::Hyphen
+        }
+    }
+
+    /// True if the end of part `end - 1` is an emergency break
+    pub fn part_end_emergency(&self, end: usize) -> bool {
+        end > 0 && end <= self.breaks.len() && self.breaks[end - 1].emergency()
+    }
+
+    /// Get the horizontal span `(left, right)` of glyph `gi`
+    ///
+    /// The glyph extends to the next glyph in visual order, or to the end of
+    /// the run.
+    pub fn glyph_span(&self, gi: usize) -> (f32, f32) {
+        let x = |gi: usize| match self.glyphs.get(gi) {
+            Some(glyph) => glyph.position.0,
+            None => self.caret,
+        };
+        let end = match self.level.is_rtl() {
+            false => x(gi + 1),
+            true if gi > 0 => x(gi - 1),
+            true => self.caret,
+        };
+        let start = x(gi);
+        (start.min(end), start.max(end))
+    }
+
+    /// Distance from the logical start of the run to the start of glyph `gi`
+    fn logical_x(&self, gi: usize) -> f32 {
+        if self.level.is_ltr() {
+            self.glyphs
+                .get(gi)
+                .map(|g| g.position.0)
+                .unwrap_or(self.caret)
+        } else if gi > 0 {
+            self.caret - self.glyphs[gi - 1].position.0
+        } else {
+            0.0
+        }
+    }
+
+    /// Distance from the logical start of the run to the end of parts
+    /// `..end`, excluding white-space
+    fn logical_no_space_end(&self, end: usize) -> f32 {
+        let x = match end <= self.breaks.len() {
+            true => self.breaks[end - 1].no_space_end,
+            false => self.no_space_end,
+        };
+        if self.level.is_ltr() {
+            x
+        } else {
+            self.caret - x
+        }
+    }
+
+    /// Find cluster boundaries within parts `range`
+    ///
+    /// A cluster is a sequence of glyphs with the same text index followed by
+    /// any zero-width glyphs (e.g. combining marks). When shaping, this is
+    /// usually a grapheme cluster.
+    ///
+    /// Returns the index of the first glyph of each cluster, excluding the
+    /// first cluster and clusters of trailing white-space.
+    pub fn cluster_boundaries(&self, range: std::ops::Range<usize>) -> Vec<usize> {
+        if range.is_empty() {
+            return vec![];
+        }
+        let glyphs = self.to_glyph_range(range.clone());
+        let no_space_end = self.logical_no_space_end(range.end);
+
+        let mut boundaries = vec![];
+        for gi in glyphs.start() + 1..glyphs.end() {
+            let x = self.logical_x(gi);
+            let advance = self.logical_x(gi + 1) - x;
+            if self.glyphs[gi].index != self.glyphs[gi - 1].index
+                && advance != 0.0
+                && x + 0.5 * advance < no_space_end
+            {
+                boundaries.push(gi);
+            }
+        }
+        boundaries
+    }
+
+    /// Get the length of the longest cluster within parts `range`
+    ///
+    /// See [`Self::cluster_boundaries`]. Trailing white-space is excluded.
+    pub fn max_cluster_len(&self, range: std::ops::Range<usize>) -> f32 {
+        if range.is_empty() {
+            return 0.0;
+        }
+        let start = self.logical_x(self.to_glyph_range(range.clone()).start());
+        let end = self.logical_no_space_end(range.end);
+        let mut max = 0.0f32;
+        let mut x = start;
+        for gi in self.cluster_boundaries(range) {
+            let next = self.logical_x(gi);
+            max = max.max(next - x);
+            x = next;
+        }
+        max.max(end - x)
+    }
+
+    /// Add emergency breaks at all cluster boundaries
+    ///
+    /// Does nothing if the run already has emergency breaks.
+    pub fn add_emergency_breaks(&mut self) {
+        if self.breaks.iter().any(|b| b.emergency()) {
+            return;
+        }
+
+        let mut breaks = TinyVec::with_capacity(self.breaks.len());
+        for part in 0..self.num_parts() {
+            for gi in self.cluster_boundaries(part..part + 1) {
+                // We are within a word, thus the end excluding space is the
+                // start of this glyph (LTR) or of the previous glyph (RTL)
+                let no_space_end = match self.level.is_ltr() {
+                    true => self.glyphs[gi].position.0,
+                    false => self.glyphs[gi - 1].position.0,
+                };
+                let index = self.glyphs[gi].index;
+                breaks.push(GlyphBreak::new_emergency(index, to_u32(gi), no_space_end));
+            }
+            if let Some(b) = self.breaks.get(part) {
+                breaks.push(*b);
+            }
+        }
+        self.breaks = breaks;
+    }
+
+    /// Remove all emergency breaks
+    pub fn remove_emergency_breaks(&mut self) {
+        self.breaks.retain(|b| !b.emergency());
+    }
+
+    /// True if a hyphen is displayed when wrapping before glyph `gi`
+    pub fn hyphen_before(&self, gi: u32) -> bool {
+        if to_usize(gi) >= self.glyphs.len() {
+            self.special == RunSpecial::Hyphen
+        } else {
+            self.breaks.iter().any(|b| b.gi() == gi && b.hyphen())
+        }
+    }
+
     /// Get glyph index from part index
     pub fn to_glyph_range(&self, range: std::ops::Range<usize>) -> Range {
         if self.level.is_ltr() {
@@ -199,7 +528,7 @@ impl GlyphRun {
                 if part == 0 {
                     0
                 } else if part <= self.breaks.len() {
-                    to_usize(self.breaks[part - 1].gi)
+                    to_usize(self.breaks[part - 1].gi())
                 } else {
                     debug_assert_eq!(part, self.breaks.len() + 1);
                     self.glyphs.len()
@@ -214,7 +543,7 @@ impl GlyphRun {
                 if part == 0 {
                     0
                 } else if part <= self.breaks.len() {
-                    to_usize(self.breaks[part - 1].gi)
+                    to_usize(self.breaks[part - 1].gi())
                 } else {
                     debug_assert_eq!(part, self.breaks.len() + 1);
                     self.glyphs.len()
@@ -236,6 +565,23 @@ pub(crate) struct Input<'a> {
     pub base_level: Level,
     pub level: Level,
     pub script: Script,
+    /// Letter spacing (px)
+    pub letter_spacing: f32,
+    /// Word spacing (px)
+    pub word_spacing: f32,
+    pub orientation: Orientation,
+    pub white_space: WhiteSpace,
+}
+
+/// True if `c` is a word-separator character
+///
+/// Word spacing is applied to these characters. The list is taken from
+/// [CSS Text](https://www.w3.org/TR/css-text-3/#word-separator).
+fn is_word_separator(c: char) -> bool {
+    matches!(
+        c,
+        ' ' | '\u{A0}' | '\u{1361}' | '\u{10100}' | '\u{10101}' | '\u{1039F}' | '\u{1091F}'
+    )
 }
 
 /// Shape a `run` of text
@@ -250,7 +596,7 @@ pub(crate) fn shape(
     // All soft-break locations within this run, excluding the end
     mut breaks: TinyVec<[GlyphBreak; 4]>,
     special: RunSpecial,
-) -> GlyphRun {
+) -> (GlyphRun, RunExtra) {
     /*
     eprint!("shape[{} breaks, {:?}]:\t", breaks.len(), special);
     let mut start = range.start();
@@ -269,6 +615,7 @@ pub(crate) fn shape(
     }
 
     let mut glyphs = vec![];
+    let mut v_offsets = vec![];
     let mut no_space_end = 0.0;
     let mut caret = 0.0;
 
@@ -284,8 +631,9 @@ pub(crate) fn shape(
         let r = shape_simple(sf, input, range, &mut breaks);
 
         glyphs = r.0;
-        no_space_end = r.1;
-        caret = r.2;
+        v_offsets = r.1;
+        no_space_end = r.2;
+        caret = r.3;
     }
 
     if input.level.is_rtl() {
@@ -300,7 +648,7 @@ pub(crate) fn shape(
             if let Some(b) = breaks.get_mut(break_i)
                 && b.index == glyph.index
             {
-                b.gi = to_u32(gi);
+                b.set_gi(to_u32(gi));
                 b.no_space_end = start_no_space - side_bearing(last_id);
                 break_i += 1;
             }
@@ -321,13 +669,18 @@ pub(crate) fn shape(
     #[cfg(debug_assertions)]
     {
         debug_assert!(glyphs.iter().is_sorted_by_key(|g| g.index));
-        debug_assert!(breaks.iter().all(|b| b.gi > 0));
+        debug_assert!(breaks.iter().all(|b| b.gi() > 0));
         for b in &breaks {
-            assert_eq!(b.index, glyphs[to_usize(b.gi)].index);
+            assert_eq!(b.index, glyphs[to_usize(b.gi())].index);
         }
     }
 
-    GlyphRun {
+    let mut kashida = vec![];
+    if input.script == Script::Arabic && face.glyph_index('\u{0640}').0 != 0 {
+        kashida = kashida_points(input.text, range, &glyphs);
+    }
+
+    let run = GlyphRun {
         range,
         dpem: input.dpem,
         dpu,
@@ -340,7 +693,97 @@ pub(crate) fn shape(
         breaks,
         no_space_end,
         caret,
+    };
+    let extra = RunExtra {
+        no_wrap: !input.white_space.wraps(),
+        orientation: input.orientation,
+        v_offsets,
+        object: None,
+        kashida,
+        primary_face: None,
+    };
+    (run, extra)
+}
+
+/// Construct a run for an inline object
+///
+/// The object is represented by a single placeholder glyph at `range.start`.
+pub(crate) fn shape_object(
+    input: Input,
+    range: Range,
+    face_id: FaceId,
+    object: InlineObject,
+    special: RunSpecial,
+) -> (GlyphRun, RunExtra) {
+    let dpu = fonts::library().get_face(face_id).dpu(input.dpem);
+    let glyph = Glyph {
+        index: range.start,
+        id: GlyphId(0),
+        position: Vec2::ZERO,
+    };
+
+    let run = GlyphRun {
+        range,
+        dpem: input.dpem,
+        dpu,
+        face_id,
+        special,
+        base_level: input.base_level,
+        level: input.level,
+
+        glyphs: vec![glyph],
+        breaks: Default::default(),
+        no_space_end: object.width,
+        caret: object.width,
+    };
+    let extra = RunExtra {
+        no_wrap: !input.white_space.wraps(),
+        orientation: input.orientation,
+        object: Some(object),
+        ..Default::default()
+    };
+    (run, extra)
+}
+
+/// Find kashida opportunities within `range`
+///
+/// A tatweel may be inserted between a letter which joins to the following
+/// letter and a letter which joins to the preceding letter (ignoring
+/// transparent characters such as vowel marks). Result values are indices into
+/// `glyphs`, which must be in logical order.
+fn kashida_points(text: &str, range: Range, glyphs: &[Glyph]) -> Vec<u32> {
+    use icu_properties::{CodePointMapData, props::JoiningType};
+
+    let joining_type = CodePointMapData::<JoiningType>::new();
+    let mut points = vec![];
+    let mut joins_next = false;
+    let mut gi = 0;
+    for (i, c) in text[range].char_indices() {
+        let jt = joining_type.get(c);
+        if jt == JoiningType::Transparent {
+            continue;
+        }
+
+        let joins_prev = matches!(
+            jt,
+            JoiningType::DualJoining | JoiningType::RightJoining | JoiningType::JoinCausing
+        );
+        if joins_next && joins_prev {
+            let index = range.start + to_u32(i);
+            while gi < glyphs.len() && glyphs[gi].index < index {
+                gi += 1;
+            }
+            if gi > 0 && gi < glyphs.len() && glyphs[gi].index == index {
+                points.push(to_u32(gi));
+            }
+        }
+
+        joins_next = matches!(
+            jt,
+            JoiningType::DualJoining | JoiningType::LeftJoining | JoiningType::JoinCausing
+        );
     }
+    points
 }
 
 // Use Rustybuzz lib
@@ -350,12 +793,16 @@ fn shape_rustybuzz(
     range: Range,
     face_id: FaceId,
     breaks: &mut [GlyphBreak],
-) -> (Vec<Glyph>, f32, f32) {
+) -> (Vec<Glyph>, Vec<Vec2>, f32, f32) {
     let Input {
         text,
         dpem,
         level,
         script,
+        letter_spacing,
+        word_spacing,
+        orientation,
+        white_space,
         ..
     } = input;
 
@@ -370,19 +817,39 @@ fn shape_rustybuzz(
     let slice = &text[range];
     let idx_offset = range.start;
     let rtl = level.is_rtl();
+    let upright = orientation == Orientation::Upright;
 
     // TODO: cache the buffer for reuse later?
     let mut buffer = rustybuzz::UnicodeBuffer::new();
-    buffer.set_direction(match rtl {
-        false => rustybuzz::Direction::LeftToRight,
-        true => rustybuzz::Direction::RightToLeft,
+    buffer.set_direction(match (rtl, upright) {
+        (_, true) => rustybuzz::Direction::TopToBottom,
+        (false, false) => rustybuzz::Direction::LeftToRight,
+        (true, false) => rustybuzz::Direction::RightToLeft,
     });
-    buffer.push_str(slice);
+    let collapse = white_space.collapse(text, range.to_std());
+    let collapse_at = |i: usize| collapse.get(i).copied().unwrap_or(Collapse::Keep);
+    if !collapse.is_empty() {
+        // Collapsible chars are single-byte, thus indices are unaffected
+        let collapse = |(i, c)| match collapse_at(i) {
+            Collapse::Keep => c,
+            _ => ' ',
+        };
+        buffer.push_str(&slice.char_indices().map(collapse).collect::<String>());
+    } else {
+        buffer.push_str(slice);
+    }
     let tag = crate::util::to_ttf_parser_tag(script);
     if let Some(script) = rustybuzz::Script::from_iso15924_tag(tag) {
         buffer.set_script(script);
     }
-    let features = [];
+    // Optional ligatures are disabled when letter spacing is used (CSS Text §8.2)
+    let features = if letter_spacing != 0.0 {
+        use {rustybuzz::Feature, ttf_parser::Tag};
+        let off = |tag| Feature::new(Tag::from_bytes(tag), 0, ..);
+        vec![off(b"liga"), off(b"clig"), off(b"dlig"), off(b"hlig")]
+    } else {
+        vec![]
+    };
 
     let output = rustybuzz::shape(face, &features, buffer);
 
@@ -391,13 +858,30 @@ fn shape_rustybuzz(
     let mut break_i = 0;
 
     let mut glyphs = Vec::with_capacity(output.len());
+    let mut v_offsets = Vec::with_capacity(if upright { output.len() } else { 0 });
 
-    for (info, pos) in output
-        .glyph_infos()
+    let infos = output.glyph_infos();
+    for (i, (info, pos)) in infos
         .iter()
         .zip(output.glyph_positions().iter())
+        .enumerate()
     {
         let index = idx_offset + info.cluster;
+        let c = text[to_usize(index)..].chars().next().unwrap();
+        let collapse = collapse_at(to_usize(info.cluster));
+        let c = if collapse == Collapse::Keep { c } else { ' ' };
+        let letter_spacing = match collapse {
+            Collapse::Remove => 0.0,
+            _ => letter_spacing,
+        };
+
+        // Letter spacing follows each cluster in logical order. For RTL text
+        // we iterate in visual order, thus add spacing at the cluster start.
+        let cluster_start = i == 0 || infos[i - 1].cluster != info.cluster;
+        if rtl && cluster_start {
+            caret += letter_spacing;
+        }
+
         assert!(info.glyph_id <= u16::MAX as u32, "failed to map glyph id");
         let id = GlyphId(info.glyph_id as u16);
 
@@ -406,36 +890,52 @@ fn shape_rustybuzz(
             .map(|b| b.index == index)
             .unwrap_or(false)
         {
-            breaks[break_i].gi = to_u32(glyphs.len());
+            breaks[break_i].set_gi(to_u32(glyphs.len()));
             breaks[break_i].no_space_end = no_space_end;
             break_i += 1;
         }
 
-        let position = Vec2(
-            caret + dpu.i32_to_px(pos.x_offset),
-            dpu.i32_to_px(pos.y_offset),
-        );
+        let position = if upright {
+            // Offsets are relative to the pen position; font units have y upwards
+            v_offsets.push(Vec2(
+                dpu.i32_to_px(pos.x_offset),
+                -dpu.i32_to_px(pos.y_offset),
+            ));
+            Vec2(caret, 0.0)
+        } else {
+            Vec2(
+                caret + dpu.i32_to_px(pos.x_offset),
+                dpu.i32_to_px(pos.y_offset),
+            )
+        };
         glyphs.push(Glyph {
             index,
             id,
             position,
         });
 
-        // IIRC this is only applicable to vertical text, which we don't
-        // currently support:
-        debug_assert_eq!(pos.y_advance, 0);
-        caret += dpu.i32_to_px(pos.x_advance);
-        if text[to_usize(index)..]
-            .chars()
-            .next()
-            .map(|c| !c.is_whitespace())
-            .unwrap()
-        {
+        if collapse == Collapse::Remove {
+            // Collapsed white-space is invisible
+        } else if upright {
+            caret -= dpu.i32_to_px(pos.y_advance);
+        } else {
+            debug_assert_eq!(pos.y_advance, 0);
+            caret += dpu.i32_to_px(pos.x_advance);
+        }
+        if !c.is_whitespace() {
             no_space_end = caret;
         }
+        if is_word_separator(c) && collapse != Collapse::Remove {
+            caret += word_spacing;
+        }
+
+        let cluster_end = infos.get(i + 1).is_none_or(|n| n.cluster != info.cluster);
+        if !rtl && cluster_end {
+            caret += letter_spacing;
+        }
     }
 
-    (glyphs, no_space_end, caret)
+    (glyphs, v_offsets, no_space_end, caret)
 }
 
 // Simple implementation (kerning but no shaping)
@@ -445,46 +945,75 @@ fn shape_simple(
     input: Input<'_>,
     range: Range,
     breaks: &mut [GlyphBreak],
-) -> (Vec<Glyph>, f32, f32) {
-    let Input { text, level, .. } = input;
+) -> (Vec<Glyph>, Vec<Vec2>, f32, f32) {
+    let Input {
+        text,
+        level,
+        letter_spacing,
+        word_spacing,
+        orientation,
+        white_space,
+        ..
+    } = input;
 
+    use icu_segmenter::GraphemeClusterSegmenter;
     use unicode_bidi_mirroring::get_mirrored;
 
     let slice = &text[range];
     let idx_offset = range.start;
     let rtl = level.is_rtl();
+    let upright = orientation == Orientation::Upright;
 
     let mut caret = 0.0;
     let mut no_space_end = caret;
     let mut prev_glyph_id: Option<GlyphId> = None;
+    let mut v_offsets = vec![];
     let mut break_i = 0;
 
     // Allocate with an over-estimate and shrink later:
     let mut glyphs = Vec::with_capacity(slice.len());
+    let collapse = white_space.collapse(text, range.to_std());
+    let clusters: Vec<usize> = GraphemeClusterSegmenter::new().segment_str(slice).collect();
     let mut iter = slice.char_indices();
     let mut next_char_index = || match rtl {
         false => iter.next(),
         true => iter.next_back(),
     };
     while let Some((index, mut c)) = next_char_index() {
+        let cluster_end = clusters.binary_search(&(index + c.len_utf8())).is_ok();
         let index = idx_offset + to_u32(index);
         if rtl && let Some(m) = get_mirrored(c) {
             c = m;
         }
-        let id = sf.face().glyph_index(c);
+        let collapse = collapse.get(to_usize(index - idx_offset)).copied();
+        let collapse = collapse.unwrap_or(Collapse::Keep);
+        if collapse != Collapse::Keep {
+            c = ' ';
+        }
+        let mut id = sf.face().glyph_index(c);
+        let mut advance = match upright {
+            false => sf.h_advance(id),
+            true => sf.v_advance(id),
+        };
+        if c == '\u{AD}' || collapse == Collapse::Remove {
+            // Soft hyphens and collapsed white-space are invisible
+            id = sf.face().glyph_index(' ');
+            advance = 0.0;
+        }
 
         if breaks
             .get(break_i)
             .map(|b| b.index == index)
             .unwrap_or(false)
         {
-            breaks[break_i].gi = to_u32(glyphs.len());
+            breaks[break_i].set_gi(to_u32(glyphs.len()));
             breaks[break_i].no_space_end = no_space_end;
             break_i += 1;
             no_space_end = caret;
         }
 
         if let Some(prev) = prev_glyph_id
+            && !upright
             && let Some(kern) = sf.face().0.tables().kern
             && let Some(adv) = kern
                 .subtables
@@ -496,6 +1025,22 @@ fn shape_simple(
         }
         prev_glyph_id = Some(id);
 
+        // Letter spacing follows each grapheme cluster in logical order. For
+        // RTL text we iterate in visual order, thus add spacing before the
+        // last char of the cluster.
+        let letter_spacing = match collapse {
+            _ if !cluster_end => 0.0,
+            Collapse::Remove => 0.0,
+            _ => letter_spacing,
+        };
+        if rtl {
+            caret += letter_spacing;
+        }
+
+        if upright {
+            v_offsets.push(sf.v_origin_offset(id));
+        }
+
         let position = Vec2(caret, 0.0);
         let glyph = Glyph {
             index,
@@ -504,15 +1049,21 @@ fn shape_simple(
         };
         glyphs.push(glyph);
 
-        caret += sf.h_advance(id);
+        caret += advance;
         if !c.is_whitespace() {
             no_space_end = caret;
         }
+        if is_word_separator(c) && collapse != Collapse::Remove {
+            caret += word_spacing;
+        }
+        if !rtl {
+            caret += letter_spacing;
+        }
     }
 
     glyphs.shrink_to_fit();
 
-    (glyphs, no_space_end, caret)
+    (glyphs, v_offsets, no_space_end, caret)
 }
 
 /// Warning: test results may depend on system fonts
@@ -520,21 +1071,21 @@ fn shape_simple(
 /// Tests are extensions of those in `forme/text_runs.rs`.
 #[cfg(test)]
 mod test {
-    use crate::{Direction, FontToken, Forme};
+    use crate::{Direction, FontToken, Forme, Length};
     use std::iter;
     use std::ops::Range;
 
     type Expected<'a> = &'a [(Range<usize>, &'a [u32], &'a [u32])];
 
-    fn test_shaping(text: &str, dir: Direction, expected: Expected) {
-        let fonts = iter::once(FontToken {
-            start: 0,
-            dpem: 16.0,
-            font: Default::default(),
-        });
-
+    fn shape_with(text: &str, dir: Direction, token: FontToken) -> Forme {
         let mut forme = Forme::default();
+        let fonts = iter::once(token);
         assert!(forme.set_text(text, dir).with_tokens(fonts, false).is_ok());
+        forme
+    }
+
+    fn test_shaping(text: &str, dir: Direction, expected: Expected) {
+        let forme = shape_with(text, dir, FontToken::default());
 
         for (i, (run, expected)) in forme.raw_runs().iter().zip(expected.iter()).enumerate() {
             assert_eq!(
@@ -548,7 +1099,7 @@ mod test {
                 "glyph indices for text \"{text}\", run {i}"
             );
             assert_eq!(
-                run.breaks.iter().map(|b| b.gi).collect::<Vec<_>>(),
+                run.breaks.iter().map(|b| b.gi()).collect::<Vec<_>>(),
                 expected.2,
                 "glyph break indices for text \"{text}\", run {i}"
             );
@@ -613,6 +1164,65 @@ mod test {
         test_shaping(sample, Direction::Rtl, &expected_rtl[..]);
     }
 
+    #[test]
+    fn test_letter_spacing() {
+        let samples = [
+            ("abc de", Direction::Ltr, 6, None),
+            ("אבג דה", Direction::Rtl, 6, None),
+            // Combining marks are part of the preceding cluster
+            ("e\u{301}x", Direction::Ltr, 2, Some(1)),
+            ("אָבג", Direction::Rtl, 3, Some(2)),
+        ];
+        for (sample, dir, clusters, mark) in samples {
+            let base = shape_with(sample, dir, FontToken::default());
+            let token = FontToken {
+                letter_spacing: Length::Px(2.0),
+                ..Default::default()
+            };
+            let spaced = shape_with(sample, dir, token);
+
+            let (base, spaced) = (&base.raw_runs()[0], &spaced.raw_runs()[0]);
+            let clusters = clusters as f32;
+            assert_eq!(spaced.caret, base.caret + 2.0 * clusters, "{sample}");
+            // Trailing spacing is excluded from the length without white-space
+            let no_space = spaced.no_space_end - base.no_space_end;
+            if dir == Direction::Ltr {
+                assert_eq!(no_space, 2.0 * (clusters - 1.0), "{sample}");
+            } else {
+                assert_eq!(spaced.no_space_end, base.no_space_end + 2.0, "{sample}");
+            }
+            // A mark keeps its position relative to the base char
+            let x = |run: &crate::shaper::GlyphRun, index| {
+                let glyph = run.glyphs.iter().find(|g| g.index == index);
+                glyph.map(|g| g.position.0)
+            };
+            if let Some(mark) = mark
+                && let Some(x_mark) = x(base, mark)
+            {
+                let offset = x_mark - x(base, 0).unwrap();
+                let spaced_offset = x(spaced, mark).unwrap() - x(spaced, 0).unwrap();
+                assert_eq!(spaced_offset, offset, "{sample}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_word_spacing() {
+        let sample = "ab cd  ef";
+        let base = shape_with(sample, Direction::Ltr, FontToken::default());
+        let token = FontToken {
+            word_spacing: Length::Em(0.25),
+            ..Default::default()
+        };
+        let spaced = shape_with(sample, Direction::Ltr, token);
+
+        let (base, spaced) = (&base.raw_runs()[0], &spaced.raw_runs()[0]);
+        assert_eq!(spaced.caret, base.caret + 3.0 * 4.0);
+        let pos = |run: &crate::shaper::GlyphRun, i: usize| run.glyphs[i].position.0;
+        assert_eq!(pos(spaced, 3), pos(base, 3) + 4.0);
+        assert_eq!(pos(spaced, 7), pos(base, 7) + 12.0);
+    }
+
     // Additional tests for right-to-left languages: Hebrew, Arabic.
     // Samples are translations of the first article of the UDHR from https://r12a.github.io/
     #[test]
@@ -5,13 +5,26 @@
 
 //! Text object
 
+use crate::conv::{to_u32, to_usize};
 use crate::fonts::{FontSelector, NoFontMatch};
-use crate::format::FormattableText;
-use crate::forme::{Forme, MarkerPosIter, NotReady};
+use crate::format::{EditableText, FormattableText};
+use crate::forme::{
+    Affinity, CaretHeight, CaretShape, Forme, HitTest, Justify, LineSpacing, MarkerPos,
+    MarkerPosIter, NotReady, OverflowWrap, TabStops, Truncate, WhiteSpace, WrapStrategy,
+    WritingMode,
+};
+use crate::segment;
 use crate::{Align, Direction, GlyphRun, Line, Status, Vec2};
 use std::fmt::Debug;
 use std::num::NonZeroUsize;
 
+mod edit;
+pub use edit::{EditBuffer, Selection};
+
+mod preedit;
+use preedit::Composition;
+pub use preedit::Preedit;
+
 /// Text type-setting object (high-level API)
 ///
 /// This struct contains:
@@ -55,9 +68,11 @@ pub struct Text<T: FormattableText + ?Sized> {
     /// is to the top.
     align: (Align, Align),
     direction: Direction,
+    white_space: WhiteSpace,
     status: Status,
 
     forme: Forme,
+    composition: Option<Composition<T::Effect>>,
     text: T,
 }
 
@@ -82,9 +97,11 @@ impl<T: FormattableText> Text<T> {
             wrap_width: f32::INFINITY,
             align: Default::default(),
             direction: Direction::default(),
+            white_space: WhiteSpace::default(),
             status: Status::Empty,
             text,
             forme: Default::default(),
+            composition: None,
         }
     }
 
@@ -128,7 +145,10 @@ impl<T: FormattableText> Text<T> {
     ///
     /// One must call [`Text::prepare`] afterwards and may wish to inspect its
     /// return value to check the size allocation meets requirements.
+    ///
+    /// Any [preedit](Self::set_preedit) is cleared.
     pub fn set_text(&mut self, text: T) {
+        self.clear_preedit();
         if self.text == text {
             return; // no change
         }
@@ -136,6 +156,59 @@ impl<T: FormattableText> Text<T> {
         self.text = text;
         self.set_max_status(Status::Empty);
     }
+
+    /// Replace `range` of the text with `replace_with`
+    ///
+    /// See [`EditableText::replace_range`]. Where the text has already been
+    /// shaped, only affected paragraphs are re-shaped and re-wrapped (see
+    /// [`Forme::edit_text`]). One must call [`Text::prepare`] afterwards.
+    ///
+    /// Any [preedit](Self::set_preedit) is cleared.
+    pub fn replace_range(&mut self, range: std::ops::Range<usize>, replace_with: &str)
+    where
+        T: EditableText,
+    {
+        self.text.replace_range(range.clone(), replace_with);
+        self.edited(range, replace_with.len());
+    }
+
+    /// Replace `range` of the text with formatted text `replace_with`
+    ///
+    /// See [`EditableText::splice`] and [`Self::replace_range`].
+    pub fn splice(&mut self, range: std::ops::Range<usize>, replace_with: &T)
+    where
+        T: EditableText,
+    {
+        self.text.splice(range.clone(), replace_with);
+        self.edited(range, replace_with.as_str().len());
+    }
+
+    /// Update the [`Forme`] after `range` was replaced by `new_len` bytes
+    fn edited(&mut self, range: std::ops::Range<usize>, new_len: usize) {
+        // The AutoRtl heuristic applies to the whole text
+        if self.composition.take().is_some()
+            || self.status == Status::Empty
+            || self.direction == Direction::AutoRtl
+        {
+            self.status = Status::Empty;
+            return;
+        }
+
+        let text = self.text.as_str();
+        let mut appender = self.forme.edit_text(text, self.direction, range, new_len);
+        appender.with_white_space(self.white_space);
+        for (index, format) in self.text.paragraph_formats() {
+            appender.with_paragraph(to_usize(*index), *format);
+        }
+        for (index, object) in self.text.objects() {
+            appender.with_object(to_usize(*index), *object);
+        }
+        let tokens = self.text.font_tokens(self.dpem, self.font);
+        self.status = match appender.with_tokens(tokens, true) {
+            Ok(_) => Status::Shaped,
+            Err(_) => Status::Empty,
+        };
+    }
 }
 
 /// Text, font and type-setting getters and setters
@@ -238,6 +311,47 @@ impl<T: FormattableText + ?Sized> Text<T> {
         }
     }
 
+    /// Get the white-space handling mode
+    #[inline]
+    pub fn white_space(&self) -> WhiteSpace {
+        self.white_space
+    }
+
+    /// Set the white-space handling mode
+    ///
+    /// See [`Appender::with_white_space`](crate::Appender::with_white_space).
+    ///
+    /// It is necessary to [`prepare`][Self::prepare] the text after calling this.
+    #[inline]
+    pub fn set_white_space(&mut self, mode: WhiteSpace) {
+        if mode != self.white_space {
+            self.white_space = mode;
+            self.set_max_status(Status::Empty);
+        }
+    }
+
+    /// Get the writing mode
+    #[inline]
+    pub fn writing_mode(&self) -> WritingMode {
+        self.forme.writing_mode()
+    }
+
+    /// Set the writing mode
+    ///
+    /// In vertical writing modes, the wrap width applies to the vertical axis
+    /// and alignment is `(inline, block)`: `align.0` applies to the vertical
+    /// axis and `align.1` to the horizontal axis. See also
+    /// [`Forme::set_writing_mode`].
+    ///
+    /// It is necessary to [`prepare`][Self::prepare] the text after calling this.
+    #[inline]
+    pub fn set_writing_mode(&mut self, mode: WritingMode) {
+        if mode != self.forme.writing_mode() {
+            self.forme.set_writing_mode(mode);
+            self.set_max_status(Status::Empty);
+        }
+    }
+
     /// Get the text wrap width
     #[inline]
     pub fn wrap_width(&self) -> f32 {
@@ -283,6 +397,114 @@ impl<T: FormattableText + ?Sized> Text<T> {
         }
     }
 
+    /// Get justification options
+    #[inline]
+    pub fn justify(&self) -> Justify {
+        self.forme.justify()
+    }
+
+    /// Set justification options
+    ///
+    /// These options affect text with horizontal alignment [`Align::Stretch`].
+    ///
+    /// It is necessary to [`prepare`][Self::prepare] the text after calling this.
+    #[inline]
+    pub fn set_justify(&mut self, justify: Justify) {
+        if justify != self.forme.justify() {
+            self.forme.set_justify(justify);
+            self.set_max_status(Status::Shaped);
+        }
+    }
+
+    /// Get tab stops
+    #[inline]
+    pub fn tab_stops(&self) -> &TabStops {
+        self.forme.tab_stops()
+    }
+
+    /// Set tab stops
+    ///
+    /// It is necessary to [`prepare`][Self::prepare] the text after calling this.
+    #[inline]
+    pub fn set_tab_stops(&mut self, tab_stops: TabStops) {
+        if tab_stops != *self.forme.tab_stops() {
+            self.forme.set_tab_stops(tab_stops);
+            self.set_max_status(Status::Shaped);
+        }
+    }
+
+    /// Get line spacing options
+    #[inline]
+    pub fn line_spacing(&self) -> LineSpacing {
+        self.forme.line_spacing()
+    }
+
+    /// Set line spacing options
+    ///
+    /// It is necessary to [`prepare`][Self::prepare] the text after calling this.
+    #[inline]
+    pub fn set_line_spacing(&mut self, spacing: LineSpacing) {
+        if spacing != self.forme.line_spacing() {
+            self.forme.set_line_spacing(spacing);
+            self.set_max_status(Status::Shaped);
+        }
+    }
+
+    /// Get truncation options
+    #[inline]
+    pub fn truncate(&self) -> &Truncate {
+        self.forme.truncate()
+    }
+
+    /// Set truncation options
+    ///
+    /// It is necessary to [`prepare`][Self::prepare] the text after calling this.
+    #[inline]
+    pub fn set_truncate(&mut self, truncate: Truncate) {
+        if truncate != *self.forme.truncate() {
+            self.forme.set_truncate(truncate);
+            self.set_max_status(Status::Shaped);
+        }
+    }
+
+    /// Get the line-breaking strategy
+    #[inline]
+    pub fn wrap_strategy(&self) -> WrapStrategy {
+        self.forme.wrap_strategy()
+    }
+
+    /// Set the line-breaking strategy
+    ///
+    /// See [`Forme::set_wrap_strategy`].
+    ///
+    /// It is necessary to [`prepare`][Self::prepare] the text after calling this.
+    #[inline]
+    pub fn set_wrap_strategy(&mut self, strategy: WrapStrategy) {
+        if strategy != self.forme.wrap_strategy() {
+            self.forme.set_wrap_strategy(strategy);
+            self.set_max_status(Status::Shaped);
+        }
+    }
+
+    /// Get the overflow wrapping mode
+    #[inline]
+    pub fn overflow_wrap(&self) -> OverflowWrap {
+        self.forme.overflow_wrap()
+    }
+
+    /// Set the overflow wrapping mode
+    ///
+    /// See [`Forme::set_overflow_wrap`].
+    ///
+    /// It is necessary to [`prepare`][Self::prepare] the text after calling this.
+    #[inline]
+    pub fn set_overflow_wrap(&mut self, mode: OverflowWrap) {
+        if mode != self.forme.overflow_wrap() {
+            self.forme.set_overflow_wrap(mode);
+            self.set_max_status(Status::Shaped);
+        }
+    }
+
     /// Get text bounds
     #[inline]
     pub fn bounds(&self) -> Vec2 {
@@ -299,7 +521,11 @@ impl<T: FormattableText + ?Sized> Text<T> {
     pub fn set_bounds(&mut self, bounds: Vec2) {
         debug_assert!(bounds.is_finite());
         if bounds != self.bounds {
-            if bounds.0 != self.bounds.0 {
+            let inline_changed = match self.forme.writing_mode().is_vertical() {
+                false => bounds.0 != self.bounds.0,
+                true => bounds.1 != self.bounds.1,
+            };
+            if inline_changed {
                 self.set_max_status(Status::Shaped);
             } else {
                 self.set_max_status(Status::Wrapped);
@@ -381,10 +607,7 @@ impl<T: FormattableText + ?Sized> Text<T> {
     #[inline]
     fn prepare_runs(&mut self) -> Result<(), NoFontMatch> {
         match self.status {
-            Status::Empty => self
-                .forme
-                .set_text(self.text.as_str(), self.direction)
-                .with_tokens(self.text.font_tokens(self.dpem, self.font), true)?,
+            Status::Empty => self.shape()?,
             _ => (),
         }
 
@@ -392,6 +615,40 @@ impl<T: FormattableText + ?Sized> Text<T> {
         Ok(())
     }
 
+    /// Set the display text on the [`Forme`]
+    fn shape(&mut self) -> Result<(), NoFontMatch> {
+        // Text inserted by a preedit is formatted as the preceding text
+        let (index, len) = match &self.composition {
+            Some(c) => (to_u32(c.preedit.index), to_u32(c.preedit.text.len())),
+            None => (u32::MAX, 0),
+        };
+        let display = match &self.composition {
+            Some(c) => &c.text,
+            None => self.text.as_str(),
+        };
+
+        let mut appender = self.forme.set_text(display, self.direction);
+        appender.with_white_space(self.white_space);
+        for (start, format) in self.text.paragraph_formats() {
+            let start = if *start > index { start + len } else { *start };
+            appender.with_paragraph(to_usize(start), *format);
+        }
+        for (start, object) in self.text.objects() {
+            let start = if *start >= index { start + len } else { *start };
+            appender.with_object(to_usize(start), *object);
+        }
+        let tokens = self
+            .text
+            .font_tokens(self.dpem, self.font)
+            .map(|mut token| {
+                if token.start > index || (token.start == index && index > 0) {
+                    token.start += len;
+                }
+                token
+            });
+        appender.with_tokens(tokens, true)
+    }
+
     /// Measure required width, up to some `max_width`
     ///
     /// This method partially prepares the [`Forme`] as required.
@@ -401,19 +658,37 @@ impl<T: FormattableText + ?Sized> Text<T> {
     /// exceeds `max_width`, the algorithm stops early, returning `max_width`.
     ///
     /// The return value is unaffected by alignment and wrap configuration.
+    ///
+    /// In vertical [writing modes](Self::set_writing_mode), this measures the
+    /// length of lines on the vertical axis.
     pub fn measure_width(&mut self, max_width: f32) -> Result<f32, NoFontMatch> {
         self.prepare_runs()?;
 
         Ok(self.forme.measure_width(max_width))
     }
 
+    /// Measure the minimum width required to avoid overflow
+    ///
+    /// This method partially prepares the [`Forme`] as required.
+    ///
+    /// See [`Forme::measure_min_width`].
+    pub fn measure_min_width(&mut self) -> Result<f32, NoFontMatch> {
+        self.prepare_runs()?;
+
+        Ok(self.forme.measure_min_width())
+    }
+
     /// Measure required vertical height, wrapping as configured
     ///
-    /// Stops after `max_lines`, if provided.
+    /// Stops after `max_lines` or [`Truncate::max_lines`], if provided.
+    ///
+    /// In vertical [writing modes](Self::set_writing_mode), this measures the
+    /// horizontal extent of all lines.
     pub fn measure_height(&mut self, max_lines: Option<NonZeroUsize>) -> Result<f32, NoFontMatch> {
         if self.status >= Status::Wrapped {
-            let (tl, br) = self.forme.bounding_box();
-            return Ok(br.1 - tl.1);
+            let top = self.forme.lines().next().map(|line| line.top());
+            let bottom = self.forme.lines().last().map(|line| line.bottom());
+            return Ok(bottom.unwrap_or(0.0) - top.unwrap_or(0.0));
         }
 
         self.prepare_runs()?;
@@ -439,13 +714,18 @@ impl<T: FormattableText + ?Sized> Text<T> {
         self.prepare_runs().unwrap();
         debug_assert!(self.status >= Status::Shaped);
 
+        let (inline, block) = match self.forme.writing_mode().is_vertical() {
+            false => (self.bounds.0, self.bounds.1),
+            true => (self.bounds.1, self.bounds.0),
+        };
+
         if self.status == Status::Shaped {
             self.forme
-                .prepare_lines(self.wrap_width, self.bounds.0, self.align.0);
+                .prepare_lines(self.wrap_width, inline, self.align.0);
         }
 
         if self.status <= Status::Wrapped {
-            self.forme.vertically_align(self.bounds.1, self.align.1);
+            self.forme.vertically_align(block, self.align.1);
         }
 
         self.status = Status::Ready;
@@ -493,7 +773,13 @@ impl<T: FormattableText + ?Sized> Text<T> {
         &self,
         index: usize,
     ) -> Result<Option<(usize, std::ops::Range<usize>)>, NotReady> {
-        Ok(self.wrapped_forme()?.find_line(index))
+        let result = self
+            .wrapped_forme()?
+            .find_line(self.to_display_index(index));
+        Ok(result.map(|(line, range)| {
+            let range = self.from_display_index(range.start)..self.from_display_index(range.end);
+            (line, range)
+        }))
     }
 
     /// Get the directionality of the current line
@@ -509,7 +795,8 @@ impl<T: FormattableText + ?Sized> Text<T> {
     /// See [`Forme::text_index_nearest`].
     #[inline]
     pub fn text_index_nearest(&self, pos: Vec2) -> Result<usize, NotReady> {
-        Ok(self.forme()?.text_index_nearest(pos))
+        let index = self.forme()?.text_index_nearest(pos);
+        Ok(self.from_display_index(index))
     }
 
     /// Find the text index nearest horizontal-coordinate `x` on `line`
@@ -517,14 +804,222 @@ impl<T: FormattableText + ?Sized> Text<T> {
     /// See [`Forme::line_index_nearest`].
     #[inline]
     pub fn line_index_nearest(&self, line: usize, x: f32) -> Result<Option<usize>, NotReady> {
-        Ok(self.wrapped_forme()?.line_index_nearest(line, x))
+        let index = self.wrapped_forme()?.line_index_nearest(line, x);
+        Ok(index.map(|index| self.from_display_index(index)))
     }
 
     /// Find the starting position (top-left) of the glyph at the given index
     ///
     /// See [`Forme::text_glyph_pos`].
     pub fn text_glyph_pos(&self, index: usize) -> Result<MarkerPosIter, NotReady> {
-        Ok(self.forme()?.text_glyph_pos(index))
+        Ok(self.forme()?.text_glyph_pos(self.to_display_index(index)))
+    }
+
+    /// Find the caret position nearest `pos`
+    ///
+    /// See [`Forme::hit_test`].
+    #[inline]
+    pub fn hit_test(&self, pos: Vec2) -> Result<HitTest, NotReady> {
+        let mut hit = self.forme()?.hit_test(pos);
+        hit.index = self.from_display_index(hit.index);
+        Ok(hit)
+    }
+
+    /// Find the position of the caret at `index` with the given `affinity`
+    ///
+    /// See [`Forme::caret_pos`].
+    #[inline]
+    pub fn caret_pos(
+        &self,
+        index: usize,
+        affinity: Affinity,
+    ) -> Result<Option<MarkerPos>, NotReady> {
+        Ok(self
+            .forme()?
+            .caret_pos(self.to_display_index(index), affinity))
+    }
+
+    /// Find the shape of the caret at `index` with the given `affinity`
+    ///
+    /// See [`Forme::caret_shape`].
+    #[inline]
+    pub fn caret_shape(
+        &self,
+        index: usize,
+        affinity: Affinity,
+        height: CaretHeight,
+    ) -> Result<Option<CaretShape>, NotReady> {
+        let index = self.to_display_index(index);
+        Ok(self.forme()?.caret_shape(index, affinity, height))
+    }
+
+    /// Get rectangles covering the text `range`, for drawing a selection
+    ///
+    /// See [`Forme::selection_rects`].
+    #[inline]
+    pub fn selection_rects(
+        &self,
+        range: std::ops::Range<usize>,
+        extend_newlines: bool,
+    ) -> Result<Vec<(Vec2, Vec2)>, NotReady> {
+        let range = self.to_display_range(range);
+        Ok(self.forme()?.selection_rects(range, extend_newlines))
+    }
+
+    /// Find the next grapheme cluster boundary after `index`
+    ///
+    /// See [`segment::next_grapheme`].
+    #[inline]
+    pub fn next_grapheme(&self, index: usize) -> usize {
+        segment::next_grapheme(self.as_str(), index)
+    }
+
+    /// Find the previous grapheme cluster boundary before `index`
+    ///
+    /// See [`segment::prev_grapheme`].
+    #[inline]
+    pub fn prev_grapheme(&self, index: usize) -> usize {
+        segment::prev_grapheme(self.as_str(), index)
+    }
+
+    /// Find the end of the next word after `index`
+    ///
+    /// See [`segment::next_word_end`].
+    #[inline]
+    pub fn next_word_end(&self, index: usize) -> usize {
+        segment::next_word_end(self.as_str(), index)
+    }
+
+    /// Find the start of the previous word before `index`
+    ///
+    /// See [`segment::prev_word_start`].
+    #[inline]
+    pub fn prev_word_start(&self, index: usize) -> usize {
+        segment::prev_word_start(self.as_str(), index)
+    }
+
+    /// Find the end of the next paragraph after `index`
+    ///
+    /// See [`segment::next_paragraph_end`].
+    #[inline]
+    pub fn next_paragraph_end(&self, index: usize) -> usize {
+        segment::next_paragraph_end(self.as_str(), index)
+    }
+
+    /// Find the start of the previous paragraph before `index`
+    ///
+    /// See [`segment::prev_paragraph_start`].
+    #[inline]
+    pub fn prev_paragraph_start(&self, index: usize) -> usize {
+        segment::prev_paragraph_start(self.as_str(), index)
+    }
+
+    /// Find the range of the word at `index`
+    ///
+    /// See [`segment::word_at`].
+    #[inline]
+    pub fn word_at(&self, index: usize, options: segment::WordOptions) -> std::ops::Range<usize> {
+        segment::word_at(self.as_str(), index, options)
+    }
+
+    /// Find the range of the sentence at `index`
+    ///
+    /// See [`segment::sentence_at`].
+    #[inline]
+    pub fn sentence_at(&self, index: usize) -> std::ops::Range<usize> {
+        segment::sentence_at(self.as_str(), index)
+    }
+
+    /// Find the range of the paragraph at `index`
+    ///
+    /// See [`segment::paragraph_at`].
+    #[inline]
+    pub fn paragraph_at(&self, index: usize) -> std::ops::Range<usize> {
+        segment::paragraph_at(self.as_str(), index)
+    }
+
+    /// Find the start of the line containing `index`
+    ///
+    /// See [`Forme::line_start`].
+    #[inline]
+    pub fn line_start(&self, index: usize) -> Result<usize, NotReady> {
+        let index = self
+            .wrapped_forme()?
+            .line_start(self.to_display_index(index));
+        Ok(self.from_display_index(index))
+    }
+
+    /// Find the end of the line containing `index`
+    ///
+    /// See [`Forme::line_end`].
+    #[inline]
+    pub fn line_end(&self, index: usize) -> Result<usize, NotReady> {
+        let index = self.wrapped_forme()?.line_end(self.to_display_index(index));
+        Ok(self.from_display_index(index))
+    }
+
+    /// Move the cursor one grapheme cluster to the left (visually)
+    ///
+    /// See [`Forme::move_left`].
+    #[inline]
+    pub fn move_left(&self, index: usize) -> Result<usize, NotReady> {
+        self.move_display(index, |forme, text, index| forme.move_left(text, index))
+    }
+
+    /// Move the cursor one grapheme cluster to the right (visually)
+    ///
+    /// See [`Forme::move_right`].
+    #[inline]
+    pub fn move_right(&self, index: usize) -> Result<usize, NotReady> {
+        self.move_display(index, |forme, text, index| forme.move_right(text, index))
+    }
+
+    /// Apply a cursor movement in display text, skipping over any preedit
+    fn move_display(
+        &self,
+        index: usize,
+        f: impl Fn(&Forme, &str, usize) -> usize,
+    ) -> Result<usize, NotReady> {
+        let forme = self.forme()?;
+        let mut display = self.to_display_index(index);
+        loop {
+            let next = f(forme, self.display_str(), display);
+            let result = self.from_display_index(next);
+            if next == display || result != index {
+                return Ok(result);
+            }
+            display = next;
+        }
+    }
+
+    /// Move the cursor up or down by `lines`
+    ///
+    /// See [`Forme::move_vertical`].
+    #[inline]
+    pub fn move_vertical(
+        &self,
+        index: usize,
+        lines: isize,
+        goal_x: Option<f32>,
+    ) -> Result<(usize, f32), NotReady> {
+        let forme = self.forme()?;
+        let (index, goal_x) = forme.move_vertical(self.to_display_index(index), lines, goal_x);
+        Ok((self.from_display_index(index), goal_x))
+    }
+
+    /// Move the cursor up or down by a `distance` in pixels
+    ///
+    /// See [`Forme::move_page`].
+    #[inline]
+    pub fn move_page(
+        &self,
+        index: usize,
+        distance: f32,
+        goal_x: Option<f32>,
+    ) -> Result<(usize, f32), NotReady> {
+        let forme = self.forme()?;
+        let (index, goal_x) = forme.move_page(self.to_display_index(index), distance, goal_x);
+        Ok((self.from_display_index(index), goal_x))
     }
 
     /// Iterate over runs of positioned glyphs
@@ -532,14 +1027,20 @@ impl<T: FormattableText + ?Sized> Text<T> {
     /// All glyphs are translated by the given `offset` (this is practically
     /// free).
     ///
-    /// Uses effect tokens supplied by [`FormattableText::effect_tokens`].
+    /// Uses effect tokens supplied by [`FormattableText::effect_tokens`],
+    /// merged with those of any [preedit](Self::set_preedit) (as at the time
+    /// the preedit was set).
     ///
     /// Runs are yielded in undefined order.
     pub fn runs<'a>(
         &'a self,
         offset: Vec2,
     ) -> Result<impl Iterator<Item = GlyphRun<'a, T::Effect>> + 'a, NotReady> {
-        Ok(self.forme()?.runs(offset, self.text.effect_tokens()))
+        let effects = match &self.composition {
+            Some(c) => &c.effects,
+            None => self.text.effect_tokens(),
+        };
+        Ok(self.forme()?.runs(offset, effects))
     }
 
     /// Iterate over runs of positioned glyphs using a custom effects list
@@ -553,7 +1054,9 @@ impl<T: FormattableText + ?Sized> Text<T> {
     /// sequence such that `effects[i].0` values are strictly increasing. A
     /// glyph for index `j` in the source text will use effect `effects[i].1`
     /// where `i` is the largest value such that `effects[i].0 <= j`, or the
-    /// default value of `E` if no such `i` exists.
+    /// default value of `E` if no such `i` exists. While a
+    /// [preedit](Self::set_preedit) is set, indices are into the
+    /// [display text](Self::display_str).
     ///
     /// Runs are yielded in undefined order.
     pub fn runs_with_effects<'a, E: Copy + Debug + Default>(
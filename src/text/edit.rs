@@ -0,0 +1,433 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! Editable text buffer with undo history
+
+use super::Text;
+use crate::format::EditableText;
+use std::ops::Range;
+
+/// A text selection
+///
+/// The selected range is between `anchor` and `cursor`; where these are equal
+/// the selection is empty (a caret). Indices are byte indices into the text.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Selection {
+    /// The fixed end of the selection
+    pub anchor: usize,
+    /// The moving end of the selection (the caret position)
+    pub cursor: usize,
+}
+
+impl Selection {
+    /// Construct a selection from `anchor` to `cursor`
+    #[inline]
+    pub fn new(anchor: usize, cursor: usize) -> Self {
+        Selection { anchor, cursor }
+    }
+
+    /// Construct an empty selection (a caret) at `index`
+    #[inline]
+    pub fn caret(index: usize) -> Self {
+        Selection::new(index, index)
+    }
+
+    /// Get the selected range
+    #[inline]
+    pub fn range(&self) -> Range<usize> {
+        self.anchor.min(self.cursor)..self.anchor.max(self.cursor)
+    }
+
+    /// True if the selection is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.cursor
+    }
+
+    /// Adjust for replacement of `range` by `new_len` bytes
+    ///
+    /// Positions before `range` (including at its start) are unaffected,
+    /// positions after `range` are shifted and positions within `range`
+    /// move to the end of the new text.
+    pub fn adjust(&mut self, range: Range<usize>, new_len: usize) {
+        let adjust = |index: usize| {
+            if index <= range.start {
+                index
+            } else if index >= range.end {
+                index - range.end + range.start + new_len
+            } else {
+                range.start + new_len
+            }
+        };
+        self.anchor = adjust(self.anchor);
+        self.cursor = adjust(self.cursor);
+    }
+}
+
+/// A single replacement
+#[derive(Clone, Debug)]
+struct Change<T> {
+    start: usize,
+    removed: T,
+    inserted: T,
+}
+
+/// A group of changes, undone and redone together
+#[derive(Clone, Debug)]
+struct Transaction<T> {
+    changes: Vec<Change<T>>,
+    before: Selection,
+    after: Selection,
+}
+
+/// An editable text buffer with selection and undo history
+///
+/// This wraps a [`Text`] with a [`Selection`] and undo/redo history. Edits
+/// are applied via [`Text::replace_range`], thus formatting tokens are
+/// adjusted (see [`EditableText`]) and only affected paragraphs are re-shaped
+/// and re-wrapped. Call [`Text::prepare`] (via [`Self::text_mut`]) after
+/// editing.
+///
+/// Each edit is recorded as an undoable transaction, unless made between
+/// [`Self::begin_group`] and [`Self::end_group`], in which case all edits of
+/// the group form a single transaction.
+#[derive(Clone, Debug)]
+pub struct EditBuffer<T: EditableText> {
+    text: Text<T>,
+    selection: Selection,
+    undo: Vec<Transaction<T>>,
+    redo: Vec<Transaction<T>>,
+    depth: usize,
+}
+
+impl<T: EditableText + Default> Default for EditBuffer<T> {
+    #[inline]
+    fn default() -> Self {
+        EditBuffer::new(Text::default())
+    }
+}
+
+impl<T: EditableText> EditBuffer<T> {
+    /// Construct from a [`Text`]
+    ///
+    /// The selection is initially a caret at the end of the text.
+    #[inline]
+    pub fn new(text: Text<T>) -> Self {
+        let selection = Selection::caret(text.str_len());
+        EditBuffer {
+            text,
+            selection,
+            undo: vec![],
+            redo: vec![],
+            depth: 0,
+        }
+    }
+
+    /// Access the [`Text`]
+    #[inline]
+    pub fn text(&self) -> &Text<T> {
+        &self.text
+    }
+
+    /// Access the [`Text`] mutably
+    ///
+    /// This may be used to adjust configuration and to prepare the text.
+    /// To replace the text, use [`Self::set_text`] instead of
+    /// [`Text::set_text`] (otherwise the undo history is invalid).
+    #[inline]
+    pub fn text_mut(&mut self) -> &mut Text<T> {
+        &mut self.text
+    }
+
+    /// Extract the [`Text`], discarding the history
+    #[inline]
+    pub fn into_text(self) -> Text<T> {
+        self.text
+    }
+
+    /// Replace the text
+    ///
+    /// This clears the undo history and sets the selection to a caret at the
+    /// end of the text.
+    pub fn set_text(&mut self, text: T) {
+        self.text.set_text(text);
+        self.selection = Selection::caret(self.text.str_len());
+        self.clear_history();
+    }
+
+    /// Get the selection
+    #[inline]
+    pub fn selection(&self) -> Selection {
+        self.selection
+    }
+
+    /// Set the selection
+    ///
+    /// Indices should be on `char` boundaries and are clamped to the length
+    /// of the text.
+    #[inline]
+    pub fn set_selection(&mut self, selection: Selection) {
+        let len = self.text.str_len();
+        self.selection = Selection::new(selection.anchor.min(len), selection.cursor.min(len));
+    }
+
+    /// Replace the selection with `text`, placing the caret after
+    #[inline]
+    pub fn insert(&mut self, text: &str) {
+        let range = self.selection.range();
+        let caret = Selection::caret(range.start + text.len());
+        self.edit(range, text, Some(caret));
+    }
+
+    /// Replace `range` of the text with `replace_with`
+    ///
+    /// The selection is adjusted (see [`Selection::adjust`]).
+    #[inline]
+    pub fn replace(&mut self, range: Range<usize>, replace_with: &str) {
+        self.edit(range, replace_with, None);
+    }
+
+    /// Delete `range` of the text
+    ///
+    /// The selection is adjusted (see [`Selection::adjust`]).
+    #[inline]
+    pub fn delete(&mut self, range: Range<usize>) {
+        self.edit(range, "", None);
+    }
+
+    fn edit(&mut self, range: Range<usize>, replace_with: &str, selection: Option<Selection>) {
+        let before = self.selection;
+        let removed = self.text.text().slice(range.clone());
+        self.text.replace_range(range.clone(), replace_with);
+        let end = range.start + replace_with.len();
+        let inserted = self.text.text().slice(range.start..end);
+
+        self.selection = selection.unwrap_or_else(|| {
+            let mut selection = before;
+            selection.adjust(range.clone(), replace_with.len());
+            selection
+        });
+
+        let change = Change {
+            start: range.start,
+            removed,
+            inserted,
+        };
+        self.redo.clear();
+        match self.undo.last_mut() {
+            Some(transaction) if self.depth > 0 => transaction.changes.push(change),
+            _ => self.undo.push(Transaction {
+                changes: vec![change],
+                before,
+                after: self.selection,
+            }),
+        }
+    }
+
+    /// Begin a group of edits
+    ///
+    /// All edits until the matching call to [`Self::end_group`] form a single
+    /// transaction for the purposes of undo and redo. Groups may be nested.
+    pub fn begin_group(&mut self) {
+        if self.depth == 0 {
+            self.undo.push(Transaction {
+                changes: vec![],
+                before: self.selection,
+                after: self.selection,
+            });
+        }
+        self.depth += 1;
+    }
+
+    /// End a group of edits
+    ///
+    /// Panics if there is no matching call to [`Self::begin_group`].
+    pub fn end_group(&mut self) {
+        assert!(self.depth > 0, "end_group without begin_group");
+        self.depth -= 1;
+        if self.depth == 0 {
+            let transaction = self.undo.last_mut().unwrap();
+            if transaction.changes.is_empty() {
+                self.undo.pop();
+            } else {
+                transaction.after = self.selection;
+            }
+        }
+    }
+
+    /// True if there is a transaction to undo
+    #[inline]
+    pub fn can_undo(&self) -> bool {
+        self.depth == 0 && !self.undo.is_empty()
+    }
+
+    /// True if there is a transaction to redo
+    #[inline]
+    pub fn can_redo(&self) -> bool {
+        self.depth == 0 && !self.redo.is_empty()
+    }
+
+    /// Undo the last transaction
+    ///
+    /// The selection is restored to that before the transaction.
+    /// Returns `false` if there is nothing to undo or a group is open.
+    pub fn undo(&mut self) -> bool {
+        if !self.can_undo() {
+            return false;
+        }
+        let transaction = self.undo.pop().unwrap();
+        for change in transaction.changes.iter().rev() {
+            let end = change.start + change.inserted.as_str().len();
+            self.text.splice(change.start..end, &change.removed);
+        }
+        self.selection = transaction.before;
+        self.redo.push(transaction);
+        true
+    }
+
+    /// Redo the last undone transaction
+    ///
+    /// The selection is restored to that after the transaction.
+    /// Returns `false` if there is nothing to redo or a group is open.
+    pub fn redo(&mut self) -> bool {
+        if !self.can_redo() {
+            return false;
+        }
+        let transaction = self.redo.pop().unwrap();
+        for change in &transaction.changes {
+            let end = change.start + change.removed.as_str().len();
+            self.text.splice(change.start..end, &change.inserted);
+        }
+        self.selection = transaction.after;
+        self.undo.push(transaction);
+        true
+    }
+
+    /// Clear the undo and redo history
+    ///
+    /// Panics if a group is open.
+    pub fn clear_history(&mut self) {
+        assert_eq!(self.depth, 0, "clear_history within a group");
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Vec2;
+    use crate::format::RichText;
+
+    fn buffer(text: &str) -> EditBuffer<String> {
+        let mut text = Text::new(text.to_string());
+        text.set_bounds(Vec2(200.0, 1000.0));
+        text.set_wrap_width(200.0);
+        text.prepare().unwrap();
+        EditBuffer::new(text)
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut buf = buffer("Hello world");
+        buf.set_selection(Selection::new(6, 11));
+        buf.insert("there");
+        assert_eq!(buf.text().as_str(), "Hello there");
+        assert_eq!(buf.selection(), Selection::caret(11));
+        buf.insert("!");
+
+        assert!(buf.undo());
+        assert_eq!(buf.text().as_str(), "Hello there");
+        assert!(buf.undo());
+        assert_eq!(buf.text().as_str(), "Hello world");
+        assert_eq!(buf.selection(), Selection::new(6, 11));
+        assert!(!buf.undo());
+
+        assert!(buf.redo());
+        assert_eq!(buf.text().as_str(), "Hello there");
+        assert_eq!(buf.selection(), Selection::caret(11));
+
+        // A new edit clears the redo history
+        buf.delete(0..6);
+        assert_eq!(buf.text().as_str(), "there");
+        assert_eq!(buf.selection(), Selection::caret(5));
+        assert!(!buf.redo());
+    }
+
+    #[test]
+    fn group() {
+        let mut buf = buffer("abc");
+        buf.begin_group();
+        buf.replace(0..1, "A");
+        buf.begin_group();
+        buf.replace(2..3, "C");
+        buf.end_group();
+        assert!(!buf.can_undo());
+        buf.end_group();
+        assert_eq!(buf.text().as_str(), "AbC");
+
+        // Empty groups are not recorded
+        buf.begin_group();
+        buf.end_group();
+
+        assert!(buf.undo());
+        assert_eq!(buf.text().as_str(), "abc");
+        assert!(!buf.can_undo());
+        assert!(buf.redo());
+        assert_eq!(buf.text().as_str(), "AbC");
+    }
+
+    #[test]
+    fn selection() {
+        let mut sel = Selection::new(8, 2);
+        assert_eq!(sel.range(), 2..8);
+        sel.adjust(4..6, 5);
+        assert_eq!(sel, Selection::new(11, 2));
+        sel.adjust(0..3, 0);
+        assert_eq!(sel, Selection::new(8, 0));
+        sel.adjust(5..10, 1);
+        assert_eq!(sel, Selection::new(6, 0));
+    }
+
+    #[test]
+    fn formatting() {
+        let mut text = RichText::<u8>::new("one two three");
+        text.set_effect(4..7, 1);
+        let original = text.clone();
+        let mut buf = EditBuffer::new(Text::new(text));
+        buf.replace(2..9, "");
+        buf.replace(0..0, "x");
+        assert_eq!(buf.text().as_str(), "xonhree");
+        assert!(buf.undo() && buf.undo());
+        assert_eq!(buf.text().text(), &original);
+    }
+
+    #[test]
+    fn relayout() {
+        let mut buf = buffer("The quick brown fox\njumps over\nthe lazy dog");
+        buf.replace(4..9, "slow");
+        buf.insert(" and more words to wrap the last line");
+        buf.text_mut().prepare().unwrap();
+
+        let mut text = Text::new(buf.text().as_str().to_string());
+        text.set_bounds(Vec2(200.0, 1000.0));
+        text.set_wrap_width(200.0);
+        text.prepare().unwrap();
+
+        let lines = |text: &Text<String>| -> Vec<_> {
+            let forme = text.forme().unwrap();
+            forme.lines().map(|line| line.text_range()).collect()
+        };
+        assert!(lines(&text).len() > 3);
+        assert_eq!(lines(buf.text()), lines(&text));
+        for index in 0..=text.str_len() {
+            assert_eq!(
+                buf.text().text_glyph_pos(index).unwrap().as_slice(),
+                text.text_glyph_pos(index).unwrap().as_slice(),
+            );
+        }
+    }
+}
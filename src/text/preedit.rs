@@ -0,0 +1,350 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License in the LICENSE-APACHE file or at:
+//     https://www.apache.org/licenses/LICENSE-2.0
+
+//! IME preedit overlay
+
+use super::Text;
+use crate::conv::to_u32;
+use crate::format::FormattableText;
+use crate::forme::{CaretHeight, CaretShape, NotReady};
+use crate::{Affinity, Status, Vec2};
+use std::ops::Range;
+
+/// An IME preedit (composition) string
+///
+/// While text is composed using an Input Method Editor, the preedit string is
+/// displayed inline without modifying the committed text.
+/// See [`Text::set_preedit`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Preedit<E> {
+    /// The index in the committed text at which the preedit is displayed
+    ///
+    /// This is usually the position of the caret.
+    pub index: usize,
+    /// The preedit text
+    pub text: String,
+    /// The cursor within the preedit, if any
+    ///
+    /// This is a byte range within [`Self::text`]; the caret is drawn at the
+    /// end of the range. If `None`, no caret should be drawn.
+    pub cursor: Option<Range<usize>>,
+    /// Effect tokens for the preedit text
+    ///
+    /// These are as for [`FormattableText::effect_tokens`], with indices
+    /// relative to the start of [`Self::text`], and are typically used to
+    /// underline clauses. Preedit text before the first token uses the default
+    /// effect.
+    pub effects: Vec<(u32, E)>,
+}
+
+/// A preedit with the resulting display text
+#[derive(Clone, Debug)]
+pub(super) struct Composition<E> {
+    pub(super) preedit: Preedit<E>,
+    /// The committed text with the preedit inserted
+    pub(super) text: String,
+    /// Effect tokens of `text`
+    pub(super) effects: Vec<(u32, E)>,
+}
+
+impl<E: Copy + Default> Composition<E> {
+    fn new(text: &str, effects: &[(u32, E)], preedit: Preedit<E>) -> Self {
+        let (index, len) = (to_u32(preedit.index), to_u32(preedit.text.len()));
+        let mut display = String::with_capacity(text.len() + preedit.text.len());
+        display.push_str(&text[..preedit.index]);
+        display.push_str(&preedit.text);
+        display.push_str(&text[preedit.index..]);
+
+        let split = effects.partition_point(|token| token.0 < index);
+        let mut merged = Vec::with_capacity(effects.len() + preedit.effects.len() + 2);
+        merged.extend_from_slice(&effects[..split]);
+        if preedit.effects.first().is_none_or(|token| token.0 > 0) {
+            merged.push((index, E::default()));
+        }
+        merged.extend(preedit.effects.iter().map(|(i, e)| (index + i, *e)));
+        if effects.get(split).is_none_or(|token| token.0 != index) {
+            // Resume the effect active before the preedit
+            let resume = effects[..split].last().map(|token| token.1);
+            merged.push((index + len, resume.unwrap_or_default()));
+        }
+        merged.extend(effects[split..].iter().map(|(i, e)| (i + len, *e)));
+
+        Composition {
+            preedit,
+            text: display,
+            effects: merged,
+        }
+    }
+
+    /// The range of the preedit in the display text
+    pub(super) fn range(&self) -> Range<usize> {
+        self.preedit.index..self.preedit.index + self.preedit.text.len()
+    }
+}
+
+/// IME preedit support
+///
+/// While a preedit is set, the displayed text is the committed text with the
+/// preedit string inserted. Methods of [`Text`] take and return indices into
+/// the committed text (mapping these as necessary), with the exception of
+/// methods returning the [`Forme`](crate::Forme), whose indices are into
+/// the [display text](Self::display_str).
+impl<T: FormattableText + ?Sized> Text<T> {
+    /// Get the current preedit, if any
+    #[inline]
+    pub fn preedit(&self) -> Option<&Preedit<T::Effect>> {
+        self.composition.as_ref().map(|c| &c.preedit)
+    }
+
+    /// Set the preedit
+    ///
+    /// The preedit is displayed at `preedit.index` without modifying the
+    /// committed text. An empty preedit string is equivalent to
+    /// [`Self::clear_preedit`]. The preedit is cleared by [`Text::set_text`].
+    ///
+    /// One must call [`Text::prepare`] afterwards.
+    ///
+    /// Panics if `preedit.index` is not on a `char` boundary of the text.
+    pub fn set_preedit(&mut self, preedit: Preedit<T::Effect>) {
+        if preedit.text.is_empty() {
+            return self.clear_preedit();
+        }
+        assert!(self.as_str().is_char_boundary(preedit.index));
+
+        let text = self.text.as_str();
+        self.composition = Some(Composition::new(text, self.text.effect_tokens(), preedit));
+        self.set_max_status(Status::Empty);
+    }
+
+    /// Clear the preedit
+    pub fn clear_preedit(&mut self) {
+        if self.composition.take().is_some() {
+            self.set_max_status(Status::Empty);
+        }
+    }
+
+    /// Access the display text
+    ///
+    /// This is the text with any preedit inserted, as used for layout.
+    #[inline]
+    pub fn display_str(&self) -> &str {
+        match &self.composition {
+            Some(c) => &c.text,
+            None => self.as_str(),
+        }
+    }
+
+    /// Get the range of the preedit within the [display text](Self::display_str)
+    #[inline]
+    pub fn preedit_range(&self) -> Option<Range<usize>> {
+        self.composition.as_ref().map(|c| c.range())
+    }
+
+    /// Map an index into the text to an index into the display text
+    ///
+    /// An index at the preedit's position maps to the start of the preedit.
+    #[inline]
+    pub fn to_display_index(&self, index: usize) -> usize {
+        match self.preedit_range() {
+            Some(range) if index > range.start => index + range.len(),
+            _ => index,
+        }
+    }
+
+    /// Map an index into the display text to an index into the text
+    ///
+    /// Any index within the preedit maps to the preedit's position.
+    #[inline]
+    pub fn from_display_index(&self, index: usize) -> usize {
+        match self.preedit_range() {
+            Some(range) if index >= range.end => index - range.len(),
+            Some(range) if index > range.start => range.start,
+            _ => index,
+        }
+    }
+
+    /// Map a range of the text to the display text
+    ///
+    /// The preedit is included only where it is within `range`.
+    pub(super) fn to_display_range(&self, range: Range<usize>) -> Range<usize> {
+        let start = match self.preedit_range() {
+            Some(preedit) if range.start == preedit.start && range.end > range.start => preedit.end,
+            _ => self.to_display_index(range.start),
+        };
+        start..self.to_display_index(range.end).max(start)
+    }
+
+    /// Find the shape of the caret within the preedit
+    ///
+    /// Returns `None` when there is no preedit or the preedit has no cursor.
+    /// See also [`Self::caret_shape`].
+    pub fn preedit_caret(&self, height: CaretHeight) -> Result<Option<CaretShape>, NotReady> {
+        let forme = self.forme()?;
+        let Some(composition) = self.composition.as_ref() else {
+            return Ok(None);
+        };
+        let Some(cursor) = composition.preedit.cursor.as_ref() else {
+            return Ok(None);
+        };
+        let index = composition.preedit.index + cursor.end;
+        Ok(forme.caret_shape(index, Affinity::Upstream, height))
+    }
+
+    /// Get the rectangle of the caret within the preedit
+    ///
+    /// This is the bounding box `(top_left, bottom_right)` of the
+    /// [preedit caret](Self::preedit_caret) with [`CaretHeight::Line`], as may
+    /// be used to position an IME candidate window.
+    pub fn preedit_caret_rect(&self) -> Result<Option<(Vec2, Vec2)>, NotReady> {
+        Ok(self.preedit_caret(CaretHeight::Line)?.map(|shape| {
+            let caret = shape.primary;
+            (caret.top.min(caret.bottom), caret.top.max(caret.bottom))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fonts::FontSelector;
+    use crate::{FontToken, InlineObject};
+
+    fn prepare(text: &str, preedit: Option<Preedit<()>>) -> Text<&str> {
+        let mut text = Text::new(text);
+        if let Some(preedit) = preedit {
+            text.set_preedit(preedit);
+        }
+        text.set_bounds(Vec2(1000.0, 1000.0));
+        text.prepare().unwrap();
+        text
+    }
+
+    #[test]
+    fn index_mapping() {
+        let preedit = Preedit {
+            index: 2,
+            text: "xyz".to_string(),
+            cursor: Some(1..1),
+            effects: vec![],
+        };
+        let text = prepare("abcd", Some(preedit));
+        assert_eq!(text.display_str(), "abxyzcd");
+        assert_eq!(text.preedit_range(), Some(2..5));
+        assert_eq!(text.to_display_index(2), 2);
+        assert_eq!(text.to_display_index(3), 6);
+        assert_eq!(text.from_display_index(4), 2);
+        assert_eq!(text.from_display_index(5), 2);
+        assert_eq!(text.from_display_index(6), 3);
+        assert_eq!(text.to_display_range(2..3), 5..6);
+        assert_eq!(text.to_display_range(1..3), 1..6);
+
+        // Hit-testing within the preedit gives the preedit's position
+        let x = |text: &Text<&str>, index| text.text_glyph_pos(index).unwrap().next().unwrap().pos;
+        let y = 1.0 + text.get_line(0).unwrap().unwrap().top();
+        let forme = text.forme().unwrap();
+        let pos = forme.text_glyph_pos(4).next().unwrap().pos;
+        assert_eq!(text.hit_test(Vec2(pos.0 + 0.1, y)).unwrap().index, 2);
+        assert_eq!(text.find_line(4).unwrap(), Some((0, 0..4)));
+
+        // Committed text after the preedit is displaced
+        let plain = prepare("abcd", None);
+        assert!(x(&text, 3).0 > x(&plain, 3).0);
+        assert_eq!(x(&text, 2), x(&plain, 2));
+
+        // Movement skips over the preedit
+        assert_eq!(text.move_right(2), Ok(3));
+        assert_eq!(text.move_left(3), Ok(2));
+    }
+
+    #[test]
+    fn caret() {
+        let preedit = Preedit {
+            index: 1,
+            text: "xyz".to_string(),
+            cursor: Some(0..2),
+            effects: vec![],
+        };
+        let mut text = prepare("ab", Some(preedit.clone()));
+        let shape = text.preedit_caret(CaretHeight::Line).unwrap().unwrap();
+        let forme = text.forme().unwrap();
+        let expected = forme.caret_pos(3, Affinity::Upstream).unwrap();
+        assert_eq!(shape.primary.top.0, expected.pos.0);
+        let rect = text.preedit_caret_rect().unwrap().unwrap();
+        assert_eq!(rect.0.0, expected.pos.0);
+        assert!(rect.1.1 > rect.0.1);
+
+        text.set_preedit(Preedit {
+            cursor: None,
+            ..preedit
+        });
+        text.prepare().unwrap();
+        assert_eq!(text.preedit_caret_rect(), Ok(None));
+        text.clear_preedit();
+        assert!(!text.is_ready());
+        text.prepare().unwrap();
+        assert_eq!(text.display_str(), "ab");
+    }
+
+    #[test]
+    fn objects() {
+        #[derive(PartialEq)]
+        struct WithObject(&'static str, [(u32, InlineObject); 1]);
+        impl FormattableText for WithObject {
+            type Effect = ();
+            fn as_str(&self) -> &str {
+                self.0
+            }
+            fn font_tokens(
+                &self,
+                dpem: f32,
+                font: FontSelector,
+            ) -> impl Iterator<Item = FontToken> {
+                self.0.font_tokens(dpem, font)
+            }
+            fn effect_tokens(&self) -> &[(u32, ())] {
+                &[]
+            }
+            fn objects(&self) -> &[(u32, InlineObject)] {
+                &self.1
+            }
+        }
+
+        let object = InlineObject::new(20.0, 10.0, 0.0);
+        let mut text = Text::new(WithObject("ab\u{FFFC}c", [(2, object)]));
+        text.set_bounds(Vec2(1000.0, 1000.0));
+        text.prepare().unwrap();
+        let objects: Vec<_> = text.forme().unwrap().objects().collect();
+        assert_eq!(objects.len(), 1);
+        assert_eq!((objects[0].index, objects[0].size.0), (2, 20.0));
+
+        // The object is displaced by a preedit at its index
+        text.set_preedit(Preedit {
+            index: 2,
+            text: "xyz".to_string(),
+            cursor: None,
+            effects: vec![],
+        });
+        text.prepare().unwrap();
+        let objects: Vec<_> = text.forme().unwrap().objects().collect();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].index, 5);
+    }
+
+    #[test]
+    fn effects() {
+        let effects = [(0, 1), (2, 2)];
+        let preedit = Preedit {
+            index: 2,
+            text: "xy".to_string(),
+            cursor: None,
+            effects: vec![(1, 5)],
+        };
+        let merged = Composition::new("abcd", &effects, preedit.clone()).effects;
+        assert_eq!(merged, [(0, 1), (2, 0), (3, 5), (4, 2)]);
+
+        let merged = Composition::new("abcd", &[(0, 1)], preedit).effects;
+        assert_eq!(merged, [(0, 1), (2, 0), (3, 5), (4, 1)]);
+    }
+}
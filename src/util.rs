@@ -7,11 +7,13 @@
 
 #[allow(unused)]
 use crate::Forme;
-use crate::{Direction, fonts::FontSelector};
+use crate::conv::to_u32;
+use crate::{Direction, InlineObject, Length, WhiteSpace, fonts::FontSelector};
 use icu_properties::{CodePointMapData, props::LineBreak};
 use icu_segmenter::{
     LineSegmenter, iterators::LineBreakIterator, options::LineBreakOptions, scaffold::Utf8,
 };
+use std::borrow::Cow;
 use std::ops::Range;
 use unicode_bidi::{BidiInfo, LTR_LEVEL, Level, ParagraphInfo, RTL_LEVEL};
 
@@ -55,6 +57,31 @@ pub struct FontToken {
     pub dpem: f32,
     /// Font selector
     pub font: FontSelector,
+    /// Letter spacing (tracking)
+    ///
+    /// This space is added after each grapheme cluster (in logical order), but
+    /// is not counted after the last cluster on a line. When non-zero,
+    /// optional ligatures are disabled.
+    pub letter_spacing: Length,
+    /// Word spacing
+    ///
+    /// This space is added to the advance of each word-separator character
+    /// (e.g. the space U+0020 and no-break space U+00A0).
+    pub word_spacing: Length,
+}
+
+impl Default for FontToken {
+    /// Construct with `start = 0`, a 16px font size, the default font and no
+    /// additional spacing
+    fn default() -> Self {
+        FontToken {
+            start: 0,
+            dpem: 16.0,
+            font: FontSelector::default(),
+            letter_spacing: Length::ZERO,
+            word_spacing: Length::ZERO,
+        }
+    }
 }
 
 /// Analyzer for text direction
@@ -63,10 +90,15 @@ pub struct FontToken {
 /// paragraphs of text (see [`Self::new`] docs).
 pub(crate) struct AnalyzedText<'a> {
     text: &'a str,
+    direction: Direction,
     default_level: Level,
     levels: Vec<Level>,
     paragraphs: Vec<ParagraphInfo>,
     pub(crate) lb_opts: LineBreakOptions<'a>,
+    pub(crate) hyphenate: bool,
+    white_space: WhiteSpace,
+    /// Inline objects, sorted by text index
+    pub(crate) objects: Vec<(u32, InlineObject)>,
 }
 
 impl<'a> std::ops::Deref for AnalyzedText<'a> {
@@ -85,29 +117,95 @@ impl<'a> AnalyzedText<'a> {
     /// the text into sub-ranges as yielded by [`LineIterator`] and analyze
     /// each sub-range separately.
     pub fn new(text: &'a str, direction: Direction) -> Self {
-        let default_para_level = match direction {
-            Direction::Auto => None,
-            Direction::AutoRtl => {
-                use unicode_bidi::Direction::*;
-                match unicode_bidi::get_base_direction(text) {
-                    Ltr | Rtl => None,
-                    Mixed => Some(RTL_LEVEL),
-                }
-            }
-            Direction::Ltr => Some(LTR_LEVEL),
-            Direction::Rtl => Some(RTL_LEVEL),
-        };
-
-        let info = BidiInfo::new(text, default_para_level);
+        let info = BidiInfo::new(text, para_level(text, direction));
         assert_eq!(text.len(), info.levels.len());
 
         AnalyzedText {
             text,
+            direction,
             default_level: direction.level(),
             levels: info.levels,
             paragraphs: info.paragraphs,
             lb_opts: LineBreakOptions::default(),
+            hyphenate: false,
+            white_space: WhiteSpace::default(),
+            objects: vec![],
+        }
+    }
+
+    /// Re-analyze `range` using the given `direction`
+    ///
+    /// The `range` should be one or more whole paragraphs.
+    pub(crate) fn set_direction(&mut self, range: Range<usize>, direction: Direction) {
+        let text = self.bidi_text(range.clone());
+        let info = BidiInfo::new(&text, para_level(&text, direction));
+        self.levels[range.clone()].copy_from_slice(&info.levels);
+
+        // BiDi paragraphs do not break at all mandatory breaks, thus may
+        // overlap the ends of range.
+        let a = self
+            .paragraphs
+            .partition_point(|para| para.range.start < range.start);
+        let b = self
+            .paragraphs
+            .partition_point(|para| para.range.start < range.end);
+        let mut tail = None;
+        if b > 0 && self.paragraphs[b - 1].range.end > range.end {
+            let mut para = self.paragraphs[b - 1].clone();
+            para.range.start = range.end;
+            tail = Some(para);
+        }
+        if a > 0 {
+            let para = &mut self.paragraphs[a - 1];
+            para.range.end = para.range.end.min(range.start);
         }
+
+        let paras = info.paragraphs.into_iter().map(|mut para| {
+            para.range.start += range.start;
+            para.range.end += range.start;
+            para
+        });
+        self.paragraphs.splice(a..b, paras.chain(tail));
+    }
+
+    /// Get the white-space handling mode
+    #[inline]
+    pub(crate) fn white_space(&self) -> WhiteSpace {
+        self.white_space
+    }
+
+    /// Set the white-space handling mode
+    ///
+    /// Where newlines are collapsed the text is re-analyzed as a single BiDi
+    /// paragraph, since lines do not end at hard breaks.
+    pub(crate) fn set_white_space(&mut self, mode: WhiteSpace) {
+        let reanalyze = mode.collapses_newlines() != self.white_space.collapses_newlines();
+        self.white_space = mode;
+        if reanalyze {
+            let text = self.bidi_text(0..self.text.len());
+            let info = BidiInfo::new(&text, para_level(&text, self.direction));
+            self.levels = info.levels;
+            self.paragraphs = info.paragraphs;
+        }
+    }
+
+    /// Get `range` of the text for BiDi analysis
+    ///
+    /// Where newlines are collapsed, hard breaks are replaced with spaces
+    /// (preserving byte indices).
+    fn bidi_text(&self, range: Range<usize>) -> Cow<'a, str> {
+        let text = &self.text[range];
+        if !self.white_space.collapses_newlines() || !text.contains(is_hard_break) {
+            return Cow::Borrowed(text);
+        }
+        let mut s = String::with_capacity(text.len());
+        for c in text.chars() {
+            match is_hard_break(c) {
+                false => s.push(c),
+                true => s.extend(std::iter::repeat_n(' ', c.len_utf8())),
+            }
+        }
+        Cow::Owned(s)
     }
 
     /// Get the default [`Level`]
@@ -128,6 +226,15 @@ impl<'a> AnalyzedText<'a> {
         self.paragraphs.get(index)
     }
 
+    /// Get the inline object at the given text `index`, if any
+    pub(crate) fn object(&self, index: usize) -> Option<InlineObject> {
+        let index = to_u32(index);
+        self.objects
+            .binary_search_by_key(&index, |(i, _)| *i)
+            .ok()
+            .map(|i| self.objects[i].1)
+    }
+
     /// Find the index of the paragraph containing the given text `index`
     pub(crate) fn find_paragraph(&self, index: usize) -> usize {
         match self
@@ -140,18 +247,65 @@ impl<'a> AnalyzedText<'a> {
     }
 }
 
+/// Get the paragraph embedding level to use for `text`, if fixed
+fn para_level(text: &str, direction: Direction) -> Option<Level> {
+    match direction {
+        Direction::Auto => None,
+        Direction::AutoRtl => {
+            use unicode_bidi::Direction::*;
+            match unicode_bidi::get_base_direction(text) {
+                Ltr | Rtl => None,
+                Mixed => Some(RTL_LEVEL),
+            }
+        }
+        Direction::Ltr => Some(LTR_LEVEL),
+        Direction::Rtl => Some(RTL_LEVEL),
+    }
+}
+
+/// Returns `true` when `c` is a mandatory break
+pub(crate) fn is_hard_break(c: char) -> bool {
+    // This filter is copied from icu_segmenter docs.
+    matches!(
+        CodePointMapData::<LineBreak>::new().get(c),
+        LineBreak::MandatoryBreak
+            | LineBreak::CarriageReturn
+            | LineBreak::LineFeed
+            | LineBreak::NextLine
+    )
+}
+
 /// Returns `true` when `text` ends with a mandatory break
 pub(crate) fn ends_with_hard_break(text: &str) -> bool {
-    // This filter is copied from icu_segmenter docs.
-    text.chars().next_back().is_some_and(|c| {
-        matches!(
-            CodePointMapData::<LineBreak>::new().get(c),
-            LineBreak::MandatoryBreak
-                | LineBreak::CarriageReturn
-                | LineBreak::LineFeed
-                | LineBreak::NextLine
-        )
-    })
+    text.chars().next_back().is_some_and(is_hard_break)
+}
+
+/// Expand `range` to whole paragraphs
+///
+/// The result is a union of items yielded by [`LineIterator`].
+pub(crate) fn paragraph_range(text: &str, range: Range<usize>) -> Range<usize> {
+    // A break before index i, excluding CR followed by LF
+    let break_before = |i: usize| {
+        text[..i].ends_with(is_hard_break)
+            && !(text[..i].ends_with('\r') && text[i..].starts_with('\n'))
+    };
+
+    let mut start = range.start;
+    while let Some(c) = text[..start].chars().next_back()
+        && !break_before(start)
+    {
+        start -= c.len_utf8();
+    }
+
+    let mut end = range.end;
+    while let Some(c) = text[end..].chars().next() {
+        end += c.len_utf8();
+        if break_before(end) {
+            break;
+        }
+    }
+
+    start..end
 }
 
 /// Iterator over lines / paragraphs within the text
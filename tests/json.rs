@@ -2,8 +2,8 @@
 #![cfg(feature = "serde")]
 
 use fontique::GenericFamily;
-use kas_text::Vec2;
 use kas_text::fonts::{FamilyName, FamilySelector, FontSelector, FontStyle, FontWeight, FontWidth};
+use kas_text::{Length, Vec2};
 use serde::{de::Deserialize, ser::Serialize};
 use std::cmp::PartialEq;
 use std::fmt::Debug;
@@ -25,6 +25,12 @@ fn vec2() {
     test(Vec2(1.0, 2.0), "[1.0,2.0]");
 }
 
+#[test]
+fn length() {
+    test(Length::Px(2.0), "{\"Px\":2.0}");
+    test(Length::Em(0.5), "{\"Em\":0.5}");
+}
+
 #[test]
 fn font() {
     test(FamilyName::Named("abc".to_string()), "{\"Named\":\"abc\"}");